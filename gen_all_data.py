@@ -129,8 +129,25 @@ def gen_matrix():
             print(f'Generating data for {clusters} clusters and {dimentionality} dimentionality')
             generate_clustered_data(2_000_000, clusters, f'benchmark_data/synthetic/random_{clusters}k_{dimentionality}d.csv', dimensions=dimentionality, cluster_std=8.0, min_distance=15.0, center_range=100.0, order='random')
 
+def gen_arff(n=100_000, k=5, dimensions=4):
+    """
+    Write a labelled stream as ARFF so the same file can be fed to MOA and to our algorithms.
+    """
+    cluster_centers = generate_cluster_centers(k, 15.0, 100.0, dimensions)
+    labels = np.random.randint(0, k, size=n)
+    data_points = np.random.normal(loc=cluster_centers[labels], scale=8.0)
+    with open(f'benchmark_data/arff/random_{k}k_{dimensions}d.arff', 'w') as arff_file:
+        arff_file.write(f'@relation random_{k}k_{dimensions}d\n\n')
+        for i in range(dimensions):
+            arff_file.write(f'@attribute dim_{i+1} numeric\n')
+        arff_file.write('@attribute class {' + ','.join(str(i) for i in range(k)) + '}\n\n@data\n')
+        for point, label in zip(data_points, labels):
+            arff_file.write(','.join(str(x) for x in point) + f',{label}\n')
+
 if __name__ == '__main__':
     Path("./demos").mkdir(parents=True, exist_ok=True)
     Path("./benchmark_data/synthetic").mkdir(parents=True, exist_ok=True)
+    Path("./benchmark_data/arff").mkdir(parents=True, exist_ok=True)
     gen_all_demos()
     gen_matrix()
+    gen_arff()
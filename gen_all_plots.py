@@ -122,6 +122,29 @@ def plot_benchmark():
     except Exception as e:
         print(f"Processing rate over time for samplers not ploted: {e}")
 
+    try:
+        df = pd.read_csv('benchmark_results/neighbour_search.csv')
+        for dimention in df['dimention'].unique():
+            sns.lineplot(data=df[df['dimention'] == dimention], x='micro_clusters', y='seconds', hue='index', style='index')
+            plt.title(f'Nearest Micro-Cluster Search, {dimention} dimensions')
+            plt.xscale('log')
+            plt.xlabel('Number of micro-clusters')
+            plt.ylabel('Time (s)')
+            plt.savefig(f'plots/neighbour_search_{dimention}.png', dpi=300)
+            plt.clf()
+
+        df = pd.read_csv('benchmark_results/neighbour_search_algorithms.csv')
+        for algo in df['algorithm'].unique():
+            sns.lineplot(data=df[df['algorithm'] == algo], x='micro_clusters', y='seconds', hue='index', style='index')
+            plt.title(f'Processing Time by Neighbour Index for {algo}')
+            plt.xlabel('Number of micro-clusters')
+            plt.ylabel('Processing Time (s)')
+            plt.savefig(f'plots/neighbour_search_{algo}.png', dpi=300)
+            plt.clf()
+        print("Neighbour search ploted")
+    except Exception as e:
+        print(f"Neighbour search not ploted: {e}")
+
 def plot_demos():
     results_dir = Path('./demo_results')
     for result_path in results_dir.glob('*.csv'):
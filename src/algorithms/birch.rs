@@ -1,139 +1,643 @@
-use std::{collections::VecDeque, fmt::Debug};
+use super::{
+    moments::Dispersion, ClusterEvent, ClusterEventListener, ClusteringError, Euclidean, Listeners,
+    Manhattan, Metric, Schema, SparseVector, Spread,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
 
 const L: usize = 20;
+/// Least factor by which a rebuild raises the threshold
+const THRESHOLD_GROWTH: f64 = 1.5;
 const MAX_ITERATIONS: usize = 100_000;
+/// Share of non-zero coordinates above which a sparse linear sum is stored densely
+const DENSIFY_RATIO: f64 = 0.25;
 type Point = Vec<f64>;
 
+/// Mean of a CF. CFs built from sparse points stay sparse until enough coordinates are
+/// non-zero that a dense vector is cheaper; combining with anything dense makes them dense.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Mean {
+    Dense(Point),
+    Sparse(SparseVector),
+}
+
+impl Mean {
+    fn to_dense(&self) -> Point {
+        match self {
+            Mean::Dense(mean) => mean.clone(),
+            Mean::Sparse(mean) => mean.to_dense(),
+        }
+    }
+
+    fn dimension(&self) -> usize {
+        match self {
+            Mean::Dense(mean) => mean.len(),
+            Mean::Sparse(mean) => mean.dimension(),
+        }
+    }
+
+    /// `other - self`, densely
+    fn delta(&self, other: &Self) -> Point {
+        let mut delta = other.to_dense();
+        match self {
+            Mean::Dense(mean) => delta.iter_mut().zip(mean.iter()).for_each(|(d, x)| *d -= x),
+            Mean::Sparse(mean) => mean.add_to(&mut delta, -1.),
+        }
+        delta
+    }
+
+    /// Computed from the differences, so close means far from the origin do not cancel out
+    fn squared_distance(&self, other: &Self) -> f64 {
+        match (self, other) {
+            (Mean::Dense(a), Mean::Dense(b)) => {
+                a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+            }
+            (Mean::Sparse(a), Mean::Dense(b)) | (Mean::Dense(b), Mean::Sparse(a)) => {
+                let mut difference = b.clone();
+                a.add_to(&mut difference, -1.);
+                difference.iter().map(|x| x * x).sum()
+            }
+            (Mean::Sparse(a), Mean::Sparse(b)) => a.add(&b.scaled(-1.)).squared_norm(),
+        }
+    }
+
+    /// `a · self + b · other`
+    fn combine(self, a: f64, other: &Self, b: f64) -> Self {
+        match (self, other) {
+            (Mean::Dense(mut x), Mean::Dense(y)) => {
+                x.iter_mut()
+                    .zip(y.iter())
+                    .for_each(|(x, y)| *x = a * *x + b * y);
+                Mean::Dense(x)
+            }
+            (Mean::Dense(mut x), Mean::Sparse(y)) => {
+                x.iter_mut().for_each(|x| *x *= a);
+                y.add_to(&mut x, b);
+                Mean::Dense(x)
+            }
+            (Mean::Sparse(x), Mean::Dense(y)) => {
+                let mut y: Point = y.iter().map(|y| b * y).collect();
+                x.add_to(&mut y, a);
+                Mean::Dense(y)
+            }
+            (Mean::Sparse(x), Mean::Sparse(y)) => {
+                let sum = x.scaled(a).add(&y.scaled(b));
+                if sum.nnz() as f64 > DENSIFY_RATIO * sum.dimension() as f64 {
+                    Mean::Dense(sum.to_dense())
+                } else {
+                    Mean::Sparse(sum)
+                }
+            }
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        match self {
+            Mean::Dense(mean) => mean.capacity() * std::mem::size_of::<f64>(),
+            Mean::Sparse(mean) => mean.memory_bytes(),
+        }
+    }
+}
+
+/// Clustering feature kept as the mean and the sum of squared deviations from it (M2)
+/// rather than the paper's linear and squared sums (LS, SS), which cancel out
+/// catastrophically for tight clusters far from the origin. Merges use Chan's formula.
 #[derive(Debug, Clone)]
 pub struct ClusteringFeature {
-    n: usize,
-    ls: Point,
-    ss: f64,
+    /// Number of points, a fading weight with decay
+    n: f64,
+    mean: Mean,
+    m2: f64,
+    /// M2 of every coordinate (and co-moments), only with `Spread::Variance` or
+    /// `Spread::Covariance`
+    dispersion: Option<Dispersion>,
+    /// Tree time of the last update, decay is measured from it
+    last_update: usize,
+    /// Tree times of the first and the newest point, kept apart from `last_update`, which
+    /// decay moves on without new points
+    created: usize,
+    last_point: usize,
 }
 
 impl ClusteringFeature {
     fn new(element: Point) -> Self {
         ClusteringFeature {
-            n: 1,
-            ls: element.clone(),
-            ss: element
-                .iter()
-                .map(|&sub_element| sub_element * sub_element)
-                .sum(),
+            n: 1.,
+            mean: Mean::Dense(element),
+            m2: 0.,
+            dispersion: None,
+            last_update: 0,
+            created: 0,
+            last_point: 0,
+        }
+    }
+
+    fn from_sparse(element: &SparseVector) -> Self {
+        let mean = if element.nnz() as f64 > DENSIFY_RATIO * element.dimension() as f64 {
+            Mean::Dense(element.to_dense())
+        } else {
+            Mean::Sparse(element.clone())
+        };
+        ClusteringFeature {
+            n: 1.,
+            mean,
+            m2: 0.,
+            dispersion: None,
+            last_update: 0,
+            created: 0,
+            last_point: 0,
+        }
+    }
+
+    /// Tracks what `spread` reports, for a CF of a single point
+    fn with_spread(mut self, spread: Spread) -> Self {
+        let dimension = self.mean.dimension();
+        self.dispersion = match spread {
+            Spread::Radius => None,
+            Spread::Variance => Some(Dispersion::zero(dimension, false)),
+            Spread::Covariance => Some(Dispersion::zero(dimension, true)),
+        };
+        self
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn summary(&self, spread: Spread) -> (Option<Vec<f64>>, Option<Vec<Vec<f64>>>) {
+        self.dispersion.as_ref().map_or((None, None), |dispersion| {
+            dispersion.summary(self.n, spread)
+        })
+    }
+
+    /// Fades the CF to time `now` by `2^(-lambda·Δt)`, which keeps centroid and radius
+    fn decay_to(&mut self, now: usize, lambda: f64) {
+        if now > self.last_update {
+            self.scale(2_f64.powf(-lambda * (now - self.last_update) as f64));
+            self.last_update = now;
+        }
+    }
+
+    /// Weighs every point by `factor`, which keeps centroid and radius
+    fn scale(&mut self, factor: f64) {
+        self.n *= factor;
+        self.m2 *= factor;
+        if let Some(dispersion) = &mut self.dispersion {
+            dispersion.scale(factor);
         }
     }
 
     fn centroid(&self) -> Point {
-        self.ls.iter().map(|&l| l / self.n as f64).collect()
+        self.mean.to_dense()
     }
 
     fn radius(&self) -> f64 {
-        f64::sqrt((self.ss / self.n as f64) - self.centroid().iter().map(|x| x.powi(2)).sum::<f64>())
+        f64::sqrt((self.m2 / self.n).max(0.))
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.mean.memory_bytes()
+            + self.dispersion.as_ref().map_or(0, Dispersion::memory_bytes)
     }
 
-    /// Euclidean distance_0
-    fn distance_0(&self, other: &Self) -> f64 {
-        let a: Point = self.centroid();
-        let b: Point = other.centroid();
-        a.iter()
-            .zip(b.iter())
-            .map(|(&a, &b)| (a - b).powi(2))
-            .sum::<f64>()
-            .sqrt()
+    /// One of the inter-cluster distances D0–D4 of the BIRCH paper
+    pub fn distance(&self, other: &Self, kind: CFDistance) -> f64 {
+        let (n_a, n_b) = (self.n, other.n);
+        let squared = match kind {
+            CFDistance::D0 => return Euclidean.distance(&self.centroid(), &other.centroid()),
+            CFDistance::D1 => return Manhattan.distance(&self.centroid(), &other.centroid()),
+            CFDistance::D2 => {
+                self.mean.squared_distance(&other.mean) + self.m2 / n_a + other.m2 / n_b
+            }
+            CFDistance::D3 => {
+                let merged = self.clone() + other.clone();
+                if merged.n < 2. {
+                    return 0.;
+                }
+                2. * merged.m2 / (merged.n - 1.)
+            }
+            CFDistance::D4 => n_a * n_b / (n_a + n_b) * self.mean.squared_distance(&other.mean),
+        };
+        // Rounding can push the squared distance slightly below zero
+        squared.max(0.).sqrt()
     }
+}
+
+/// How `Birch` groups the leaf entries into `k` global clusters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GlobalClustering {
+    /// k-means weighted by the number of points of every entry
+    #[default]
+    KMeans,
+    /// Merges the closest pair of clusters until `k` are left, as in the BIRCH paper
+    Agglomerative,
+}
+
+/// Result of `Birch::refine`
+#[derive(Debug, Clone)]
+pub struct Refinement {
+    /// Global cluster of every point, in input order
+    pub labels: Vec<usize>,
+    /// Clusters recomputed from the relabelled points
+    pub clusters: Vec<super::ClusteringElement>,
+}
+
+/// Inter-cluster distances from the BIRCH paper, all computed from the CF vectors alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CFDistance {
+    /// Euclidean distance between the centroids
+    D0,
+    /// Manhattan distance between the centroids
+    D1,
+    /// Average distance between the points of one cluster and the points of the other
+    D2,
+    /// Average pairwise distance inside the merged cluster
+    D3,
+    /// Square root of the increase in sum of squares caused by the merge
+    D4,
+}
+
+/// How the tree compares entries: the metric between centroids, or one of D0–D4
+#[derive(Debug, Clone)]
+struct EntryDistance<D> {
+    metric: D,
+    cf_distance: Option<CFDistance>,
+}
 
-    #[allow(dead_code)]
-    /// Manhattan distance_0
-    fn distance_1(&self, other: &Self) -> f64 {
-        let a: Point = self.centroid();
-        let b: Point = other.centroid();
-        a.iter().zip(b.iter()).map(|(&a, &b)| (a - b).abs()).sum()
+impl<D: Metric> EntryDistance<D> {
+    fn between(&self, a: &ClusteringFeature, b: &ClusteringFeature) -> f64 {
+        match self.cf_distance {
+            Some(kind) => a.distance(b, kind),
+            None => match (&a.mean, &b.mean) {
+                (_, Mean::Sparse(mean)) => self.metric.sparse_distance(mean, &a.centroid()),
+                (Mean::Sparse(mean), _) => self.metric.sparse_distance(mean, &b.centroid()),
+                _ => self.metric.distance(&a.centroid(), &b.centroid()),
+            },
+        }
     }
 }
 
+/// With decay, both sides have to be faded to the same time first
 impl std::ops::Add for ClusteringFeature {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
         let n = self.n + other.n;
-        let ls = self
-            .ls
-            .iter()
-            .zip(other.ls.iter())
-            .map(|(&a, &b)| a + b)
-            .collect();
-        let ss = self.ss + other.ss;
-        ClusteringFeature { n, ls, ss }
+        let spread = self.mean.squared_distance(&other.mean) * self.n * other.n / n;
+        let dispersion = match (self.dispersion, &other.dispersion) {
+            (Some(mut dispersion), Some(other_dispersion)) => {
+                let delta = self.mean.delta(&other.mean);
+                dispersion.merge(Some(other_dispersion), &delta, self.n * other.n / n);
+                Some(dispersion)
+            }
+            _ => None,
+        };
+        ClusteringFeature {
+            mean: self.mean.combine(self.n / n, &other.mean, other.n / n),
+            m2: self.m2 + other.m2 + spread,
+            dispersion,
+            n,
+            last_update: self.last_update.max(other.last_update),
+            created: self.created.min(other.created),
+            last_point: self.last_point.max(other.last_point),
+        }
     }
 }
 
 impl std::ops::AddAssign for ClusteringFeature {
     fn add_assign(&mut self, other: Self) {
-        self.n += other.n;
-        self.ls = self
-            .ls
+        *self = std::mem::replace(self, ClusteringFeature::new(Vec::new())) + other;
+    }
+}
+
+/// Takes points back out of a CF, e.g. to retract them: the inverse of the merge. With
+/// decay, both sides have to be faded to the same time first.
+impl std::ops::Sub for ClusteringFeature {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        let n = self.n - other.n;
+        let last_update = self.last_update.max(other.last_update);
+        if n <= 0. {
+            // Nothing left
+            return ClusteringFeature {
+                n,
+                mean: self.mean,
+                m2: 0.,
+                dispersion: None,
+                last_update,
+                created: self.created,
+                last_point: self.last_point,
+            };
+        }
+        let mean = self.mean.combine(self.n / n, &other.mean, -other.n / n);
+        let spread = mean.squared_distance(&other.mean) * n * other.n / self.n;
+        let dispersion = match (self.dispersion, &other.dispersion) {
+            (Some(mut dispersion), Some(other_dispersion)) => {
+                let delta = mean.delta(&other.mean);
+                dispersion.unmerge(other_dispersion, &delta, n * other.n / self.n);
+                Some(dispersion)
+            }
+            _ => None,
+        };
+        ClusteringFeature {
+            m2: (self.m2 - other.m2 - spread).max(0.),
+            dispersion,
+            mean,
+            n,
+            last_update,
+            created: self.created,
+            last_point: self.last_point,
+        }
+    }
+}
+
+impl std::ops::SubAssign for ClusteringFeature {
+    fn sub_assign(&mut self, other: Self) {
+        *self = std::mem::replace(self, ClusteringFeature::new(Vec::new())) - other;
+    }
+}
+
+/// Leaf entry moved out of the tree because it was much lighter than the others
+#[derive(Debug, Clone, Serialize)]
+pub struct Outlier {
+    /// Micro-cluster id the entry had in the tree
+    pub id: usize,
+    pub center: Point,
+    pub radius: f64,
+    pub weight: f64,
+}
+
+/// Line of the spill file
+#[derive(Serialize, Deserialize)]
+struct StoredOutlier {
+    id: usize,
+    n: f64,
+    mean: Mean,
+    m2: f64,
+    #[serde(default)]
+    dispersion: Option<Dispersion>,
+    last_update: usize,
+    #[serde(default)]
+    created: usize,
+    #[serde(default)]
+    last_point: usize,
+}
+
+fn io_error(err: impl std::fmt::Display) -> ClusteringError {
+    ClusteringError::Io(err.to_string())
+}
+
+/// Potential outliers moved out of the tree by rebuilds. With a spill file, everything
+/// beyond `max_in_memory` entries is appended to it as one JSON line per entry.
+#[derive(Debug, Default)]
+struct OutlierStore {
+    entries: Vec<(ClusteringFeature, usize)>,
+    spill_file: Option<PathBuf>,
+    max_in_memory: usize,
+    /// Entries currently in the spill file
+    spilled: usize,
+}
+
+impl OutlierStore {
+    fn len(&self) -> usize {
+        self.entries.len() + self.spilled
+    }
+
+    /// Writes the in-memory entries to the spill file if there are too many. Entries stay
+    /// in memory when writing fails.
+    fn spill(&mut self) -> Result<(), ClusteringError> {
+        let Some(path) = &self.spill_file else {
+            return Ok(());
+        };
+        if self.entries.len() <= self.max_in_memory {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for (cf, id) in self.entries.iter() {
+            let stored = StoredOutlier {
+                id: *id,
+                n: cf.n,
+                mean: cf.mean.clone(),
+                m2: cf.m2,
+                dispersion: cf.dispersion.clone(),
+                last_update: cf.last_update,
+                created: cf.created,
+                last_point: cf.last_point,
+            };
+            lines.push_str(&serde_json::to_string(&stored).map_err(io_error)?);
+            lines.push('\n');
+        }
+        // A file left over from another run is overwritten by the first spill
+        let mut file = if self.spilled == 0 {
+            File::create(path)
+        } else {
+            OpenOptions::new().append(true).open(path)
+        }
+        .map_err(io_error)?;
+        file.write_all(lines.as_bytes()).map_err(io_error)?;
+        self.spilled += self.entries.len();
+        self.entries.clear();
+        Ok(())
+    }
+
+    fn read_spilled(&self) -> Result<Vec<(ClusteringFeature, usize)>, ClusteringError> {
+        let Some(path) = self.spill_file.as_ref().filter(|_| self.spilled > 0) else {
+            return Ok(Vec::new());
+        };
+        BufReader::new(File::open(path).map_err(io_error)?)
+            .lines()
+            .map(|line| {
+                let stored: StoredOutlier =
+                    serde_json::from_str(&line.map_err(io_error)?).map_err(io_error)?;
+                let cf = ClusteringFeature {
+                    n: stored.n,
+                    mean: stored.mean,
+                    m2: stored.m2,
+                    dispersion: stored.dispersion,
+                    last_update: stored.last_update,
+                    created: stored.created,
+                    last_point: stored.last_point,
+                };
+                Ok((cf, stored.id))
+            })
+            .collect()
+    }
+
+    fn all(&self) -> Result<Vec<(ClusteringFeature, usize)>, ClusteringError> {
+        let mut entries = self.read_spilled()?;
+        entries.extend(self.entries.iter().cloned());
+        Ok(entries)
+    }
+
+    /// Every stored entry, leaving the store and its file empty
+    fn take_all(&mut self) -> Result<Vec<(ClusteringFeature, usize)>, ClusteringError> {
+        let mut entries = self.read_spilled()?;
+        if let Some(path) = self.spill_file.as_ref().filter(|_| self.spilled > 0) {
+            File::create(path).map_err(io_error)?;
+            self.spilled = 0;
+        }
+        entries.append(&mut self.entries);
+        Ok(entries)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.entries
             .iter()
-            .zip(other.ls.iter())
-            .map(|(&a, &b)| a + b)
-            .collect();
-        self.ss += other.ss;
+            .map(|(cf, _)| cf.memory_bytes() + std::mem::size_of::<usize>())
+            .sum()
     }
 }
 
-fn kmeans(features: &[ClusteringFeature], k: usize, max_iterations: usize) -> Vec<usize> {
-    let mut centroids: Vec<Point> = features.iter().take(k).map(|cf| cf.centroid()).collect();
-    let mut assignments = vec![0; features.len()];
+/// Sum of a split group and its seed, which is not part of `rest` yet
+fn sum_with(seed: &ClusteringFeature, rest: &[(ClusteringFeature, usize)]) -> ClusteringFeature {
+    rest.iter()
+        .fold(seed.clone(), |acc, (cf, _)| acc + cf.clone())
+}
+
+/// Renumbers labels to `0..m` in order of first appearance
+fn compact(labels: Vec<usize>) -> Vec<usize> {
+    let mut ids = std::collections::HashMap::new();
+    labels
+        .into_iter()
+        .map(|label| {
+            let next = ids.len();
+            *ids.entry(label).or_insert(next)
+        })
+        .collect()
+}
+
+/// k-means over CF entries weighted by their number of points: a centroid is the mean of
+/// all the points of its entries. Seeds are picked greedily, the heaviest entry first, then
+/// the entry with the largest `n · d²` to the seeds so far, so the result is deterministic.
+fn kmeans<D: Metric>(
+    features: &[ClusteringFeature],
+    k: usize,
+    max_iterations: usize,
+    metric: &D,
+) -> Vec<usize> {
+    let k = k.min(features.len());
+    let centers: Vec<Point> = features.iter().map(|cf| cf.centroid()).collect();
+    let heaviest = (0..features.len())
+        .max_by(|&i, &j| features[i].n.total_cmp(&features[j].n))
+        .unwrap();
+    let mut centroids = vec![centers[heaviest].clone()];
+    while centroids.len() < k {
+        let farthest = (0..features.len())
+            .map(|i| {
+                let d = centroids
+                    .iter()
+                    .map(|centroid| metric.distance(&centers[i], centroid))
+                    .fold(f64::INFINITY, f64::min);
+                (i, features[i].n * d * d)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+            .0;
+        centroids.push(centers[farthest].clone());
+    }
 
+    let mut assignments = vec![usize::MAX; features.len()];
     for _ in 0..max_iterations {
         // Assign clusters
-        for (i, feature) in features.iter().enumerate() {
-            let distances: Vec<f64> = centroids
-                .iter()
-                .map(|centroid| {
-                    feature
-                        .centroid()
-                        .iter()
-                        .zip(centroid.iter())
-                        .map(|(&a, &b)| (a - b).powi(2))
-                        .sum::<f64>()
-                        .sqrt()
-                })
-                .collect();
-            let (min_index, _) = distances
-                .iter()
-                .enumerate()
-                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                .unwrap();
-            assignments[i] = min_index;
+        let new_assignments: Vec<usize> = centers
+            .iter()
+            .map(|center| {
+                centroids
+                    .iter()
+                    .enumerate()
+                    .map(|(j, centroid)| (j, metric.distance(center, centroid)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        if new_assignments == assignments {
+            break;
         }
+        assignments = new_assignments;
 
-        // Update centroids
-        let mut new_centroids = vec![vec![0.0; centroids[0].len()]; k];
-        let mut counts = vec![0; k];
-
+        // Update centroids, an empty cluster keeps its centroid
+        let mut sums: Vec<Option<ClusteringFeature>> = vec![None; k];
         for (assignment, feature) in assignments.iter().zip(features.iter()) {
-            for (i, value) in feature.centroid().iter().enumerate() {
-                new_centroids[*assignment][i] += value;
+            sums[*assignment] = Some(match sums[*assignment].take() {
+                Some(sum) => sum + feature.clone(),
+                None => feature.clone(),
+            });
+        }
+        for (centroid, sum) in centroids.iter_mut().zip(sums.iter()) {
+            if let Some(sum) = sum {
+                *centroid = sum.centroid();
             }
-            counts[*assignment] += 1;
         }
+    }
+
+    compact(assignments)
+}
+
+/// Agglomerative clustering of CF entries, as in phase 3 of the BIRCH paper: starting from
+/// one cluster per entry, the two closest clusters (by the tree's entry distance) are
+/// merged until `k` are left. Every cluster remembers its nearest neighbour, which only
+/// has to be searched again when it took part in a merge.
+fn agglomerative<D: Metric>(
+    features: &[ClusteringFeature],
+    k: usize,
+    distance: &EntryDistance<D>,
+) -> Vec<usize> {
+    let mut clusters: Vec<Option<ClusteringFeature>> = features.iter().cloned().map(Some).collect();
+    let mut labels: Vec<usize> = (0..features.len()).collect();
+    let nearest_to = |clusters: &[Option<ClusteringFeature>], i: usize| {
+        let cf = clusters[i].as_ref().unwrap();
+        clusters
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .filter_map(|(j, other)| other.as_ref().map(|other| (j, distance.between(cf, other))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    };
+    let mut nearest: Vec<Option<(usize, f64)>> = (0..clusters.len())
+        .map(|i| nearest_to(&clusters, i))
+        .collect();
+    let mut remaining = clusters.len();
+    while remaining > k.max(1) {
+        let (a, b) = nearest
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| clusters[*i].is_some())
+            .filter_map(|(i, nearest)| nearest.map(|(j, d)| (i, j, d)))
+            .min_by(|x, y| x.2.total_cmp(&y.2))
+            .map(|(i, j, _)| (i.min(j), i.max(j)))
+            .unwrap();
+        let merged = clusters[a].take().unwrap() + clusters[b].take().unwrap();
+        clusters[a] = Some(merged);
+        nearest[b] = None;
+        labels
+            .iter_mut()
+            .filter(|label| **label == b)
+            .for_each(|label| *label = a);
+        remaining -= 1;
 
-        for (centroid, count) in new_centroids.iter_mut().zip(counts.iter()) {
-            if *count > 0 {
-                for value in centroid.iter_mut() {
-                    *value /= *count as f64;
+        for i in 0..clusters.len() {
+            let Some(cf) = clusters[i].as_ref() else {
+                continue;
+            };
+            match nearest[i] {
+                Some((j, _)) if i == a || j == a || j == b => nearest[i] = nearest_to(&clusters, i),
+                Some((_, d)) => {
+                    let to_merged = distance.between(cf, clusters[a].as_ref().unwrap());
+                    if to_merged < d {
+                        nearest[i] = Some((a, to_merged));
+                    }
                 }
+                None => nearest[i] = nearest_to(&clusters, i),
             }
         }
-
-        if centroids == new_centroids {
-            break;
-        }
-
-        centroids = new_centroids;
     }
-
-    assignments
+    compact(labels)
 }
 
 #[derive(Clone, Debug)]
@@ -141,7 +645,8 @@ enum CFNode {
     Leaf {
         id: usize,
         parent_id: Option<usize>,
-        features: Vec<ClusteringFeature>,
+        /// Entries with their micro-cluster ids
+        features: Vec<(ClusteringFeature, usize)>,
         prev: Option<usize>,
         next: Option<usize>,
     },
@@ -167,34 +672,72 @@ impl CFNode {
         }
     }
 
-    fn sum(&self) -> ClusteringFeature {
+    fn set_id(&mut self, new_id: usize) {
         match self {
-            CFNode::Leaf { features, .. } => {
-                if features.is_empty() {
-                    println!("Empty leaf node");
-                }
-                features.iter().cloned().reduce(|acc, cf| acc + cf).unwrap()
-            }
-            CFNode::NonLeaf { features, .. } => features
-                .iter()
-                .cloned()
-                .map(|(cf, _)| cf)
-                .reduce(|acc, cf| acc + cf)
-                .unwrap(),
+            CFNode::Leaf { id, .. } => *id = new_id,
+            CFNode::NonLeaf { id, .. } => *id = new_id,
+        }
+    }
+
+    fn features(&self) -> &Vec<(ClusteringFeature, usize)> {
+        match self {
+            CFNode::Leaf { features, .. } => features,
+            CFNode::NonLeaf { features, .. } => features,
+        }
+    }
+
+    fn features_mut(&mut self) -> &mut Vec<(ClusteringFeature, usize)> {
+        match self {
+            CFNode::Leaf { features, .. } => features,
+            CFNode::NonLeaf { features, .. } => features,
         }
     }
+
+    /// `None` for a node without entries
+    fn sum(&self) -> Option<ClusteringFeature> {
+        self.features()
+            .iter()
+            .cloned()
+            .map(|(cf, _)| cf)
+            .reduce(|acc, cf| acc + cf)
+    }
+}
+
+/// Exponential decay of a damped CF-tree
+#[derive(Debug, Clone, Copy)]
+struct Decay {
+    lambda: f64,
+    /// Entries lighter than this are removed
+    floor: f64,
+    /// Inserts between sweeps for faded entries
+    sweep_every: usize,
 }
 
 #[derive(Debug)]
-struct CFTree {
+struct CFTree<D> {
     arena: Vec<CFNode>,
     root_id: usize,
     next_id: usize,
     threshold: f64,
     branching_factor: usize,
+    splits: usize,
+    /// Rebuild with a larger threshold whenever the tree has more nodes than this
+    max_nodes: Option<usize>,
+    rebuilds: usize,
+    /// Rebuilds move out leaf entries lighter than this share of the mean entry weight
+    outlier_density: Option<f64>,
+    outliers: OutlierStore,
+    decay: Option<Decay>,
+    /// Entries removed after fading below the floor
+    faded: usize,
+    next_entry_id: usize,
+    /// Points inserted so far, used as event timestamp
+    timestamp: usize,
+    listeners: Listeners,
+    distance: EntryDistance<D>,
 }
 
-impl CFTree {
+impl CFTree<Euclidean> {
     fn new(threshold: f64, branching_factor: usize) -> Self {
         CFTree {
             arena: Vec::new(),
@@ -202,6 +745,46 @@ impl CFTree {
             next_id: 0,
             threshold,
             branching_factor,
+            splits: 0,
+            max_nodes: None,
+            rebuilds: 0,
+            outlier_density: None,
+            outliers: OutlierStore::default(),
+            decay: None,
+            faded: 0,
+            next_entry_id: 0,
+            timestamp: 0,
+            listeners: Listeners::default(),
+            distance: EntryDistance {
+                metric: Euclidean,
+                cf_distance: None,
+            },
+        }
+    }
+}
+
+impl<D: Metric> CFTree<D> {
+    fn with_metric<E: Metric>(self, metric: E) -> CFTree<E> {
+        CFTree {
+            arena: self.arena,
+            root_id: self.root_id,
+            next_id: self.next_id,
+            threshold: self.threshold,
+            branching_factor: self.branching_factor,
+            splits: self.splits,
+            max_nodes: self.max_nodes,
+            rebuilds: self.rebuilds,
+            outlier_density: self.outlier_density,
+            outliers: self.outliers,
+            decay: self.decay,
+            faded: self.faded,
+            next_entry_id: self.next_entry_id,
+            timestamp: self.timestamp,
+            listeners: self.listeners,
+            distance: EntryDistance {
+                metric,
+                cf_distance: self.distance.cf_distance,
+            },
         }
     }
 
@@ -212,15 +795,17 @@ impl CFTree {
         let mut queue = VecDeque::new();
         queue.push_back((self.root_id, 0));
         while let Some((id, indent)) = queue.pop_front() {
-            let sum = self.arena[id].sum();
+            let Some(sum) = self.arena[id].sum() else {
+                continue;
+            };
             match &self.arena[id] {
                 CFNode::Leaf { .. } => {
                     println!(
                         "{:indent$}Leaf({}, {:?}, {})",
                         "",
                         sum.n,
-                        sum.ls,
-                        sum.ss,
+                        sum.mean,
+                        sum.m2,
                         indent = indent
                     );
                 }
@@ -229,8 +814,8 @@ impl CFTree {
                         "{:indent$}NonLeaf({}, {:?}, {})",
                         "",
                         sum.n,
-                        sum.ls,
-                        sum.ss,
+                        sum.mean,
+                        sum.m2,
                         indent = indent
                     );
                     for (_, child_id) in features {
@@ -241,34 +826,193 @@ impl CFTree {
         }
     }
 
-    fn insert(&mut self, instance: Point) {
-        let entry = ClusteringFeature::new(instance);
+    /// All leaves sit at the same level, so following the first child is enough
+    fn depth(&self) -> usize {
+        if self.arena.is_empty() {
+            return 0;
+        }
+        let mut depth = 1;
+        let mut current_id = self.root_id;
+        while let CFNode::NonLeaf { features, .. } = &self.arena[current_id] {
+            depth += 1;
+            current_id = features[0].1;
+        }
+        depth
+    }
+
+    fn new_entry_id(&mut self) -> usize {
+        let id = self.next_entry_id;
+        self.next_entry_id += 1;
+        self.listeners.emit(ClusterEvent::Created {
+            id,
+            timestamp: self.timestamp,
+        });
+        id
+    }
+
+    fn insert(&mut self, entry: ClusteringFeature) -> Result<(), ClusteringError> {
+        self.timestamp += 1;
+        let mut entry = entry;
+        entry.last_update = self.timestamp;
+        entry.created = self.timestamp;
+        entry.last_point = self.timestamp;
+        self.insert_entry(entry, None);
+        if let Some(decay) = self.decay {
+            if self.timestamp.is_multiple_of(decay.sweep_every) {
+                self.sweep(decay.floor);
+            }
+        }
+        if let Some(max_nodes) = self.max_nodes {
+            while self.arena.len() > max_nodes {
+                self.rebuild()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaf and index of the entry `insert_entry` would pick for `entry`, found by
+    /// descending from the root to the closest child on every level
+    fn closest_leaf_entry(&self, entry: &ClusteringFeature) -> Option<(usize, usize)> {
+        self.arena.get(self.root_id)?;
+        let closest = |features: &[(ClusteringFeature, usize)]| {
+            features
+                .iter()
+                .enumerate()
+                .min_by(|(_, cf_0), (_, cf_1)| {
+                    self.distance
+                        .between(&cf_0.0, entry)
+                        .total_cmp(&self.distance.between(&cf_1.0, entry))
+                })
+                .map(|(index, (_, child_id))| (index, *child_id))
+        };
+        let mut current_search_id = self.root_id;
+        loop {
+            let (index, child_id) = closest(self.arena[current_search_id].features())?;
+            match &self.arena[current_search_id] {
+                CFNode::Leaf { .. } => return Some((current_search_id, index)),
+                CFNode::NonLeaf { .. } => current_search_id = child_id,
+            }
+        }
+    }
+
+    /// Whether the leaf entry `insert_entry` would pick for `entry` can absorb it
+    fn fits(&self, entry: &ClusteringFeature) -> bool {
+        self.closest_leaf_entry(entry)
+            .is_some_and(|(leaf_id, index)| {
+                self.absorbs(&self.arena[leaf_id].features()[index].0, entry)
+            })
+    }
+
+    /// The test `insert_entry` absorbs an entry with
+    fn absorbs(&self, cf: &ClusteringFeature, entry: &ClusteringFeature) -> bool {
+        (self.decayed(cf.clone()) + self.decayed(entry.clone())).radius() < self.threshold
+    }
+
+    /// Leaf and index of the entry to take `point` out of: the closest one that holds it,
+    /// searched from the root closest child first, as `insert_entry` descends. Splits can
+    /// have moved the entry away from that path, so other children are tried next, except
+    /// for those whose sums cannot hold the point either.
+    fn holding_leaf_entry(&self, point: &ClusteringFeature) -> Option<(usize, usize)> {
+        self.arena.get(self.root_id)?;
+        let mut stack = vec![self.root_id];
+        while let Some(node_id) = stack.pop() {
+            let mut candidates: Vec<(usize, usize, f64)> = self.arena[node_id]
+                .features()
+                .iter()
+                .enumerate()
+                .filter(|(_, (cf, _))| self.holds(cf, point))
+                .map(|(index, (cf, child_id))| (index, *child_id, self.distance.between(cf, point)))
+                .collect();
+            // Farthest first, so the closest child is searched next
+            candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+            match self.arena[node_id] {
+                CFNode::Leaf { .. } => {
+                    if let Some(&(index, _, _)) = candidates.last() {
+                        return Some((node_id, index));
+                    }
+                }
+                CFNode::NonLeaf { .. } => {
+                    stack.extend(candidates.into_iter().map(|(_, child_id, _)| child_id))
+                }
+            }
+        }
+        None
+    }
+
+    /// `point` weighed as one of the points of `cf`. As the time it came in is unknown, it
+    /// weighs what a point from when the entry was created has faded to, the least it can.
+    fn as_part_of(&self, cf: &ClusteringFeature, point: &ClusteringFeature) -> ClusteringFeature {
+        let mut point = point.clone();
+        if let Some(decay) = self.decay {
+            let age = self.timestamp.saturating_sub(cf.created) as f64;
+            point.scale(2_f64.powf(-decay.lambda * age));
+        }
+        point
+    }
+
+    /// Whether `point` can be one of the points summed up in `cf`, weighed by `as_part_of`.
+    /// An entry of no more than the point has to absorb it as on insert; a larger one must
+    /// be left with a spread of at least zero once the point is taken out.
+    fn holds(&self, cf: &ClusteringFeature, point: &ClusteringFeature) -> bool {
+        let cf = self.decayed(cf.clone());
+        let point = self.as_part_of(&cf, point);
+        if cf.n - point.n <= 0. {
+            return self.absorbs(&cf, &point);
+        }
+        let spread = cf.mean.squared_distance(&point.mean) * cf.n * point.n / (cf.n - point.n);
+        // Rounding leaves the spread of repeated points slightly off
+        spread <= cf.m2 * (1. + 1e-9) + 1e-12
+    }
 
+    /// Adds `entry` to the closest leaf entry or as a new one. `id` is set when a rebuild
+    /// moves an existing entry, which keeps its id or is reported as merged.
+    fn insert_entry(&mut self, entry: ClusteringFeature, id: Option<usize>) {
+        let entry = self.decayed(entry);
         if self.arena.get(self.root_id).is_some() {
             // Insert
             let mut current_search_id = self.root_id;
             loop {
+                // Everything on the path is faded to now, so sums and splits add up
+                self.decay_node(current_search_id);
                 match self.arena.get_mut(current_search_id).unwrap() {
                     CFNode::Leaf { features, .. } => {
-                        let closest_feature = features
-                            .iter_mut()
-                            .min_by(|cf_0, cf_1| {
-                                cf_0.distance_0(&entry)
-                                    .partial_cmp(&cf_1.distance_0(&entry))
-                                    .unwrap()
-                            })
-                            .unwrap();
-                        if (closest_feature.clone() + entry.clone()).radius() < self.threshold {
+                        let closest = features.iter_mut().min_by(|cf_0, cf_1| {
+                            self.distance
+                                .between(&cf_0.0, &entry)
+                                .total_cmp(&self.distance.between(&cf_1.0, &entry))
+                        });
+                        if let Some((closest_feature, closest_id)) = closest.filter(|(cf, _)| {
+                            (cf.clone() + entry.clone()).radius() < self.threshold
+                        }) {
                             // Absorb
                             *closest_feature += entry;
+                            let into = *closest_id;
+                            self.listeners.emit(match id {
+                                Some(absorbed) => ClusterEvent::Merged {
+                                    id: into,
+                                    absorbed,
+                                    timestamp: self.timestamp,
+                                },
+                                None => ClusterEvent::Absorbed {
+                                    id: into,
+                                    timestamp: self.timestamp,
+                                },
+                            });
                             self.refresh_tree_from(current_search_id);
                         } else if features.len() <= L {
                             // Insert
-                            features.push(entry);
+                            let id = id.unwrap_or_else(|| self.new_entry_id());
+                            if let CFNode::Leaf { features, .. } =
+                                &mut self.arena[current_search_id]
+                            {
+                                features.push((entry, id));
+                            }
                             self.refresh_tree_from(current_search_id);
                         } else {
-                            // Split
+                            // Split, then look for a place for the entry in the new tree
                             self.split(current_search_id);
+                            current_search_id = self.root_id;
+                            continue;
                         }
                         break;
                     }
@@ -276,10 +1020,9 @@ impl CFTree {
                         let min_child = features
                             .iter()
                             .min_by(|cf_0, cf_1| {
-                                cf_0.0
-                                    .distance_0(&entry)
-                                    .partial_cmp(&cf_1.0.distance_0(&entry))
-                                    .unwrap()
+                                self.distance
+                                    .between(&cf_0.0, &entry)
+                                    .total_cmp(&self.distance.between(&cf_1.0, &entry))
                             })
                             .unwrap();
                         current_search_id = min_child.1;
@@ -288,7 +1031,7 @@ impl CFTree {
             }
         } else {
             let mut features = Vec::with_capacity(self.branching_factor);
-            features.push(entry);
+            features.push((entry, id.unwrap_or_else(|| self.new_entry_id())));
             let leaf = CFNode::Leaf {
                 id: self.next_id,
                 features,
@@ -302,6 +1045,8 @@ impl CFTree {
     }
 
     fn split(&mut self, node_id: usize) {
+        self.splits += 1;
+        tracing::debug!(node = node_id, "splitting node");
         match self.arena.get(node_id).cloned().unwrap() {
             CFNode::Leaf {
                 id,
@@ -321,21 +1066,36 @@ impl CFTree {
                             .map(move |(j, p2)| (i, j, p1, p2))
                     })
                     .max_by(|(_, _, p1, p2), (_, _, q1, q2)| {
-                        p1.distance_0(p2).partial_cmp(&q1.distance_0(q2)).unwrap()
+                        self.distance
+                            .between(&p1.0, &p2.0)
+                            .total_cmp(&self.distance.between(&q1.0, &q2.0))
                     })
                     .unwrap();
-                let (mut group_a, mut group_b): (Vec<ClusteringFeature>, Vec<ClusteringFeature>) =
-                    features
-                        .iter()
-                        .cloned()
-                        .enumerate()
-                        .filter(|(i, _)| i != &seed_index_a && i != &seed_index_b)
-                        .map(|(_, p)| p)
-                        .partition(|p| seed_a.distance_0(p) < seed_b.distance_0(p));
+                #[allow(clippy::type_complexity)]
+                let (mut group_a, mut group_b): (
+                    Vec<(ClusteringFeature, usize)>,
+                    Vec<(ClusteringFeature, usize)>,
+                ) = features
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .filter(|(i, _)| i != &seed_index_a && i != &seed_index_b)
+                    .map(|(_, p)| p)
+                    .partition(|p| {
+                        self.distance.between(&seed_a.0, &p.0)
+                            < self.distance.between(&seed_b.0, &p.0)
+                    });
+                let sum_a = sum_with(&seed_a.0, &group_a);
+                let sum_b = sum_with(&seed_b.0, &group_b);
                 group_a.push(seed_a.clone());
                 group_b.push(seed_b.clone());
                 let b_id = self.next_id;
                 self.next_id += 1;
+                self.listeners.emit(ClusterEvent::Split {
+                    node: id,
+                    sibling: b_id,
+                    timestamp: self.timestamp,
+                });
                 let mut leaf_split_a = CFNode::Leaf {
                     id,
                     features: group_a,
@@ -350,10 +1110,13 @@ impl CFTree {
                     next,
                     parent_id,
                 };
+                if let Some(CFNode::Leaf { prev, .. }) =
+                    next.and_then(|next| self.arena.get_mut(next))
+                {
+                    *prev = Some(b_id);
+                }
                 if let Some(parent_id) = parent_id {
                     // Update parent
-                    let sum_a = leaf_split_a.sum();
-                    let sum_b = leaf_split_b.sum();
                     self.arena[id] = leaf_split_a;
                     self.arena.push(leaf_split_b);
                     if let CFNode::NonLeaf { features, .. } = &mut self.arena[parent_id] {
@@ -367,8 +1130,6 @@ impl CFTree {
                     // Create new root
                     leaf_split_a.set_parent_id(Some(self.next_id));
                     leaf_split_b.set_parent_id(Some(self.next_id));
-                    let sum_a = leaf_split_a.sum();
-                    let sum_b = leaf_split_b.sum();
                     self.arena[id] = leaf_split_a;
                     self.arena.push(leaf_split_b);
 
@@ -398,9 +1159,9 @@ impl CFTree {
                             .map(move |(j, p2)| (i, j, p1, p2))
                     })
                     .max_by(|(_, _, p1, p2), (_, _, q1, q2)| {
-                        p1.0.distance_0(&p2.0)
-                            .partial_cmp(&q1.0.distance_0(&q2.0))
-                            .unwrap()
+                        self.distance
+                            .between(&p1.0, &p2.0)
+                            .total_cmp(&self.distance.between(&q1.0, &q2.0))
                     })
                     .unwrap();
                 #[allow(clippy::type_complexity)]
@@ -413,11 +1174,21 @@ impl CFTree {
                     .enumerate()
                     .filter(|(i, _)| i != &seed_index_a && i != &seed_index_b)
                     .map(|(_, p)| p)
-                    .partition(|p| seed_a.0.distance_0(&p.0) < seed_b.0.distance_0(&p.0));
+                    .partition(|p| {
+                        self.distance.between(&seed_a.0, &p.0)
+                            < self.distance.between(&seed_b.0, &p.0)
+                    });
+                let sum_a = sum_with(&seed_a.0, &group_a);
+                let sum_b = sum_with(&seed_b.0, &group_b);
                 group_a.push(seed_a.clone());
                 group_b.push(seed_b.clone());
                 let b_id = self.next_id;
                 self.next_id += 1;
+                self.listeners.emit(ClusterEvent::Split {
+                    node: id,
+                    sibling: b_id,
+                    timestamp: self.timestamp,
+                });
                 let mut split_a = CFNode::NonLeaf {
                     id,
                     features: group_a,
@@ -433,8 +1204,6 @@ impl CFTree {
                 };
                 if let Some(parent_id) = parent_id {
                     // Update parent
-                    let sum_a = split_a.sum();
-                    let sum_b = split_b.sum();
                     self.arena[id] = split_a;
                     self.arena.push(split_b);
                     if let CFNode::NonLeaf { features, .. } = &mut self.arena[parent_id] {
@@ -448,8 +1217,6 @@ impl CFTree {
                     // Create new root
                     split_a.set_parent_id(Some(self.next_id));
                     split_b.set_parent_id(Some(self.next_id));
-                    let sum_a = split_a.sum();
-                    let sum_b = split_b.sum();
                     self.arena[id] = split_a;
                     self.arena.push(split_b);
 
@@ -466,51 +1233,666 @@ impl CFTree {
         }
     }
 
-    fn refresh_tree_from(&mut self, node_id: usize) {
-        let mut current_node_id = node_id;
-        while let Some(parent_id) = self.arena[current_node_id].parent_id() {
-            let updated_sum = self.arena[current_node_id].sum();
-            if let CFNode::NonLeaf { features, .. } = &mut self.arena[parent_id] {
-                for (feature, child_id) in features.iter_mut() {
-                    if *child_id == current_node_id {
-                        *feature = updated_sum;
-                        break;
-                    }
-                }
-            }
-            current_node_id = parent_id;
+    /// Leaf entries in leaf-chain order
+    fn leaf_entries(&self) -> Vec<(ClusteringFeature, usize)> {
+        let mut entries = Vec::new();
+        let mut current = self.arena.iter().find_map(|node| match node {
+            CFNode::Leaf { id, prev: None, .. } => Some(*id),
+            _ => None,
+        });
+        while let Some(CFNode::Leaf { features, next, .. }) = current.map(|id| &self.arena[id]) {
+            entries.extend(
+                features
+                    .iter()
+                    .map(|(cf, id)| (self.decayed(cf.clone()), *id)),
+            );
+            current = *next;
         }
+        entries
     }
-}
 
-#[derive(Debug)]
-pub struct Birch {
-    tree: CFTree,
-    cluster_count: usize,
-}
+    /// Mean over the leaves of the radius their closest pair of entries would have after a
+    /// merge, so that a typical leaf loses an entry, but at least `THRESHOLD_GROWTH` times
+    /// the current threshold
+    fn next_threshold(&self) -> f64 {
+        let closest: Vec<f64> = self
+            .arena
+            .iter()
+            .filter_map(|node| match node {
+                CFNode::Leaf { features, .. } => features
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, a)| features.iter().skip(i + 1).map(move |b| (a, b)))
+                    .map(|(a, b)| (self.decayed(a.0.clone()) + self.decayed(b.0.clone())).radius())
+                    .min_by(f64::total_cmp),
+                CFNode::NonLeaf { .. } => None,
+            })
+            .collect();
+        let mean = closest.iter().sum::<f64>() / closest.len().max(1) as f64;
+        let threshold = mean.max(self.threshold * THRESHOLD_GROWTH);
+        if threshold > self.threshold {
+            threshold
+        } else {
+            // Zero threshold and only duplicate entries: anything positive absorbs them
+            f64::MIN_POSITIVE
+        }
+    }
+
+    /// Rebuilding phase of BIRCH: raises the threshold and reinserts the leaf entries into
+    /// a fresh tree, merging the ones that now fit together. Node ids start over. With
+    /// outlier handling, light entries go to the outlier store instead, and stored ones
+    /// that fit under the new threshold come back.
+    fn rebuild(&mut self) -> Result<(), ClusteringError> {
+        let entries = self.leaf_entries();
+        let cutoff = self.outlier_density.map(|density| {
+            density * entries.iter().map(|(cf, _)| cf.n).sum::<f64>() / entries.len().max(1) as f64
+        });
+        let previous = self.threshold;
+        self.threshold = self.next_threshold();
+        self.rebuilds += 1;
+        tracing::debug!(
+            from = previous,
+            to = self.threshold,
+            entries = entries.len(),
+            "rebuilding CF-tree"
+        );
+        let (outliers, kept) = entries
+            .into_iter()
+            .partition(|(entry, _)| cutoff.is_some_and(|cutoff| entry.n < cutoff));
+        self.outliers.entries.extend::<Vec<_>>(outliers);
+        self.reinsert(kept);
+        if self.outlier_density.is_some() {
+            self.reabsorb_outliers()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the tree with one built from `entries`. Node ids start over.
+    fn reinsert(&mut self, entries: Vec<(ClusteringFeature, usize)>) {
+        self.arena.clear();
+        self.root_id = 0;
+        self.next_id = 0;
+        for (entry, id) in entries {
+            self.insert_entry(entry, Some(id));
+        }
+    }
+
+    /// `cf` faded to the current time, as is without decay
+    fn decayed(&self, mut cf: ClusteringFeature) -> ClusteringFeature {
+        if let Some(decay) = self.decay {
+            cf.decay_to(self.timestamp, decay.lambda);
+        }
+        cf
+    }
+
+    /// Fades the entries of a node to the current time, so they can be added up
+    fn decay_node(&mut self, node_id: usize) {
+        if let Some(decay) = self.decay {
+            let now = self.timestamp;
+            self.arena[node_id]
+                .features_mut()
+                .iter_mut()
+                .for_each(|(cf, _)| cf.decay_to(now, decay.lambda));
+        }
+    }
+
+    /// Drops the leaf entries that faded below `floor` where they are: the sums above them
+    /// are refreshed and the nodes left empty removed. Only when that leaves the leaves
+    /// mostly empty is the tree rebuilt, with the same threshold, from the remaining entries.
+    fn sweep(&mut self, floor: f64) {
+        let mut faded = Vec::new();
+        let mut touched = Vec::new();
+        for node_id in 0..self.arena.len() {
+            if !matches!(self.arena[node_id], CFNode::Leaf { .. }) {
+                continue;
+            }
+            self.decay_node(node_id);
+            let features = self.arena[node_id].features_mut();
+            let before = features.len();
+            features.retain(|(cf, id)| {
+                if cf.n < floor {
+                    faded.push(*id);
+                }
+                cf.n >= floor
+            });
+            if features.len() < before {
+                touched.push(node_id);
+            }
+        }
+        if faded.is_empty() {
+            return;
+        }
+        tracing::debug!(faded = faded.len(), "removing faded CF entries");
+        for id in faded.iter() {
+            self.listeners.emit(ClusterEvent::Pruned {
+                id: *id,
+                timestamp: self.timestamp,
+            });
+        }
+        self.faded += faded.len();
+
+        let (mut leaves, mut entries) = (0, 0);
+        for node in self.arena.iter() {
+            if let CFNode::Leaf { features, .. } = node {
+                leaves += usize::from(!features.is_empty());
+                entries += features.len();
+            }
+        }
+        if leaves > 1 && entries * 4 < leaves * (L + 1) {
+            tracing::debug!(entries, leaves, "rebuilding sparse CF-tree");
+            let kept = self.leaf_entries();
+            self.reinsert(kept);
+            return;
+        }
+        // Node ids only change once empty nodes go, so the sums are refreshed first
+        for leaf_id in touched {
+            let mut path = self.arena[leaf_id].parent_id();
+            while let Some(node_id) = path {
+                self.decay_node(node_id);
+                path = self.arena[node_id].parent_id();
+            }
+            if !self.arena[leaf_id].features().is_empty() {
+                self.refresh_tree_from(leaf_id);
+            }
+        }
+        while let Some(empty_id) = self
+            .arena
+            .iter()
+            .position(|node| matches!(node, CFNode::Leaf { .. }) && node.features().is_empty())
+        {
+            self.remove_empty_nodes(empty_id);
+        }
+    }
+
+    /// Puts the potential outliers that fit into a leaf entry back into the tree
+    fn reabsorb_outliers(&mut self) -> Result<usize, ClusteringError> {
+        let mut absorbed = 0;
+        for (entry, id) in self.outliers.take_all()? {
+            if self.fits(&entry) {
+                self.insert_entry(entry, Some(id));
+                absorbed += 1;
+            } else {
+                self.outliers.entries.push((entry, id));
+            }
+        }
+        self.outliers.spill()?;
+        Ok(absorbed)
+    }
+
+    /// Subtracts `entry`, weighed by `as_part_of`, from an entry that holds it: the closest
+    /// potential outlier that does, or else the leaf entry `holding_leaf_entry` finds. An entry left lighter than
+    /// the decay floor, or than half a point without decay, is removed, and so are the
+    /// nodes this leaves empty. `false` when neither holds the point.
+    fn remove(&mut self, entry: ClusteringFeature) -> Result<bool, ClusteringError> {
+        let cutoff = self.decay.map_or(0.5, |decay| decay.floor);
+        let mut entry = entry;
+        entry.last_update = self.timestamp;
+
+        let outlier = self
+            .outliers
+            .all()?
+            .iter()
+            .enumerate()
+            .filter(|(_, (cf, _))| self.holds(cf, &entry))
+            .min_by(|(_, cf_0), (_, cf_1)| {
+                self.distance
+                    .between(&cf_0.0, &entry)
+                    .total_cmp(&self.distance.between(&cf_1.0, &entry))
+            })
+            .map(|(index, _)| index);
+        if let Some(index) = outlier {
+            // In the same order as `all`
+            let mut outliers = self.outliers.take_all()?;
+            let cf = self.decayed(outliers[index].0.clone());
+            let point = self.as_part_of(&cf, &entry);
+            let remainder = cf - point;
+            if remainder.n < cutoff {
+                let (_, id) = outliers.remove(index);
+                self.listeners.emit(ClusterEvent::Pruned {
+                    id,
+                    timestamp: self.timestamp,
+                });
+            } else {
+                outliers[index].0 = remainder;
+            }
+            self.outliers.entries = outliers;
+            self.outliers.spill()?;
+            return Ok(true);
+        }
+
+        let Some((leaf_id, index)) = self.holding_leaf_entry(&entry) else {
+            return Ok(false);
+        };
+        // Fade the path to the root, so the sums refreshed below add up
+        let mut path = Some(leaf_id);
+        while let Some(node_id) = path {
+            self.decay_node(node_id);
+            path = self.arena[node_id].parent_id();
+        }
+        let entry = self.as_part_of(&self.arena[leaf_id].features()[index].0, &entry);
+        let features = self.arena[leaf_id].features_mut();
+        features[index].0 -= entry;
+        if features[index].0.n >= cutoff {
+            self.refresh_tree_from(leaf_id);
+            return Ok(true);
+        }
+        let (_, id) = features.remove(index);
+        self.listeners.emit(ClusterEvent::Pruned {
+            id,
+            timestamp: self.timestamp,
+        });
+        self.remove_empty_nodes(leaf_id);
+        Ok(true)
+    }
+
+    /// Removes `node_id` if it has no entries, then its ancestors that are left empty,
+    /// refreshes the sums above the first one that is not, and shortens the tree while
+    /// the root has a single child
+    fn remove_empty_nodes(&mut self, node_id: usize) {
+        let mut node_id = node_id;
+        loop {
+            if !self.arena[node_id].features().is_empty() {
+                self.refresh_tree_from(node_id);
+                break;
+            }
+            let Some(mut parent_id) = self.arena[node_id].parent_id() else {
+                // The last entry is gone
+                self.arena.clear();
+                self.root_id = 0;
+                self.next_id = 0;
+                return;
+            };
+            if let CFNode::Leaf { prev, next, .. } = self.arena[node_id] {
+                if let Some(CFNode::Leaf { next: link, .. }) =
+                    prev.map(|prev| &mut self.arena[prev])
+                {
+                    *link = next;
+                }
+                if let Some(CFNode::Leaf { prev: link, .. }) =
+                    next.map(|next| &mut self.arena[next])
+                {
+                    *link = prev;
+                }
+            }
+            self.arena[parent_id]
+                .features_mut()
+                .retain(|(_, child_id)| *child_id != node_id);
+            if self.drop_node(node_id) == parent_id {
+                parent_id = node_id;
+            }
+            node_id = parent_id;
+        }
+        while let CFNode::NonLeaf { features, .. } = &self.arena[self.root_id] {
+            if features.len() > 1 {
+                break;
+            }
+            let (old_root, child_id) = (self.root_id, features[0].1);
+            self.arena[child_id].set_parent_id(None);
+            self.root_id = child_id;
+            if self.drop_node(old_root) == child_id {
+                self.root_id = old_root;
+            }
+        }
+    }
+
+    /// Takes a detached node out of the arena by moving the last node into its slot, and
+    /// fixes every link to the moved node. Returns the old id of the moved node.
+    fn drop_node(&mut self, node_id: usize) -> usize {
+        let moved = self.arena.len() - 1;
+        self.arena.swap_remove(node_id);
+        self.next_id -= 1;
+        if moved == node_id {
+            return moved;
+        }
+        self.arena[node_id].set_id(node_id);
+        if self.root_id == moved {
+            self.root_id = node_id;
+        }
+        if let Some(parent_id) = self.arena[node_id].parent_id() {
+            if let Some(link) = self.arena[parent_id]
+                .features_mut()
+                .iter_mut()
+                .find(|(_, child_id)| *child_id == moved)
+            {
+                link.1 = node_id;
+            }
+        }
+        match self.arena[node_id].clone() {
+            CFNode::Leaf { prev, next, .. } => {
+                if let Some(CFNode::Leaf { next: link, .. }) =
+                    prev.map(|prev| &mut self.arena[prev])
+                {
+                    *link = Some(node_id);
+                }
+                if let Some(CFNode::Leaf { prev: link, .. }) =
+                    next.map(|next| &mut self.arena[next])
+                {
+                    *link = Some(node_id);
+                }
+            }
+            CFNode::NonLeaf { features, .. } => {
+                for (_, child_id) in features {
+                    self.arena[child_id].set_parent_id(Some(node_id));
+                }
+            }
+        }
+        moved
+    }
+
+    fn refresh_tree_from(&mut self, node_id: usize) {
+        let mut current_node_id = node_id;
+        while let Some(parent_id) = self.arena[current_node_id].parent_id() {
+            let Some(updated_sum) = self.arena[current_node_id].sum() else {
+                tracing::warn!(node = current_node_id, "refreshing from an empty node");
+                break;
+            };
+            if let CFNode::NonLeaf { features, .. } = &mut self.arena[parent_id] {
+                for (feature, child_id) in features.iter_mut() {
+                    if *child_id == current_node_id {
+                        *feature = updated_sum;
+                        break;
+                    }
+                }
+            }
+            current_node_id = parent_id;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Birch<D = Euclidean> {
+    tree: CFTree<D>,
+    cluster_count: usize,
+    global_clustering: GlobalClustering,
+    spread: Spread,
+    schema: Schema,
+}
 
 impl Birch {
-    pub fn new(threshold: f64, branching_factor: usize, k: usize) -> Self {
-        Birch {
+    pub fn new(threshold: f64, branching_factor: usize, k: usize) -> Result<Self, ClusteringError> {
+        if !threshold.is_finite() || threshold < 0. {
+            return Err(ClusteringError::invalid_parameter(
+                "threshold",
+                "must be a non-negative number",
+            ));
+        }
+        if branching_factor < 2 {
+            return Err(ClusteringError::invalid_parameter(
+                "branching_factor",
+                "must be at least 2",
+            ));
+        }
+        if k == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "k",
+                "must be at least 1",
+            ));
+        }
+        Ok(Birch {
             tree: CFTree::new(threshold, branching_factor),
             cluster_count: k,
+            global_clustering: GlobalClustering::default(),
+            spread: Spread::Radius,
+            schema: Schema::new(),
+        })
+    }
+}
+
+impl<D: Metric> Birch<D> {
+    /// Compares entry centroids with `metric` instead of the Euclidean distance. The
+    /// threshold still bounds the (Euclidean) radius of the leaf entries.
+    pub fn with_metric<E: Metric>(self, metric: E) -> Birch<E> {
+        Birch {
+            tree: self.tree.with_metric(metric),
+            cluster_count: self.cluster_count,
+            global_clustering: self.global_clustering,
+            spread: self.spread,
+            schema: self.schema,
         }
     }
 
-    pub fn insert(&mut self, instance: Point) {
-        self.tree.insert(instance);
+    /// Compares entries with one of the CF distances D0–D4 instead of the metric
+    pub fn with_cf_distance(mut self, kind: CFDistance) -> Self {
+        self.tree.distance.cf_distance = Some(kind);
+        self
     }
 
-    pub fn global_clustering(&self) -> Vec<(ClusteringFeature, usize)> {
-        let leafs: Vec<&CFNode> = self
+    /// Fixes the accepted dimension (and feature names), see `Schema` for when it fails
+    pub fn with_schema(mut self, schema: Schema) -> Result<Self, ClusteringError> {
+        self.schema.replace(schema)?;
+        Ok(self)
+    }
+
+    pub fn with_global_clustering(mut self, kind: GlobalClustering) -> Self {
+        self.global_clustering = kind;
+        self
+    }
+
+    /// Caps the CF-tree at `max_nodes` nodes. An insert that goes over raises the threshold
+    /// and rebuilds the tree from its leaf entries, as in the original BIRCH, so memory
+    /// stays bounded and a too small starting threshold only costs rebuilds.
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Result<Self, ClusteringError> {
+        if max_nodes == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "max_nodes",
+                "must be at least 1",
+            ));
+        }
+        self.tree.max_nodes = Some(max_nodes);
+        Ok(self)
+    }
+
+    /// Moves leaf entries with fewer than `density` times the mean number of points per
+    /// entry out of the tree when it is rebuilt (see `with_max_nodes`), so noise does not
+    /// take up leaves. After every rebuild, the stored entries that fit under the raised
+    /// threshold are absorbed again.
+    pub fn with_outlier_handling(mut self, density: f64) -> Result<Self, ClusteringError> {
+        if !(density > 0. && density <= 1.) {
+            return Err(ClusteringError::invalid_parameter(
+                "density",
+                "must be in (0, 1]",
+            ));
+        }
+        self.tree.outlier_density = Some(density);
+        Ok(self)
+    }
+
+    /// Keeps at most `max_in_memory` potential outliers in memory and appends the others
+    /// to `path`, which is overwritten by the first spill
+    pub fn with_outlier_file(mut self, path: impl Into<PathBuf>, max_in_memory: usize) -> Self {
+        self.tree.outliers.spill_file = Some(path.into());
+        self.tree.outliers.max_in_memory = max_in_memory;
+        self
+    }
+
+    /// Re-absorption pass over the potential outliers, e.g. at the end of the stream.
+    /// Returns how many went back into the tree.
+    pub fn reabsorb_outliers(&mut self) -> Result<usize, ClusteringError> {
+        self.tree.reabsorb_outliers()
+    }
+
+    /// Potential outliers that have not fit back into the tree
+    pub fn outliers(&self) -> Result<Vec<Outlier>, ClusteringError> {
+        Ok(self
             .tree
-            .arena
-            .iter()
-            .filter(|node| matches!(node, CFNode::Leaf { .. }))
+            .outliers
+            .all()?
+            .into_iter()
+            .map(|(cf, id)| (self.tree.decayed(cf), id))
+            .map(|(cf, id)| Outlier {
+                id,
+                center: cf.centroid(),
+                radius: cf.radius(),
+                weight: cf.n,
+            })
+            .collect())
+    }
+
+    /// Damped CF-tree for drifting streams: every entry fades by `2^(-lambda)` per inserted
+    /// point, so recent points outweigh old ones. Entries that fade below `floor` are
+    /// removed, along with the nodes they leave empty; this sweep runs as often as it takes
+    /// a single point to fade below the floor.
+    pub fn with_decay(mut self, lambda: f64, floor: f64) -> Result<Self, ClusteringError> {
+        if !(lambda.is_finite() && lambda > 0.) {
+            return Err(ClusteringError::invalid_parameter(
+                "lambda",
+                "must be positive",
+            ));
+        }
+        if !(floor > 0. && floor < 1.) {
+            return Err(ClusteringError::invalid_parameter(
+                "floor",
+                "must be in (0, 1)",
+            ));
+        }
+        self.tree.decay = Some(Decay {
+            lambda,
+            floor,
+            sweep_every: ((1. / floor).log2() / lambda).floor().max(1.) as usize,
+        });
+        Ok(self)
+    }
+
+    /// Reports the variance (and covariance) of every leaf entry in `clusters()`, see `Spread`
+    pub fn with_spread(mut self, spread: Spread) -> Result<Self, ClusteringError> {
+        if self.tree.timestamp > 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "spread",
+                "has to be set before the first insert",
+            ));
+        }
+        self.spread = spread;
+        Ok(self)
+    }
+
+    /// Current threshold, which rebuilds raise over time
+    pub fn threshold(&self) -> f64 {
+        self.tree.threshold
+    }
+
+    pub fn insert(&mut self, instance: Point) -> Result<(), ClusteringError> {
+        self.schema.validate(&instance)?;
+        self.tree
+            .insert(ClusteringFeature::new(instance).with_spread(self.spread))
+    }
+
+    pub fn insert_sparse(&mut self, instance: &SparseVector) -> Result<(), ClusteringError> {
+        self.schema.validate_sparse(instance)?;
+        self.tree
+            .insert(ClusteringFeature::from_sparse(instance).with_spread(self.spread))
+    }
+
+    /// Takes a point back out of the tree, e.g. to retract bad data or to let points leave
+    /// a sliding window. The point is subtracted from the potential outlier or the leaf
+    /// entry that holds it; entries and nodes left empty are removed. `false` when no
+    /// entry can hold the point, such as one that was never inserted.
+    pub fn remove(&mut self, instance: Point) -> Result<bool, ClusteringError> {
+        self.schema.validate(&instance)?;
+        self.tree
+            .remove(ClusteringFeature::new(instance).with_spread(self.spread))
+    }
+
+    /// Leaf entries with the global cluster each one belongs to
+    pub fn global_clustering(&self) -> Vec<(ClusteringFeature, usize)> {
+        self.labelled_entries()
+            .into_iter()
+            .map(|(cf, _, cluster)| (cf, cluster))
+            .collect()
+    }
+
+    /// Leaf entries with their ids and global clusters
+    fn labelled_entries(&self) -> Vec<(ClusteringFeature, usize, usize)> {
+        let (entries, ids): (Vec<ClusteringFeature>, Vec<usize>) =
+            self.tree.leaf_entries().into_iter().unzip();
+        if entries.is_empty() {
+            return Vec::new();
+        }
+        let assignments = match self.global_clustering {
+            GlobalClustering::KMeans => kmeans(
+                &entries,
+                self.cluster_count,
+                MAX_ITERATIONS,
+                &self.tree.distance.metric,
+            ),
+            GlobalClustering::Agglomerative => {
+                agglomerative(&entries, self.cluster_count, &self.tree.distance)
+            }
+        };
+        entries
+            .into_iter()
+            .zip(ids)
+            .zip(assignments)
+            .map(|((cf, id), cluster)| (cf, id, cluster))
+            .collect()
+    }
+
+    /// Centroid of every global cluster, indexed by cluster id
+    pub fn global_centroids(&self) -> Vec<Point> {
+        let mut sums: Vec<Option<ClusteringFeature>> = Vec::new();
+        for (cf, cluster) in self.global_clustering() {
+            if sums.len() <= cluster {
+                sums.resize(cluster + 1, None);
+            }
+            sums[cluster] = Some(match sums[cluster].take() {
+                Some(sum) => sum + cf,
+                None => cf,
+            });
+        }
+        sums.into_iter().flatten().map(|cf| cf.centroid()).collect()
+    }
+
+    /// Phase 4 of BIRCH: another pass over the data (e.g. the input read again) that
+    /// assigns every point to the nearest global centroid and recomputes the clusters from
+    /// the points themselves, undoing the coarseness of the CF entries. The tree is left
+    /// as it is.
+    pub fn refine<I: IntoIterator<Item = Point>>(
+        &self,
+        points: I,
+    ) -> Result<Refinement, ClusteringError> {
+        let seeds = self.global_centroids();
+        let mut sums: Vec<Option<ClusteringFeature>> = vec![None; seeds.len()];
+        let mut labels = Vec::new();
+        for point in points {
+            self.schema.check(&point)?;
+            let label = seeds
+                .iter()
+                .enumerate()
+                .map(|(i, seed)| (i, self.tree.distance.metric.distance(&point, seed)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .ok_or_else(|| {
+                    ClusteringError::invalid_parameter("points", "the model has no clusters yet")
+                })?
+                .0;
+            let cf = ClusteringFeature::new(point).with_spread(self.spread);
+            sums[label] = Some(match sums[label].take() {
+                Some(sum) => sum + cf,
+                None => cf,
+            });
+            labels.push(label);
+        }
+        let clusters = sums
+            .into_iter()
+            .enumerate()
+            .filter_map(|(cluster, cf)| {
+                cf.map(|cf| {
+                    let (variance, covariance) = cf.summary(self.spread);
+                    // The points carry no time, so the refined clusters have none either
+                    super::ClusteringElement {
+                        center: cf.centroid(),
+                        radius: cf.radius(),
+                        cluster,
+                        categories: Vec::new(),
+                        variance,
+                        covariance,
+                        weight: cf.n,
+                        id: cluster,
+                        created: 0,
+                        last_update: 0,
+                    }
+                })
+            })
             .collect();
-        let cfs: Vec<ClusteringFeature> = leafs.iter().map(|leafs| leafs.sum()).collect();
-        let assignments = kmeans(&cfs, self.cluster_count, MAX_ITERATIONS);
-        cfs.iter().cloned().zip(assignments).collect()
+        Ok(Refinement { labels, clusters })
     }
 
     pub fn _print_tree(&self) {
@@ -518,23 +1900,98 @@ impl Birch {
     }
 }
 
-impl super::DataStreamClusteringAlgorithm for Birch {
-    fn insert(&mut self, data: Point) {
-        self.insert(data);
+impl<D: Metric> super::DataStreamClusteringAlgorithm for Birch<D> {
+    fn insert(&mut self, data: Point) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("birch", point = self.tree.timestamp + 1).entered();
+        self.insert(data)
+    }
+    fn insert_sparse(&mut self, data: &SparseVector) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("birch", point = self.tree.timestamp + 1).entered();
+        self.insert_sparse(data)
     }
-    fn clusters(&self) -> Vec<super::ClusteringElement> {
-        self.global_clustering()
+    fn clusters(&self) -> Result<Vec<super::ClusteringElement>, ClusteringError> {
+        Ok(self
+            .labelled_entries()
             .iter()
-            .map(|(cf, i)| super::ClusteringElement {
-                center: cf.centroid(),
-                radius: cf.radius(),
-                cluster: *i,
+            .map(|(cf, id, cluster)| {
+                let (variance, covariance) = cf.summary(self.spread);
+                super::ClusteringElement {
+                    center: cf.centroid(),
+                    radius: cf.radius(),
+                    cluster: *cluster,
+                    categories: Vec::new(),
+                    variance,
+                    covariance,
+                    weight: cf.n,
+                    id: *id,
+                    created: cf.created,
+                    last_update: cf.last_point,
+                }
             })
-            .collect()
+            .collect())
     }
     fn name(&self) -> String {
         "BIRCH".to_string()
     }
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.tree.listeners.subscribe(listener);
+    }
+    fn stats(&self) -> super::AlgorithmStats {
+        let mut leaves = 0;
+        let mut micro_clusters = 0;
+        let mut total_weight = 0.;
+        let mut fan_outs = Vec::new();
+        let mut memory_bytes = std::mem::size_of::<Self>() + self.tree.outliers.memory_bytes();
+        for node in self.tree.arena.iter() {
+            memory_bytes += std::mem::size_of::<CFNode>();
+            match node {
+                CFNode::Leaf { features, .. } => {
+                    leaves += 1;
+                    micro_clusters += features.len();
+                    total_weight += features
+                        .iter()
+                        .map(|(cf, _)| self.tree.decayed(cf.clone()).n)
+                        .sum::<f64>();
+                    memory_bytes += features
+                        .iter()
+                        .map(|(cf, _)| cf.memory_bytes() + std::mem::size_of::<usize>())
+                        .sum::<usize>();
+                }
+                CFNode::NonLeaf { features, .. } => {
+                    fan_outs.push(features.len());
+                    memory_bytes += features
+                        .iter()
+                        .map(|(cf, _)| cf.memory_bytes() + std::mem::size_of::<usize>())
+                        .sum::<usize>();
+                }
+            }
+        }
+        super::AlgorithmStats {
+            micro_clusters,
+            total_weight,
+            memory_bytes,
+            details: super::AlgorithmDetails::Birch {
+                nodes: self.tree.arena.len(),
+                leaves,
+                depth: self.tree.depth(),
+                mean_fan_out: if fan_outs.is_empty() {
+                    0.
+                } else {
+                    fan_outs.iter().sum::<usize>() as f64 / fan_outs.len() as f64
+                },
+                max_fan_out: fan_outs.iter().copied().max().unwrap_or(0),
+                splits: self.tree.splits,
+                threshold: self.tree.threshold,
+                rebuilds: self.tree.rebuilds,
+                outliers: self.tree.outliers.len(),
+                faded: self.tree.faded,
+            },
+            sampler: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -548,7 +2005,155 @@ mod tests {
         let cf3 = cf1 + cf2;
 
         assert_eq!(cf3.centroid(), vec![2.5]);
-        assert_eq!(cf3.radius(), 4.);
+        assert_eq!(cf3.radius(), 0.5);
+    }
+
+    #[test]
+    fn test_far_from_origin() {
+        // Points 1e9 ± 0.5 have radius 0.5, which LS and SS lose to cancellation
+        let a = ClusteringFeature::new(vec![1e9 - 0.5, 1e9]);
+        let b = ClusteringFeature::new(vec![1e9 + 0.5, 1e9]);
+        let merged = a.clone() + b.clone();
+        assert_eq!(merged.radius(), 0.5);
+        assert_eq!(merged.centroid(), vec![1e9, 1e9]);
+        assert_eq!((merged.clone() - b.clone()).radius(), 0.);
+        assert!((merged.distance(&a, CFDistance::D2) - 0.5_f64.sqrt()).abs() < 1e-9);
+        assert!((merged.distance(&a, CFDistance::D3) - (2. / 3_f64).sqrt()).abs() < 1e-9);
+
+        let mut birch = Birch::new(1., 10, 1).unwrap();
+        for i in 0..1_000 {
+            let noise = (i % 10) as f64 * 1e-3;
+            birch.insert(vec![1e9 + noise, 1e9 + 3. * noise]).unwrap();
+        }
+        let entries = birch.tree.leaf_entries();
+        assert_eq!(entries.len(), 1);
+        // Variance 8.25e-6 per unit of noise, times 1 + 9 for the two coordinates. The
+        // coordinates themselves are only stored to about 1e-7.
+        assert!((entries[0].0.radius() / 8.25e-5_f64.sqrt() - 1.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_spread() {
+        use crate::algorithms::{DataStreamClusteringAlgorithm, Spread};
+        let mut birch = Birch::new(100., 10, 1)
+            .unwrap()
+            .with_spread(Spread::Covariance)
+            .unwrap();
+        // Elongated along x, with y following x
+        let points: Vec<Point> = (0..=10).map(|i| vec![i as f64, 0.1 * i as f64]).collect();
+        for point in points.iter() {
+            birch.insert(point.clone()).unwrap();
+        }
+        let clusters = birch.clusters().unwrap();
+        assert_eq!(clusters.len(), 1);
+        let variance = clusters[0].variance.clone().unwrap();
+        assert!((variance[0] - 10.).abs() < 1e-9);
+        assert!((variance[1] - 0.1).abs() < 1e-9);
+        let covariance = clusters[0].covariance.clone().unwrap();
+        assert!((covariance[0][1] - 1.).abs() < 1e-9);
+        assert!((covariance[1][0] - 1.).abs() < 1e-9);
+
+        // Removing points undoes them in the co-moments too
+        for point in points.iter().skip(5) {
+            birch.remove(point.clone()).unwrap();
+        }
+        let clusters = birch.clusters().unwrap();
+        // 0, 1, 2, 3, 4 have variance 2
+        assert!((clusters[0].variance.as_ref().unwrap()[0] - 2.).abs() < 1e-9);
+        assert!((clusters[0].covariance.as_ref().unwrap()[0][1] - 0.2).abs() < 1e-9);
+
+        let mut plain = Birch::new(100., 10, 1).unwrap();
+        plain.insert(vec![0., 0.]).unwrap();
+        let clusters = plain.clusters().unwrap();
+        assert!(clusters[0].variance.is_none() && clusters[0].covariance.is_none());
+    }
+
+    #[test]
+    fn test_cf_distances() {
+        let a = ClusteringFeature::new(vec![0.]) + ClusteringFeature::new(vec![2.]);
+        let b = ClusteringFeature::new(vec![4.]);
+        assert_eq!(a.distance(&b, CFDistance::D0), 3.);
+        assert_eq!(a.distance(&b, CFDistance::D1), 3.);
+        assert_eq!(a.distance(&b, CFDistance::D2), 10_f64.sqrt());
+        assert_eq!(a.distance(&b, CFDistance::D3), 8_f64.sqrt());
+        assert!((a.distance(&b, CFDistance::D4) - 6_f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_metric() {
+        use crate::algorithms::{Chebyshev, DataStreamClusteringAlgorithm};
+        let mut birch = Birch::new(1., 3, 2)
+            .unwrap()
+            .with_metric(Chebyshev)
+            .with_cf_distance(CFDistance::D2);
+        for i in 0..50 {
+            let offset = if i % 2 == 0 { 0. } else { 100. };
+            birch
+                .insert(vec![offset + (i % 5) as f64 * 0.1, offset])
+                .unwrap();
+        }
+        assert_eq!(birch.stats().micro_clusters, 2);
+    }
+
+    #[test]
+    fn test_global_clustering() {
+        let blobs = [(0., 0.), (30., 0.), (0., 30.)];
+        let points: Vec<Point> = (0..600)
+            .map(|i| {
+                let (x, y) = blobs[i % 3];
+                vec![x + (i % 11) as f64 * 0.3, y + (i % 7) as f64 * 0.3]
+            })
+            .collect();
+        for kind in [GlobalClustering::KMeans, GlobalClustering::Agglomerative] {
+            let mut birch = Birch::new(0.5, 4, 3).unwrap().with_global_clustering(kind);
+            for point in points.iter() {
+                birch.insert(point.clone()).unwrap();
+            }
+            let entries = birch.global_clustering();
+            assert_eq!(entries.len(), birch.tree.leaf_entries().len());
+            assert_eq!(birch.global_centroids().len(), 3);
+
+            let refinement = birch.refine(points.iter().cloned()).unwrap();
+            assert_eq!(refinement.labels.len(), points.len());
+            for i in 3..points.len() {
+                // Same blob, same label; different blobs, different labels
+                assert_eq!(refinement.labels[i], refinement.labels[i % 3]);
+            }
+            assert_ne!(refinement.labels[0], refinement.labels[1]);
+            assert_ne!(refinement.labels[0], refinement.labels[2]);
+            assert_ne!(refinement.labels[1], refinement.labels[2]);
+            assert_eq!(refinement.clusters.len(), 3);
+            assert!(refinement
+                .clusters
+                .iter()
+                .all(|cluster| cluster.radius < 3.));
+            assert!(birch.refine(vec![vec![0.]]).is_err());
+        }
+        let empty = Birch::new(0.5, 4, 3).unwrap();
+        assert!(empty.refine(vec![vec![0., 0.]]).is_err());
+    }
+
+    #[test]
+    fn test_global_clustering_more_clusters_than_entries() {
+        for kind in [GlobalClustering::KMeans, GlobalClustering::Agglomerative] {
+            for cluster_count in [3, 10] {
+                let mut birch = Birch::new(0.5, 4, cluster_count)
+                    .unwrap()
+                    .with_global_clustering(kind);
+                for point in [vec![0., 0.], vec![10., 0.], vec![0., 10.]] {
+                    birch.insert(point).unwrap();
+                }
+                // Every entry is a cluster of its own
+                let mut clusters: Vec<usize> = birch
+                    .global_clustering()
+                    .into_iter()
+                    .map(|(_, cluster)| cluster)
+                    .collect();
+                clusters.sort_unstable();
+                assert_eq!(clusters, vec![0, 1, 2]);
+                assert_eq!(birch.global_centroids().len(), 3);
+            }
+        }
     }
 
     #[test]
@@ -557,13 +2162,489 @@ mod tests {
             id: 0,
             parent_id: None,
             features: vec![
-                ClusteringFeature::new(vec![2.]),
-                ClusteringFeature::new(vec![3.]),
-                ClusteringFeature::new(vec![4.]),
+                (ClusteringFeature::new(vec![2.]), 0),
+                (ClusteringFeature::new(vec![3.]), 1),
+                (ClusteringFeature::new(vec![4.]), 2),
             ],
             prev: None,
             next: None,
         };
-        assert_eq!(cfnode.sum().n, 3);
+        assert_eq!(cfnode.sum().unwrap().n, 3.);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(Birch::new(-1., 50, 2).is_err());
+        assert!(Birch::new(1., 1, 2).is_err());
+        assert!(Birch::new(1., 50, 0).is_err());
+        let mut birch = Birch::new(1., 50, 2).unwrap();
+        assert!(birch.insert(vec![f64::NAN, 0.]).is_err());
+        assert!(birch.insert(vec![0., f64::INFINITY]).is_err());
+        birch.insert(vec![0., 0.]).unwrap();
+        assert_eq!(
+            birch.insert(vec![0.]),
+            Err(ClusteringError::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(birch.global_clustering().len(), 1);
+    }
+
+    #[test]
+    fn test_schema() {
+        use crate::algorithms::{DataStreamClusteringAlgorithm, Schema};
+        let mut birch = Birch::new(1., 50, 2)
+            .unwrap()
+            .with_schema(Schema::with_dimension(3).unwrap())
+            .unwrap();
+        assert!(birch.insert(vec![0., 0.]).is_err());
+        birch.insert(vec![0., 0., 0.]).unwrap();
+        assert_eq!(birch.schema().dimension(), Some(3));
+
+        // Once locked, only a schema of the same dimension is taken
+        let birch = birch
+            .with_schema(
+                Schema::with_feature_names(vec!["a".into(), "b".into(), "c".into()]).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(birch.schema().feature_names().map(<[String]>::len), Some(3));
+        assert!(birch
+            .with_schema(Schema::with_dimension(2).unwrap())
+            .is_err());
+
+        let mut birch = Birch::new(1., 50, 2).unwrap();
+        birch.insert(vec![0., 0.]).unwrap();
+        assert!(birch.with_schema(Schema::new()).is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        use crate::algorithms::{AlgorithmDetails, DataStreamClusteringAlgorithm};
+        let mut birch = Birch::new(0.5, 4, 2).unwrap();
+        for i in 0..500 {
+            birch
+                .insert(vec![(i % 50) as f64, (i / 50) as f64])
+                .unwrap();
+        }
+        let stats = birch.stats();
+        match stats.details {
+            AlgorithmDetails::Birch {
+                nodes,
+                leaves,
+                depth,
+                max_fan_out,
+                ..
+            } => {
+                assert!(depth > 1);
+                assert!(leaves < nodes);
+                assert!(max_fan_out <= 4);
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            stats.total_weight,
+            birch.tree.arena[birch.tree.root_id].sum().unwrap().n
+        );
+    }
+
+    #[test]
+    fn test_max_nodes() {
+        use crate::algorithms::{AlgorithmDetails, DataStreamClusteringAlgorithm};
+        let mut birch = Birch::new(0., 4, 2).unwrap().with_max_nodes(20).unwrap();
+        for i in 0..2_000 {
+            birch
+                .insert(vec![(i % 97) as f64, (i % 89) as f64])
+                .unwrap();
+            assert!(birch.tree.arena.len() <= 20);
+        }
+        assert!(birch.threshold() > 0.);
+        let stats = birch.stats();
+        assert_eq!(stats.total_weight, 2_000.);
+        match stats.details {
+            AlgorithmDetails::Birch {
+                rebuilds, leaves, ..
+            } => {
+                assert!(rebuilds > 0);
+                // The leaf chain still visits every leaf
+                let chained = birch.tree.leaf_entries().len();
+                assert_eq!(chained, stats.micro_clusters);
+                assert!(leaves > 0);
+            }
+            _ => unreachable!(),
+        }
+        assert!(Birch::new(1., 4, 2).unwrap().with_max_nodes(0).is_err());
+    }
+
+    #[test]
+    fn test_max_nodes_stops_at_cap() {
+        let mut birch = Birch::new(0., 4, 2).unwrap().with_max_nodes(3).unwrap();
+        let mut at_cap = false;
+        for i in 0..300 {
+            let (threshold, rebuilds) = (birch.threshold(), birch.tree.rebuilds);
+            birch.insert(vec![i as f64, (i % 7) as f64]).unwrap();
+            assert!(birch.tree.arena.len() <= 3);
+            // The threshold only grows with a rebuild, and a tree at the cap is left alone
+            if birch.tree.rebuilds == rebuilds {
+                assert_eq!(birch.threshold(), threshold);
+                at_cap |= birch.tree.arena.len() == 3;
+            }
+        }
+        assert!(at_cap);
+        assert!(birch.tree.rebuilds > 0);
+
+        // Once every point fits, more of them cost no rebuild
+        let (threshold, rebuilds) = (birch.threshold(), birch.tree.rebuilds);
+        for _ in 0..100 {
+            birch.insert(vec![150., 3.]).unwrap();
+        }
+        assert_eq!(
+            (birch.threshold(), birch.tree.rebuilds),
+            (threshold, rebuilds)
+        );
+    }
+
+    /// Parent links, child sums and the leaf chain all agree with each other
+    fn assert_consistent(tree: &CFTree<Euclidean>) {
+        for (node_id, node) in tree.arena.iter().enumerate() {
+            match node {
+                CFNode::Leaf { id, .. } => assert_eq!(*id, node_id),
+                CFNode::NonLeaf { id, features, .. } => {
+                    assert_eq!(*id, node_id);
+                    for (cf, child_id) in features {
+                        assert_eq!(tree.arena[*child_id].parent_id(), Some(node_id));
+                        // Weights fade alike, so they add up at any time
+                        let children: f64 = tree.arena[*child_id]
+                            .features()
+                            .iter()
+                            .map(|(child, _)| tree.decayed(child.clone()).n)
+                            .sum();
+                        assert!((children - tree.decayed(cf.clone()).n).abs() < 1e-9);
+                    }
+                }
+            }
+        }
+        let leaves = tree
+            .arena
+            .iter()
+            .filter(|node| matches!(node, CFNode::Leaf { .. }))
+            .map(|node| node.features().len())
+            .sum::<usize>();
+        assert_eq!(tree.leaf_entries().len(), leaves);
+    }
+
+    #[test]
+    fn test_remove() {
+        use crate::algorithms::DataStreamClusteringAlgorithm;
+        let a = ClusteringFeature::new(vec![1., 2.]);
+        let b = ClusteringFeature::new(vec![3., 6.]);
+        let difference = (a.clone() + b.clone()) - b;
+        assert_eq!(difference.n, 1.);
+        assert_eq!(difference.centroid(), a.centroid());
+        assert_eq!(difference.m2, a.m2);
+
+        let mut birch = Birch::new(0.5, 3, 2).unwrap();
+        assert_eq!(birch.remove(vec![0., 0.]), Ok(false));
+        let points: Vec<Point> = (0..300)
+            .map(|i| vec![(i % 17) as f64 * 3., (i % 13) as f64 * 3.])
+            .collect();
+        for point in points.iter() {
+            birch.insert(point.clone()).unwrap();
+        }
+        assert!(birch.tree.depth() > 2);
+        // Points that were never inserted are in no entry, between the grid points or far off
+        let before = shape(&birch.tree);
+        assert_eq!(birch.remove(vec![1.5, 1.5]), Ok(false));
+        assert_eq!(birch.remove(vec![1_000., 1_000.]), Ok(false));
+        assert_eq!(shape(&birch.tree), before);
+        assert_eq!(birch.stats().total_weight, 300.);
+        for (i, point) in points.iter().enumerate().rev() {
+            assert_eq!(birch.remove(point.clone()), Ok(true));
+            assert_consistent(&birch.tree);
+            let total: f64 = birch.tree.leaf_entries().iter().map(|(cf, _)| cf.n).sum();
+            assert!((total - i as f64).abs() < 1e-9);
+        }
+        assert!(birch.tree.arena.is_empty());
+        assert!(birch.remove(vec![0.]).is_err());
+
+        // A sliding window keeps only the latest points
+        let mut window = Birch::new(0.5, 3, 2).unwrap();
+        for (i, point) in points.iter().enumerate() {
+            window.insert(point.clone()).unwrap();
+            if i >= 50 {
+                window.remove(points[i - 50].clone()).unwrap();
+            }
+        }
+        assert_consistent(&window.tree);
+        assert!((window.stats().total_weight - 50.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_remove_outlier() {
+        use crate::algorithms::DataStreamClusteringAlgorithm;
+        let mut birch = Birch::new(0.5, 4, 3)
+            .unwrap()
+            .with_max_nodes(6)
+            .unwrap()
+            .with_outlier_handling(0.2)
+            .unwrap();
+        for i in 0..3_000 {
+            birch.insert(noisy_point(i)).unwrap();
+        }
+        let outliers = birch.tree.outliers.len();
+        let point = (0..3_000)
+            .step_by(25)
+            .map(noisy_point)
+            .find(|point| {
+                let entries = birch.tree.outliers.all().unwrap();
+                entries.iter().any(|(cf, _)| cf.centroid() == *point)
+            })
+            .unwrap();
+        let weight = birch.stats().total_weight;
+        assert_eq!(birch.remove(point.clone()), Ok(true));
+        assert_eq!(birch.tree.outliers.len(), outliers - 1);
+        assert_eq!(birch.stats().total_weight, weight);
+        let entries = birch.tree.outliers.all().unwrap();
+        assert!(entries.iter().all(|(cf, _)| cf.centroid() != point));
+    }
+
+    #[test]
+    fn test_remove_with_decay() {
+        // Sweeps are too rare to run here
+        let mut birch = Birch::new(0.5, 3, 2)
+            .unwrap()
+            .with_decay(0.005, 0.1)
+            .unwrap();
+        birch.insert(vec![0., 0.]).unwrap();
+        birch.insert(vec![0., 0.]).unwrap();
+        for i in 0..101 {
+            birch.insert(vec![10. + i as f64 * 3., 10.]).unwrap();
+        }
+        let weight = |birch: &Birch| {
+            let entries = birch.tree.leaf_entries();
+            let origin = entries.iter().find(|(cf, _)| cf.centroid() == vec![0., 0.]);
+            origin.map(|(cf, _)| birch.tree.decayed(cf.clone()).n)
+        };
+        // Both points faded to about 0.7, and only as much is taken out for one of them
+        assert!((weight(&birch).unwrap() - 1.4).abs() < 0.01);
+        assert_eq!(birch.remove(vec![0., 0.]), Ok(true));
+        assert!((weight(&birch).unwrap() - 0.7).abs() < 0.01);
+        assert_consistent(&birch.tree);
+        // The faded entry is still found, and nothing of it is left after the other point
+        assert_eq!(birch.remove(vec![0., 0.]), Ok(true));
+        assert_eq!(weight(&birch), None);
+        assert_eq!(birch.remove(vec![0., 0.]), Ok(false));
+        assert_eq!(birch.tree.leaf_entries().len(), 101);
+        assert_consistent(&birch.tree);
+    }
+
+    #[test]
+    fn test_decay() {
+        use crate::algorithms::{AlgorithmDetails, DataStreamClusteringAlgorithm};
+        let mut birch = Birch::new(1., 10, 1)
+            .unwrap()
+            .with_decay(0.01, 0.05)
+            .unwrap();
+        // The stream drifts from around (0, 0) to around (100, 100)
+        for i in 0..4_000 {
+            let shift = if i < 2_000 { 0. } else { 100. };
+            let jitter = (i % 5) as f64 * 0.1;
+            birch.insert(vec![shift + jitter, shift - jitter]).unwrap();
+        }
+        let stats = birch.stats();
+        // A point weighs 2^(-0.01·age), so the total stays near 1 / (1 - 2^(-0.01))
+        assert!(stats.total_weight < 150.);
+        match stats.details {
+            AlgorithmDetails::Birch { faded, .. } => assert!(faded > 0),
+            _ => unreachable!(),
+        }
+        let clusters = birch.clusters().unwrap();
+        assert!(clusters.iter().all(|element| element.center[0] > 50.));
+
+        let cf = ClusteringFeature::new(vec![2., 4.]);
+        let mut faded = cf.clone();
+        faded.decay_to(100, 0.01);
+        assert!((faded.n - 0.5).abs() < 1e-12);
+        assert_eq!(faded.centroid(), cf.centroid());
+        assert!(Birch::new(1., 10, 1).unwrap().with_decay(0., 0.5).is_err());
+        assert!(Birch::new(1., 10, 1).unwrap().with_decay(0.1, 1.).is_err());
+    }
+
+    /// Spill file in the temp directory, unique to this process and call so concurrent runs
+    /// do not share it, and removed on drop even when the test panics
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            let count = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            TempPath(std::env::temp_dir().join(format!(
+                "clustermancer_{}_{}_{}.ndjson",
+                name,
+                std::process::id(),
+                count
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
+
+    /// Three tight clusters with every 25th point thinly spread far away from them
+    fn noisy_point(i: usize) -> Point {
+        let centers = [(0., 0.), (50., 50.), (100., 0.)];
+        if i.is_multiple_of(25) {
+            vec![1_000. + (i * 7_919 % 100_000) as f64, 1_000.]
+        } else {
+            let (x, y) = centers[i % 3];
+            vec![x + (i % 7) as f64 * 0.1, y + (i % 5) as f64 * 0.1]
+        }
+    }
+
+    /// Node ids with their parents and the ids of their entries
+    fn shape(tree: &CFTree<Euclidean>) -> Vec<(Option<usize>, Vec<usize>)> {
+        tree.arena
+            .iter()
+            .map(|node| {
+                let ids = node.features().iter().map(|(_, id)| *id).collect();
+                (node.parent_id(), ids)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sweep_in_place() {
+        // Decay slow enough to leave every weight at 1 for the test, and no sweep on insert
+        let mut birch = Birch::new(0.5, 3, 2)
+            .unwrap()
+            .with_decay(1e-12, 0.5)
+            .unwrap();
+        for i in 0..300 {
+            birch
+                .insert(vec![(i % 17) as f64 * 3., (i % 13) as f64 * 3.])
+                .unwrap();
+        }
+        assert!(birch.tree.depth() > 2);
+        let leaf_ids: Vec<usize> = (0..birch.tree.arena.len())
+            .filter(|&id| matches!(birch.tree.arena[id], CFNode::Leaf { .. }))
+            .collect();
+
+        // One entry of a leaf fades: it goes, and every node stays where it was
+        let mut expected = shape(&birch.tree);
+        let leaf = leaf_ids[0];
+        let entry = &mut birch.tree.arena[leaf].features_mut()[0];
+        entry.0.n = 0.1;
+        let faded_id = entry.1;
+        expected[leaf].1.retain(|id| *id != faded_id);
+        birch.tree.sweep(0.5);
+        assert_eq!(shape(&birch.tree), expected);
+        assert_eq!(birch.tree.faded, 1);
+        assert_consistent(&birch.tree);
+
+        // A leaf that fades as a whole is removed, the others keep their entries
+        let leaf = leaf_ids[1];
+        let mut expected: Vec<usize> = birch
+            .tree
+            .leaf_entries()
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        for (cf, id) in birch.tree.arena[leaf].features_mut() {
+            cf.n = 0.1;
+            expected.retain(|other| other != id);
+        }
+        let nodes = birch.tree.arena.len();
+        birch.tree.sweep(0.5);
+        let kept: Vec<usize> = birch
+            .tree
+            .leaf_entries()
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        assert_eq!(kept, expected);
+        assert!(birch.tree.arena.len() < nodes);
+        assert_consistent(&birch.tree);
+    }
+
+    #[test]
+    fn test_decay_floor() {
+        // One step fades an entry by half, right down to the floor, where it stays
+        let mut birch = Birch::new(1., 10, 1).unwrap().with_decay(1., 0.5).unwrap();
+        birch.insert(vec![0., 0.]).unwrap();
+        birch.insert(vec![100., 0.]).unwrap();
+        assert_eq!(birch.tree.leaf_entries().len(), 2);
+        assert_eq!(birch.tree.faded, 0);
+        // Below it the next step
+        birch.insert(vec![200., 0.]).unwrap();
+        let ids: Vec<usize> = birch
+            .tree
+            .leaf_entries()
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(birch.tree.faded, 1);
+        // A floor at the weight of a fresh entry would drop every entry one step later
+        assert!(Birch::new(1., 10, 1).unwrap().with_decay(1., 1.).is_err());
+    }
+
+    #[test]
+    fn test_outliers() {
+        use crate::algorithms::DataStreamClusteringAlgorithm;
+        let spill = TempPath::new("birch_outliers");
+        let path = &spill.0;
+        let mut birch = Birch::new(0.5, 4, 3)
+            .unwrap()
+            .with_max_nodes(6)
+            .unwrap()
+            .with_outlier_handling(0.2)
+            .unwrap()
+            .with_outlier_file(path, 2);
+        for i in 0..3_000 {
+            birch.insert(noisy_point(i)).unwrap();
+        }
+        birch.reabsorb_outliers().unwrap();
+        let outliers = birch.outliers().unwrap();
+        assert!(!outliers.is_empty());
+        assert!(outliers.iter().all(|outlier| outlier.center[0] >= 1_000.));
+        assert!(birch.tree.outliers.entries.len() <= 2);
+        assert!(path.exists());
+        // Every point is either in the tree or an outlier
+        let stored: f64 = outliers.iter().map(|outlier| outlier.weight).sum();
+        assert_eq!(birch.stats().total_weight + stored, 3_000.);
+        assert!(Birch::new(1., 4, 2)
+            .unwrap()
+            .with_outlier_handling(0.)
+            .is_err());
+    }
+
+    #[test]
+    fn test_outlier_spill_fails() {
+        use crate::algorithms::DataStreamClusteringAlgorithm;
+        let missing = TempPath::new("missing_dir");
+        let mut birch = Birch::new(0.5, 4, 3)
+            .unwrap()
+            .with_max_nodes(6)
+            .unwrap()
+            .with_outlier_handling(0.2)
+            .unwrap()
+            .with_outlier_file(missing.0.join("outliers.ndjson"), 2);
+        let mut inserted = 0;
+        let err = loop {
+            inserted += 1;
+            if let Err(err) = birch.insert(noisy_point(inserted - 1)) {
+                break err;
+            }
+            assert!(inserted < 3_000, "the spill never failed");
+        };
+        assert!(matches!(err, ClusteringError::Io(_)));
+        // The point went in before the rebuild, and the entries that could not be written
+        // stay in memory
+        let stored: f64 = birch.outliers().unwrap().iter().map(|o| o.weight).sum();
+        assert!(birch.tree.outliers.entries.len() > 2);
+        assert_eq!(birch.stats().total_weight + stored, inserted as f64);
     }
 }
@@ -1,9 +1,13 @@
-use itertools::Itertools;
+use super::{
+    mixed::CategoricalSummary, moments::Moments, ClusterEvent, ClusterEventListener,
+    ClusteringError, Euclidean, Listeners, Metric, MixedPoint, NeighbourIndex, NeighbourSearch,
+    PointView, Schema, SparseVector, Spread,
+};
 use rand::prelude::*;
 use rand_pcg::Pcg64;
 use statrs::distribution::{ContinuousCDF, Normal};
 
-/// Memory size
+/// Default memory size, the number of micro-clusters
 const Q: usize = 50;
 /// Max kmeans iterations
 const MAX_ITERATIONS: usize = 10_000;
@@ -22,118 +26,127 @@ const ALPHA_L: usize = ALPHA.pow(L as u32);
 
 #[derive(Debug, Clone)]
 struct MicroCluster {
-    cf2x: Vec<f64>,
-    cf1x: Vec<f64>,
-    cf2t: f64,
-    cf1t: f64,
-    n: usize,
+    /// Mean and M2 of the points, in place of CF1x/CF2x
+    points: Moments,
+    /// Mean and M2 of the timestamps, in place of CF1t/CF2t
+    timestamps: Moments,
+    categories: CategoricalSummary,
+    /// Timestamps of the first and the newest point
+    created: usize,
+    last_update: usize,
 }
 
 impl MicroCluster {
-    fn new(instance: Vec<f64>, time_stamp: usize) -> Self {
+    fn new(instance: Vec<f64>, categorical: &[String], time_stamp: usize, spread: Spread) -> Self {
+        let mut categories = CategoricalSummary::default();
+        categories.add(categorical, 1.);
+        let mut points = Moments::from_point(&instance);
+        if spread == Spread::Covariance {
+            points = points.with_covariance();
+        }
         MicroCluster {
-            cf2x: instance.iter().map(|x| x * x).collect(),
-            cf1x: instance.clone(),
-            cf2t: (time_stamp * time_stamp) as f64,
-            cf1t: time_stamp as f64,
-            n: 1,
+            points,
+            timestamps: Moments::from_point(&[time_stamp as f64]),
+            categories,
+            created: time_stamp,
+            last_update: time_stamp,
         }
     }
 
+    fn n(&self) -> f64 {
+        self.points.weight()
+    }
+
     fn centroid(&self) -> Vec<f64> {
-        self.cf1x.iter().map(|x| x / self.n as f64).collect()
+        self.points.mean().to_vec()
     }
 
-    fn distance(&self, instance: &[f64]) -> f64 {
-        let centroid = self.centroid();
-        instance
-            .iter()
-            .zip(centroid.iter())
-            .map(|(x, y)| (x - y).powi(2))
-            .sum::<f64>()
-            .sqrt()
+    fn distance<D: Metric, P: PointView + ?Sized>(&self, instance: &P, metric: &D) -> f64 {
+        instance.distance(metric, self.points.mean())
+    }
+
+    /// `distance` plus the weighted share of the micro-cluster that disagrees with the
+    /// record's categorical attributes
+    fn mixed_distance<D: Metric, P: PointView + ?Sized>(
+        &self,
+        instance: &P,
+        categorical: &[String],
+        metric: &D,
+        categorical_weight: f64,
+    ) -> f64 {
+        self.distance(instance, metric)
+            + categorical_weight * self.categories.dissimilarity(categorical)
+    }
+
+    /// Same as adding `MicroCluster::new(instance, categorical, time_stamp)`
+    fn add_point<P: PointView + ?Sized>(
+        &mut self,
+        instance: &P,
+        categorical: &[String],
+        time_stamp: usize,
+    ) {
+        self.categories.add(categorical, 1.);
+        self.points.add(instance, 1.);
+        self.timestamps.add([time_stamp as f64].as_slice(), 1.);
+        self.last_update = self.last_update.max(time_stamp);
     }
 
     fn maximal_boundary(&self) -> Option<f64> {
-        if self.n > 1 {
-            let a = self
-                .cf2x
-                .iter()
-                .map(|x_p| x_p / self.n as f64)
-                .collect::<Vec<f64>>();
-            let b = self
-                .centroid()
-                .iter()
-                .map(|x_p| x_p.powi(2))
-                .collect::<Vec<f64>>();
+        if self.n() > 1. {
+            let dimension = self.points.mean().len() as f64;
             return Some(
-                MAXIMUM_BOUNDARY_FACTOR
-                    * (a.iter()
-                        .zip(b.iter())
-                        .map(|(x, y)| (x - y) / a.len() as f64)
-                        .sum::<f64>()
-                        .sqrt()),
+                MAXIMUM_BOUNDARY_FACTOR * (self.points.total_variance() / dimension).sqrt(),
             );
         }
         None
     }
 
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.points.memory_bytes()
+            + self.timestamps.memory_bytes()
+            + self.categories.memory_bytes()
+    }
+
     fn relevance_stamp(&self) -> f64 {
-        let mean = self.cf1t / self.n as f64;
-        if self.n < 2 * M {
+        let mean = self.timestamps.mean()[0];
+        if self.n() < (2 * M) as f64 {
             return mean;
         }
-        let standard_deviation = (self.cf2t / self.n as f64 - mean.powi(2)).sqrt();
-        let procentile = M as f64 / (2 * self.n) as f64;
-        let normal = Normal::new(mean, standard_deviation).unwrap();
-        normal.inverse_cdf(procentile)
+        let standard_deviation = self.timestamps.total_variance().sqrt();
+        let procentile = M as f64 / (2. * self.n());
+        match Normal::new(mean, standard_deviation) {
+            Ok(normal) => normal.inverse_cdf(procentile),
+            // All timestamps (nearly) equal
+            Err(_) => mean,
+        }
     }
 }
 
 impl std::ops::Add for MicroCluster {
     type Output = Self;
-    fn add(self, other: Self) -> Self {
-        MicroCluster {
-            cf2x: self
-                .cf2x
-                .iter()
-                .zip(other.cf2x.iter())
-                .map(|(x, y)| x + y)
-                .collect(),
-            cf1x: self
-                .cf1x
-                .iter()
-                .zip(other.cf1x.iter())
-                .map(|(x, y)| x + y)
-                .collect(),
-            cf2t: self.cf2t + other.cf2t,
-            cf1t: self.cf1t + other.cf1t,
-            n: self.n + other.n,
-        }
+    fn add(mut self, other: Self) -> Self {
+        self += other;
+        self
     }
 }
 
 impl std::ops::AddAssign for MicroCluster {
     fn add_assign(&mut self, other: Self) {
-        self.cf2x = self
-            .cf2x
-            .iter()
-            .zip(other.cf2x.iter())
-            .map(|(x, y)| x + y)
-            .collect();
-        self.cf1x = self
-            .cf1x
-            .iter()
-            .zip(other.cf1x.iter())
-            .map(|(x, y)| x + y)
-            .collect();
-        self.cf2t += other.cf2t;
-        self.cf1t += other.cf1t;
-        self.n += other.n;
+        self.points.merge(&other.points);
+        self.timestamps.merge(&other.timestamps);
+        self.categories.merge(&other.categories);
+        self.created = self.created.min(other.created);
+        self.last_update = self.last_update.max(other.last_update);
     }
 }
 
-fn kmeans(instances: Vec<Vec<f64>>, k: usize, max_iterations: usize) -> Vec<usize> {
+fn kmeans<D: Metric>(
+    instances: Vec<Vec<f64>>,
+    k: usize,
+    max_iterations: usize,
+    metric: &D,
+) -> Vec<usize> {
     let mut rng = Pcg64::from_entropy();
     let mut centroids: Vec<Vec<f64>> = instances.choose_multiple(&mut rng, k).cloned().collect();
     let mut assignments: Vec<usize> = vec![0; instances.len()];
@@ -144,12 +157,7 @@ fn kmeans(instances: Vec<Vec<f64>>, k: usize, max_iterations: usize) -> Vec<usiz
             let mut min_distance = f64::MAX;
             let mut closest_centroid = 0;
             for (j, centroid) in centroids.iter().enumerate() {
-                let distance = instance
-                    .iter()
-                    .zip(centroid.iter())
-                    .map(|(x, y)| (x - y).powi(2))
-                    .sum::<f64>()
-                    .sqrt();
+                let distance = metric.distance(instance, centroid);
                 if distance < min_distance {
                     min_distance = distance;
                     closest_centroid = j;
@@ -169,6 +177,13 @@ fn kmeans(instances: Vec<Vec<f64>>, k: usize, max_iterations: usize) -> Vec<usiz
             counts[cluster] += 1;
         }
         for (i, centroid) in new_centroids.iter_mut().enumerate() {
+            if counts[i] == 0 {
+                // Keep the centroid of an empty cluster where it was
+                if let Some(previous) = centroids.get(i) {
+                    centroid.clone_from(previous);
+                }
+                continue;
+            }
             for value in centroid.iter_mut() {
                 *value /= counts[i] as f64;
             }
@@ -184,6 +199,17 @@ fn kmeans(instances: Vec<Vec<f64>>, k: usize, max_iterations: usize) -> Vec<usiz
     assignments
 }
 
+fn micro_clusters_memory_bytes(micro_clusters: &[(MicroCluster, Vec<usize>)]) -> usize {
+    micro_clusters
+        .iter()
+        .map(|(mc, ids)| {
+            mc.memory_bytes()
+                + std::mem::size_of::<Vec<usize>>()
+                + ids.capacity() * std::mem::size_of::<usize>()
+        })
+        .sum()
+}
+
 #[derive(Debug, Clone)]
 struct Snapshot {
     timestamp: usize,
@@ -219,10 +245,30 @@ impl SnapshotVault {
         }
     }
 
+    fn occupancy(&self) -> Vec<usize> {
+        self.snapshots
+            .iter()
+            .map(|(snapshots, _)| snapshots.iter().flatten().count())
+            .collect()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.snapshots
+            .iter()
+            .flat_map(|(snapshots, _)| snapshots.iter())
+            .map(|snapshot| {
+                std::mem::size_of::<Option<Snapshot>>()
+                    + snapshot.as_ref().map_or(0, |snapshot| {
+                        micro_clusters_memory_bytes(&snapshot._micro_clusters)
+                    })
+            })
+            .sum()
+    }
+
     fn find_orders(clock_time: usize) -> usize {
         let mut i: usize = 0;
         let mut tmp_clock_time = clock_time;
-        while tmp_clock_time % ALPHA == 0 {
+        while tmp_clock_time.is_multiple_of(ALPHA) {
             tmp_clock_time /= ALPHA;
             i += 1;
         }
@@ -231,44 +277,215 @@ impl SnapshotVault {
 }
 
 #[derive(Debug)]
-pub struct CluStream {
+pub struct CluStream<D = Euclidean> {
     snapshot_vault: SnapshotVault,
     micro_clusters: Vec<(MicroCluster, Vec<usize>)>,
     initiated: bool,
     initial_buffer: Vec<Vec<f64>>,
+    /// Categorical attributes of the buffered points
+    initial_categories: Vec<Vec<String>>,
     clock: usize,
     next_id: usize,
     k: usize,
+    merges: usize,
+    replacements: usize,
+    listeners: Listeners,
+    schema: Schema,
+    metric: D,
+    categorical_weight: f64,
+    spread: Spread,
+    max_micro_clusters: usize,
+    search: NeighbourSearch,
+    /// Centroids of `micro_clusters` under their positions
+    index: Box<dyn NeighbourIndex>,
 }
 
 impl CluStream {
-    pub fn new(k: usize) -> Self {
-        CluStream {
+    pub fn new(k: usize) -> Result<Self, ClusteringError> {
+        if k == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "k",
+                "must be at least 1",
+            ));
+        }
+        Ok(CluStream {
             snapshot_vault: SnapshotVault::new(),
             micro_clusters: Vec::new(),
             initiated: false,
             initial_buffer: Vec::new(),
+            initial_categories: Vec::new(),
             clock: 1,
             next_id: 0,
             k,
+            merges: 0,
+            replacements: 0,
+            listeners: Listeners::default(),
+            schema: Schema::new(),
+            metric: Euclidean,
+            categorical_weight: 1.,
+            spread: Spread::Radius,
+            max_micro_clusters: Q,
+            search: NeighbourSearch::Linear,
+            index: NeighbourSearch::Linear.build(Euclidean),
+        })
+    }
+}
+
+impl<D: Metric> CluStream<D> {
+    /// Compares points and micro-cluster centroids with `metric` instead of the Euclidean
+    /// distance. The maximal boundary stays an RMS deviation.
+    pub fn with_metric<E: Metric>(self, metric: E) -> CluStream<E> {
+        let mut clustream = CluStream {
+            snapshot_vault: self.snapshot_vault,
+            micro_clusters: self.micro_clusters,
+            initiated: self.initiated,
+            initial_buffer: self.initial_buffer,
+            initial_categories: self.initial_categories,
+            clock: self.clock,
+            next_id: self.next_id,
+            k: self.k,
+            merges: self.merges,
+            replacements: self.replacements,
+            listeners: self.listeners,
+            schema: self.schema,
+            index: self.search.build(metric.clone()),
+            metric,
+            categorical_weight: self.categorical_weight,
+            spread: self.spread,
+            max_micro_clusters: self.max_micro_clusters,
+            search: self.search,
+        };
+        clustream.reindex();
+        clustream
+    }
+
+    /// Scales the categorical mismatch of mixed records (in `[0, 1]`) against the numeric
+    /// distance to a micro-cluster. Defaults to 1.
+    pub fn with_categorical_weight(mut self, weight: f64) -> Result<Self, ClusteringError> {
+        if !(weight.is_finite() && weight >= 0.) {
+            return Err(ClusteringError::invalid_parameter(
+                "categorical_weight",
+                "must be finite and non-negative",
+            ));
         }
+        self.categorical_weight = weight;
+        Ok(self)
     }
 
-    pub fn insert(&mut self, instance: Vec<f64>) {
+    /// Reports the variance (and covariance) of every micro-cluster in `clusters()`, see
+    /// `Spread`
+    pub fn with_spread(mut self, spread: Spread) -> Result<Self, ClusteringError> {
+        if self.initiated || !self.initial_buffer.is_empty() {
+            return Err(ClusteringError::invalid_parameter(
+                "spread",
+                "has to be set before the first insert",
+            ));
+        }
+        self.spread = spread;
+        Ok(self)
+    }
+
+    /// Finds the nearest micro-cluster (and the closest pair to merge) through `search`
+    /// instead of comparing every micro-cluster. Records with categorical attributes are
+    /// still compared with every micro-cluster, as the index only knows the numeric part.
+    pub fn with_search(mut self, search: NeighbourSearch) -> Self {
+        self.search = search;
+        self.index = search.build(self.metric.clone());
+        self.reindex();
+        self
+    }
+
+    /// Number of micro-clusters kept (q in the paper), 50 by default
+    pub fn with_max_micro_clusters(mut self, q: usize) -> Result<Self, ClusteringError> {
+        if q < 2 {
+            return Err(ClusteringError::invalid_parameter(
+                "max_micro_clusters",
+                "must be at least 2",
+            ));
+        }
+        self.max_micro_clusters = q;
+        Ok(self)
+    }
+
+    fn reindex(&mut self) {
+        self.index.clear();
+        for (i, (mc, _)) in self.micro_clusters.iter().enumerate() {
+            self.index.insert(i, &mc.centroid());
+        }
+    }
+
+    /// Position of the micro-cluster closest to the record
+    fn nearest<P: PointView + ?Sized>(&self, instance: &P, categorical: &[String]) -> usize {
+        if categorical.is_empty() || self.categorical_weight == 0. {
+            if let Some((i, _)) = self.index.nearest(&instance.to_dense()) {
+                return i;
+            }
+        }
+        self.micro_clusters
+            .iter()
+            .enumerate()
+            .map(|(i, (mc, _))| {
+                let distance =
+                    mc.mixed_distance(instance, categorical, &self.metric, self.categorical_weight);
+                (i, distance)
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+            .0
+    }
+
+    /// Fixes the accepted dimension (and feature names), see `Schema` for when it fails
+    pub fn with_schema(mut self, schema: Schema) -> Result<Self, ClusteringError> {
+        self.schema.replace(schema)?;
+        Ok(self)
+    }
+
+    pub fn insert(&mut self, instance: Vec<f64>) -> Result<(), ClusteringError> {
+        self.schema.validate(&instance)?;
+        self.insert_point(instance.as_slice(), &[]);
+        Ok(())
+    }
+
+    pub fn insert_sparse(&mut self, instance: &SparseVector) -> Result<(), ClusteringError> {
+        self.schema.validate_sparse(instance)?;
+        self.insert_point(instance, &[]);
+        Ok(())
+    }
+
+    /// Micro-clusters keep a frequency histogram of every categorical attribute, and the
+    /// nearest micro-cluster is picked by the mixed distance (see `with_categorical_weight`)
+    pub fn insert_mixed(&mut self, instance: &MixedPoint) -> Result<(), ClusteringError> {
+        self.schema.validate_mixed(instance)?;
+        self.insert_point(instance.numeric(), instance.categorical());
+        Ok(())
+    }
+
+    fn insert_point<P: PointView + ?Sized>(&mut self, instance: &P, categorical: &[String]) {
         if !self.initiated {
             // Step 1: Initialize micro-clusters with the first INIT_NUMBER points using k-means
-            self.initial_buffer.push(instance);
+            self.initial_buffer.push(instance.to_dense());
+            self.initial_categories.push(categorical.to_vec());
             if self.initial_buffer.len() == INIT_NUMBER {
                 self.initiated = true;
-                let initial_micro_cluster_mapping =
-                    kmeans(self.initial_buffer.clone(), Q, MAX_ITERATIONS);
-                let mut micro_clusters: Vec<Option<MicroCluster>> = vec![None; Q];
+                let initial_micro_cluster_mapping = kmeans(
+                    self.initial_buffer.clone(),
+                    self.max_micro_clusters,
+                    MAX_ITERATIONS,
+                    &self.metric,
+                );
+                let mut micro_clusters: Vec<Option<MicroCluster>> =
+                    vec![None; self.max_micro_clusters];
                 for (i, group) in initial_micro_cluster_mapping.iter().enumerate() {
+                    let initial = MicroCluster::new(
+                        self.initial_buffer[i].clone(),
+                        &self.initial_categories[i],
+                        i,
+                        self.spread,
+                    );
                     if let Some(micro_cluster) = &mut micro_clusters[*group] {
-                        *micro_cluster += MicroCluster::new(self.initial_buffer[i].clone(), i);
+                        *micro_cluster += initial;
                     } else {
-                        micro_clusters[*group] =
-                            Some(MicroCluster::new(self.initial_buffer[i].clone(), i));
+                        micro_clusters[*group] = Some(initial);
                     }
                 }
                 self.micro_clusters = micro_clusters
@@ -278,72 +495,117 @@ impl CluStream {
                     .map(|(i, mc)| (mc, vec![i]))
                     .collect();
                 self.next_id = self.micro_clusters.len();
+                self.reindex();
                 self.initial_buffer.clear();
+                self.initial_categories.clear();
+                tracing::debug!(micro_clusters = self.next_id, "initialized micro-clusters");
+                for id in 0..self.next_id {
+                    self.listeners.emit(ClusterEvent::Created {
+                        id,
+                        timestamp: self.clock,
+                    });
+                }
             }
         } else {
             // Step 2: Update micro-clusters
-            let min_cluster_idx = self
-                .micro_clusters
-                .iter()
-                .enumerate()
-                .min_by(|(_, a), (_, b)| {
-                    a.0.distance(&instance)
-                        .partial_cmp(&b.0.distance(&instance))
-                        .unwrap()
-                })
-                .unwrap()
-                .0;
+            let min_cluster_idx = self.nearest(instance, categorical);
             let max_boundary = match self.micro_clusters[min_cluster_idx].0.maximal_boundary() {
                 Some(boundary) => boundary,
                 None => {
                     let centroid = self.micro_clusters[min_cluster_idx].0.centroid();
                     self.micro_clusters
                         .iter()
-                        .map(|(a, _)| a.distance(&centroid))
-                        .min_by(|a, b| a.partial_cmp(b).unwrap())
+                        .map(|(a, _)| a.distance(centroid.as_slice(), &self.metric))
+                        .min_by(|a, b| a.total_cmp(b))
                         .unwrap()
                 }
             };
-            if self.micro_clusters[min_cluster_idx].0.distance(&instance) <= max_boundary {
-                self.micro_clusters[min_cluster_idx].0 += MicroCluster::new(instance, self.clock);
+            if self.micro_clusters[min_cluster_idx].0.mixed_distance(
+                instance,
+                categorical,
+                &self.metric,
+                self.categorical_weight,
+            ) <= max_boundary
+            {
+                self.micro_clusters[min_cluster_idx]
+                    .0
+                    .add_point(instance, categorical, self.clock);
+                self.index.insert(
+                    min_cluster_idx,
+                    &self.micro_clusters[min_cluster_idx].0.centroid(),
+                );
+                self.listeners.emit(ClusterEvent::Absorbed {
+                    id: self.micro_clusters[min_cluster_idx].1[0],
+                    timestamp: self.clock,
+                });
                 // println!("Added to cluster {}", self.micro_clusters[min_cluster_idx].1);
-            } else if self.micro_clusters.len() < Q {
+            } else if self.micro_clusters.len() < self.max_micro_clusters {
                 // println!("New cluster(Quota not reached)");
-                self.micro_clusters
-                    .push((MicroCluster::new(instance, self.clock), vec![self.next_id]));
-                self.next_id += 1;
+                self.index
+                    .insert(self.micro_clusters.len(), &instance.to_dense());
+                self.micro_clusters.push((
+                    MicroCluster::new(instance.to_dense(), categorical, self.clock, self.spread),
+                    vec![self.next_id],
+                ));
+                self.emit_created();
             } else {
                 let least_relevant = self
                     .micro_clusters
                     .iter()
                     .enumerate()
                     .map(|(i, (mc, _))| (i, mc.relevance_stamp()))
-                    .min_by(|(_, rel_a), (_, rel_b)| rel_a.partial_cmp(rel_b).unwrap())
+                    .min_by(|(_, rel_a), (_, rel_b)| rel_a.total_cmp(rel_b))
                     .unwrap();
                 if least_relevant.1 < THRESHOLD {
                     // Prune outliers
-                    self.micro_clusters[least_relevant.0] =
-                        (MicroCluster::new(instance, self.clock), vec![self.next_id]);
-                    // println!("Replaced least relevant with id {}", self.next_id);
-                    self.next_id += 1;
+                    self.listeners.emit(ClusterEvent::Pruned {
+                        id: self.micro_clusters[least_relevant.0].1[0],
+                        timestamp: self.clock,
+                    });
+                    self.micro_clusters[least_relevant.0] = (
+                        MicroCluster::new(
+                            instance.to_dense(),
+                            categorical,
+                            self.clock,
+                            self.spread,
+                        ),
+                        vec![self.next_id],
+                    );
+                    self.index.insert(least_relevant.0, &instance.to_dense());
+                    tracing::debug!(id = self.next_id, "replaced least relevant micro-cluster");
+                    self.emit_created();
+                    self.replacements += 1;
                 } else {
                     // Merge
-                    // println!("Merging");
-                    let closest_pair = self
-                        .micro_clusters
-                        .iter()
-                        .enumerate()
-                        .combinations(2)
-                        .map(|x| ((x[0].0, x[1].0), x[0].1 .0.distance(&x[1].1 .0.centroid())))
-                        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                        .unwrap()
-                        .0;
+                    let (first, second, _) = self.index.closest_pair().unwrap();
+                    let closest_pair = (first, second);
                     let merge_from = self.micro_clusters[closest_pair.1].clone();
+                    tracing::debug!(
+                        into = self.micro_clusters[closest_pair.0].1[0],
+                        from = merge_from.1[0],
+                        "merging closest micro-clusters"
+                    );
+                    self.listeners.emit(ClusterEvent::Merged {
+                        id: self.micro_clusters[closest_pair.0].1[0],
+                        absorbed: merge_from.1[0],
+                        timestamp: self.clock,
+                    });
                     self.micro_clusters[closest_pair.0].0 += merge_from.0;
                     self.micro_clusters[closest_pair.0].1.extend(merge_from.1);
-                    self.micro_clusters[closest_pair.1].0 = MicroCluster::new(instance, self.clock);
+                    self.micro_clusters[closest_pair.1].0 = MicroCluster::new(
+                        instance.to_dense(),
+                        categorical,
+                        self.clock,
+                        self.spread,
+                    );
                     self.micro_clusters[closest_pair.1].1 = vec![self.next_id];
-                    self.next_id += 1;
+                    self.index.insert(
+                        closest_pair.0,
+                        &self.micro_clusters[closest_pair.0].0.centroid(),
+                    );
+                    self.index.insert(closest_pair.1, &instance.to_dense());
+                    self.emit_created();
+                    self.merges += 1;
                 }
             }
         }
@@ -356,17 +618,27 @@ impl CluStream {
         self.clock += 1;
     }
 
-    fn offline_macro_clustering(&self, h: usize, k: usize) -> Vec<Vec<MicroCluster>> {
-        if h == 0 {
+    /// Hands out `next_id` to the micro-cluster that was just created
+    fn emit_created(&mut self) {
+        self.listeners.emit(ClusterEvent::Created {
+            id: self.next_id,
+            timestamp: self.clock,
+        });
+        self.next_id += 1;
+    }
+
+    /// Micro-clusters with their ids, grouped by macro-cluster
+    fn offline_macro_clustering(&self, h: usize, k: usize) -> Vec<Vec<(MicroCluster, usize)>> {
+        if h == 0 && !self.micro_clusters.is_empty() {
             let micro_clusters: Vec<Vec<f64>> = self
                 .micro_clusters
                 .iter()
                 .map(|(mc, _)| mc.centroid())
                 .collect();
-            let assignments = kmeans(micro_clusters, k, MAX_ITERATIONS);
-            let mut macro_clusters: Vec<Vec<MicroCluster>> = vec![Vec::new(); k];
-            for (i, (mc, _)) in self.micro_clusters.iter().enumerate() {
-                macro_clusters[assignments[i]].push(mc.clone());
+            let assignments = kmeans(micro_clusters, k, MAX_ITERATIONS, &self.metric);
+            let mut macro_clusters: Vec<Vec<(MicroCluster, usize)>> = vec![Vec::new(); k];
+            for (i, (mc, ids)) in self.micro_clusters.iter().enumerate() {
+                macro_clusters[assignments[i]].push((mc.clone(), ids[0]));
             }
             return macro_clusters;
         }
@@ -390,24 +662,73 @@ impl CluStream {
     }
 }
 
-impl super::DataStreamClusteringAlgorithm for CluStream {
-    fn insert(&mut self, data: Vec<f64>) {
-        self.insert(data);
+impl<D: Metric> super::DataStreamClusteringAlgorithm for CluStream<D> {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("clustream", clock = self.clock).entered();
+        self.insert(data)
+    }
+    fn insert_sparse(&mut self, data: &SparseVector) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("clustream", clock = self.clock).entered();
+        self.insert_sparse(data)
+    }
+    fn insert_mixed(&mut self, data: &MixedPoint) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("clustream", clock = self.clock).entered();
+        self.insert_mixed(data)
     }
-    fn clusters(&self) -> Vec<super::ClusteringElement> {
+    fn clusters(&self) -> Result<Vec<super::ClusteringElement>, ClusteringError> {
         let mut clusters = Vec::new();
         for (cluster_id, elements) in self.offline_macro_clustering(0, self.k).iter().enumerate() {
-            for element in elements {
+            for (element, id) in elements {
+                let (variance, covariance) = element.points.summary(self.spread);
                 clusters.push(super::ClusteringElement {
                     center: element.centroid(),
                     radius: element.maximal_boundary().unwrap_or(0.0),
                     cluster: cluster_id,
+                    categories: element.categories.modes(),
+                    variance,
+                    covariance,
+                    weight: element.n(),
+                    id: *id,
+                    created: element.created,
+                    last_update: element.last_update,
                 });
             }
         }
-        clusters
+        Ok(clusters)
     }
     fn name(&self) -> String {
         "CluStream".to_string()
     }
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.listeners.subscribe(listener);
+    }
+    fn stats(&self) -> super::AlgorithmStats {
+        super::AlgorithmStats {
+            micro_clusters: self.micro_clusters.len(),
+            total_weight: self.micro_clusters.iter().map(|(mc, _)| mc.n()).sum(),
+            memory_bytes: std::mem::size_of::<Self>()
+                + micro_clusters_memory_bytes(&self.micro_clusters)
+                + self.snapshot_vault.memory_bytes()
+                + self
+                    .initial_buffer
+                    .iter()
+                    .map(|p| p.capacity() * std::mem::size_of::<f64>())
+                    .sum::<usize>()
+                + self
+                    .initial_categories
+                    .iter()
+                    .flatten()
+                    .map(|value| std::mem::size_of::<String>() + value.capacity())
+                    .sum::<usize>(),
+            details: super::AlgorithmDetails::CluStream {
+                merges: self.merges,
+                replacements: self.replacements,
+                snapshots_per_order: self.snapshot_vault.occupancy(),
+            },
+            sampler: None,
+        }
+    }
 }
@@ -1,7 +1,15 @@
+use super::{
+    mixed::CategoricalSummary, moments::Moments, ClusterEvent, ClusterEventListener,
+    ClusteringError, Euclidean, Listeners, Metric, MixedPoint, NeighbourIndex, NeighbourSearch,
+    PointView, Schema, SparseVector, Spread,
+};
+
 // lambda > 0
 const LAMBDA: f64 = 0.2;
 const MI: f64 = 2.0;
 const EPSILON: f64 = 2.5;
+/// Default neighbourhood of the offline DBSCAN, in units of `EPSILON`
+const OFFLINE_EPSILON_FACTOR: f64 = 2.7;
 const BETA: f64 = 0.7;
 const INIT_N: usize = 100;
 const V: usize = 100;
@@ -16,24 +24,20 @@ fn decay_function(t: f64) -> f64 {
     2_f64.powf(-LAMBDA * t)
 }
 
-/// Euclidean distance between two points
-fn distance(a: &Point, b: &Point) -> f64 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| (x - y).powi(2))
-        .sum::<f64>()
-        .sqrt()
-}
-
-fn region_query(data: &[Point], point_idx: usize, eps: f64) -> Vec<usize> {
+fn region_query<D: Metric>(data: &[Point], point_idx: usize, eps: f64, metric: &D) -> Vec<usize> {
     data.iter()
         .enumerate()
-        .filter(|(idx, point)| distance(&data[point_idx], point) <= eps && *idx != point_idx)
+        .filter(|(idx, point)| metric.distance(&data[point_idx], point) <= eps && *idx != point_idx)
         .map(|(idx, _)| idx)
         .collect()
 }
 
-fn initialize_p_micro_clusters(data: &[Vec<f64>], eps: f64, beta_mu: usize) -> Vec<Vec<usize>> {
+fn initialize_p_micro_clusters<D: Metric>(
+    data: &[Vec<f64>],
+    eps: f64,
+    beta_mu: usize,
+    metric: &D,
+) -> Vec<Vec<usize>> {
     let mut visited = vec![false; data.len()];
     let mut clusters = Vec::new();
 
@@ -42,7 +46,7 @@ fn initialize_p_micro_clusters(data: &[Vec<f64>], eps: f64, beta_mu: usize) -> V
             continue;
         }
 
-        let neighbors = region_query(data, point_idx, eps);
+        let neighbors = region_query(data, point_idx, eps, metric);
 
         // Only form a micro-cluster if the density is above beta_mu
         if neighbors.len() + 1 >= beta_mu {
@@ -65,346 +69,840 @@ fn initialize_p_micro_clusters(data: &[Vec<f64>], eps: f64, beta_mu: usize) -> V
 
 #[derive(Debug, Clone)]
 pub struct PotentialMicroCluster {
-    weight: f64,
-    cf1: Point,
-    cf2: f64,
+    id: usize,
+    /// Decayed weight, mean and M2 of the points, in place of CF1/CF2
+    moments: Moments,
     last_update: usize,
+    /// Clock at creation (as an outlier micro-cluster) and at the newest point, unlike
+    /// `last_update`, which decay moves on without new points
+    t_0: usize,
+    last_point: usize,
+    categories: CategoricalSummary,
 }
 
 impl PotentialMicroCluster {
-    fn new(points: Vec<Point>) -> PotentialMicroCluster {
-        let weight = points.len() as f64;
-        let cf1 = points.iter().fold(vec![0_f64; points[0].len()], |acc, p| {
-            acc.iter().zip(p.iter()).map(|(x, y)| x + y).collect()
-        });
-        let cf2 = points
-            .iter()
-            .map(|p| p.iter().map(|x| x.powi(2)).sum::<f64>())
-            .sum::<f64>();
+    fn new(
+        id: usize,
+        points: Vec<Point>,
+        categorical: &[Vec<String>],
+        spread: Spread,
+    ) -> PotentialMicroCluster {
+        let mut moments = Moments::default();
+        if spread == Spread::Covariance {
+            moments = moments.with_covariance();
+        }
+        points.iter().for_each(|p| moments.add(p.as_slice(), 1.));
         let last_update = 0;
+        let mut categories = CategoricalSummary::default();
+        categorical
+            .iter()
+            .for_each(|values| categories.add(values, 1.));
         PotentialMicroCluster {
-            weight,
-            cf1,
-            cf2,
+            id,
+            moments,
             last_update,
+            t_0: last_update,
+            last_point: last_update,
+            categories,
         }
     }
 
     fn from_outlier(outlier: OutlierMicroCluster) -> PotentialMicroCluster {
         PotentialMicroCluster {
-            weight: outlier.weight,
-            cf1: outlier.cf1,
-            cf2: outlier.cf2,
+            id: outlier.id,
+            moments: outlier.moments,
             last_update: outlier.last_update,
+            t_0: outlier.t_0,
+            last_point: outlier.last_point,
+            categories: outlier.categories,
         }
     }
 
     pub fn center(&self) -> Point {
-        self.cf1.iter().map(|p_x| p_x / self.weight).collect()
+        self.moments.mean().to_vec()
     }
 
     pub fn radius(&self) -> f64 {
-        f64::sqrt(self.cf2 / self.weight - self.center().iter().map(|x| x.powi(2)).sum::<f64>())
+        self.moments.total_variance().sqrt()
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.moments.weight()
+    }
+
+    /// Weight faded to `timestamp`, without updating the micro-cluster
+    fn weight_at(&self, timestamp: usize) -> f64 {
+        self.weight() * decay_function((timestamp - self.last_update) as f64)
     }
 
     fn update(&mut self, timestamp: usize) {
         let decay = decay_function((timestamp - self.last_update) as f64);
-        self.weight *= decay;
-        self.cf1 = self.cf1.iter().map(|x| x * decay).collect();
-        self.cf2 *= decay;
+        self.moments.scale(decay);
+        self.categories.scale(decay);
         self.last_update = timestamp;
     }
 
-    fn add_point(&mut self, point: Point) {
-        self.weight += 1_f64;
-        self.cf1 = self
-            .cf1
-            .iter()
-            .zip(point.iter())
-            .map(|(x, y)| x + y)
-            .collect();
-        self.cf2 += point.iter().map(|x| x.powi(2)).sum::<f64>();
+    /// Adds a point at `last_update`, so `update` has to come first
+    fn add_point<P: PointView + ?Sized>(&mut self, point: &P, categorical: &[String]) {
+        self.moments.add(point, 1.);
+        self.categories.add(categorical, 1.);
+        self.last_point = self.last_update;
     }
 
-    fn get_after_merge(&self, point: Point) -> Self {
+    fn get_after_merge<P: PointView + ?Sized>(&self, point: &P, categorical: &[String]) -> Self {
         let mut potential = self.clone();
-        potential.add_point(point);
+        potential.add_point(point, categorical);
         potential
     }
 }
 
 #[derive(Debug, Clone)]
 struct OutlierMicroCluster {
-    cf1: Point,
-    cf2: f64,
-    weight: f64,
+    id: usize,
+    moments: Moments,
     t_0: usize,
     last_update: usize,
+    last_point: usize,
+    categories: CategoricalSummary,
 }
 
 impl OutlierMicroCluster {
-    fn new(point: Point, timestamp: usize) -> OutlierMicroCluster {
+    fn new(
+        id: usize,
+        point: Point,
+        categorical: &[String],
+        timestamp: usize,
+        spread: Spread,
+    ) -> OutlierMicroCluster {
+        let mut categories = CategoricalSummary::default();
+        categories.add(categorical, 1.);
+        let mut moments = Moments::from_point(&point);
+        if spread == Spread::Covariance {
+            moments = moments.with_covariance();
+        }
         OutlierMicroCluster {
-            cf1: point.clone(),
-            cf2: point.iter().map(|x| x.powi(2)).sum::<f64>(),
-            weight: 1_f64,
+            id,
+            moments,
             t_0: timestamp,
             last_update: timestamp,
+            last_point: timestamp,
+            categories,
         }
     }
 
     fn center(&self) -> Point {
-        self.cf1.iter().map(|p_x| p_x / self.weight).collect()
+        self.moments.mean().to_vec()
     }
 
     fn radius(&self) -> f64 {
-        f64::sqrt(self.cf2 / self.weight - self.center().iter().map(|x| x.powi(2)).sum::<f64>())
+        self.moments.total_variance().sqrt()
+    }
+
+    fn weight(&self) -> f64 {
+        self.moments.weight()
+    }
+
+    fn weight_at(&self, timestamp: usize) -> f64 {
+        self.weight() * decay_function((timestamp - self.last_update) as f64)
     }
 
     fn update(&mut self, timestamp: usize) {
         let decay = decay_function((timestamp - self.last_update) as f64);
-        self.weight *= decay;
-        self.cf1 = self.cf1.iter().map(|x| x * decay).collect();
-        self.cf2 *= decay;
+        self.moments.scale(decay);
+        self.categories.scale(decay);
         self.last_update = timestamp;
     }
 
-    fn add_point(&mut self, point: Point) {
-        self.weight += 1_f64;
-        self.cf1 = self
-            .cf1
-            .iter()
-            .zip(point.iter())
-            .map(|(x, y)| x + y)
-            .collect();
-        self.cf2 += point.iter().map(|x| x.powi(2)).sum::<f64>();
+    /// Adds a point at `last_update`, so `update` has to come first
+    fn add_point<P: PointView + ?Sized>(&mut self, point: &P, categorical: &[String]) {
+        self.moments.add(point, 1.);
+        self.categories.add(categorical, 1.);
+        self.last_point = self.last_update;
     }
 
-    fn get_after_merge(&self, point: Point) -> Self {
+    fn get_after_merge<P: PointView + ?Sized>(&self, point: &P, categorical: &[String]) -> Self {
         let mut potential = self.clone();
-        potential.add_point(point);
+        potential.add_point(point, categorical);
         potential
     }
 }
 
 #[derive(Debug)]
-pub struct Denstream {
+pub struct Denstream<D = Euclidean> {
     initial_buffer: Vec<Point>,
+    /// Categorical attributes of the buffered points
+    initial_categories: Vec<Vec<String>>,
     initialised: bool,
     potential_micro_clusters: Vec<PotentialMicroCluster>,
     outlier_micro_clusters: Vec<OutlierMicroCluster>,
     t_p: usize,
     clock: usize,
     small_clock: usize,
+    potential_pruned: usize,
+    outliers_pruned: usize,
+    promotions: usize,
+    /// Shared by potential and outlier micro-clusters, a promotion keeps the id
+    next_id: usize,
+    listeners: Listeners,
+    schema: Schema,
+    metric: D,
+    categorical_weight: f64,
+    spread: Spread,
+    search: NeighbourSearch,
+    /// Centers of the potential and outlier micro-clusters under their positions
+    potential_index: Box<dyn NeighbourIndex>,
+    outlier_index: Box<dyn NeighbourIndex>,
+    /// Epsilon and mu of the offline DBSCAN over potential micro-clusters
+    offline_epsilon: f64,
+    offline_mu: f64,
 }
 
 impl Denstream {
     pub fn new() -> Denstream {
         Denstream {
             initial_buffer: Vec::new(),
+            initial_categories: Vec::new(),
             initialised: false,
             potential_micro_clusters: Vec::new(),
             outlier_micro_clusters: Vec::new(),
             t_p: calculate_t_p(),
             clock: 0,
             small_clock: 0,
+            potential_pruned: 0,
+            outliers_pruned: 0,
+            promotions: 0,
+            next_id: 0,
+            listeners: Listeners::default(),
+            schema: Schema::new(),
+            metric: Euclidean,
+            categorical_weight: 1.,
+            spread: Spread::Radius,
+            search: NeighbourSearch::Linear,
+            potential_index: NeighbourSearch::Linear.build(Euclidean),
+            outlier_index: NeighbourSearch::Linear.build(Euclidean),
+            offline_epsilon: EPSILON * OFFLINE_EPSILON_FACTOR,
+            offline_mu: MI,
         }
     }
+}
 
-    fn merge(&mut self, data: Point) {
-        // Try to merge with potential micro-clusters
-        self.potential_micro_clusters
-            .iter_mut()
-            .for_each(|pcm| pcm.update(self.clock));
-        if let Some((idx, _)) = self
-            .potential_micro_clusters
-            .iter()
+impl<D: Metric> Denstream<D> {
+    /// Compares points and micro-cluster centers with `metric` instead of the Euclidean
+    /// distance. Micro-cluster radii are still bounded by `EPSILON`.
+    pub fn with_metric<E: Metric>(self, metric: E) -> Denstream<E> {
+        let mut denstream = Denstream {
+            initial_buffer: self.initial_buffer,
+            initial_categories: self.initial_categories,
+            initialised: self.initialised,
+            potential_micro_clusters: self.potential_micro_clusters,
+            outlier_micro_clusters: self.outlier_micro_clusters,
+            t_p: self.t_p,
+            clock: self.clock,
+            small_clock: self.small_clock,
+            potential_pruned: self.potential_pruned,
+            outliers_pruned: self.outliers_pruned,
+            promotions: self.promotions,
+            next_id: self.next_id,
+            listeners: self.listeners,
+            schema: self.schema,
+            potential_index: self.search.build(metric.clone()),
+            outlier_index: self.search.build(metric.clone()),
+            metric,
+            categorical_weight: self.categorical_weight,
+            spread: self.spread,
+            search: self.search,
+            offline_epsilon: self.offline_epsilon,
+            offline_mu: self.offline_mu,
+        };
+        denstream.reindex();
+        denstream
+    }
+
+    /// Finds the nearest potential and outlier micro-clusters through `search` instead of
+    /// comparing every one. Records with categorical attributes are still compared with
+    /// every micro-cluster, as the index only knows the numeric part.
+    pub fn with_search(mut self, search: NeighbourSearch) -> Self {
+        self.search = search;
+        self.potential_index = search.build(self.metric.clone());
+        self.outlier_index = search.build(self.metric.clone());
+        self.reindex();
+        self
+    }
+
+    fn reindex(&mut self) {
+        self.potential_index.clear();
+        for (i, pmc) in self.potential_micro_clusters.iter().enumerate() {
+            self.potential_index.insert(i, &pmc.center());
+        }
+        self.outlier_index.clear();
+        for (i, omc) in self.outlier_micro_clusters.iter().enumerate() {
+            self.outlier_index.insert(i, &omc.center());
+        }
+    }
+
+    /// Position of the micro-cluster nearest to the record, by the mixed distance
+    fn nearest<'a, P: PointView + ?Sized>(
+        &self,
+        index: &dyn NeighbourIndex,
+        micro_clusters: impl Iterator<Item = (Point, &'a CategoricalSummary)>,
+        data: &P,
+        categorical: &[String],
+    ) -> Option<usize> {
+        if categorical.is_empty() || self.categorical_weight == 0. {
+            return index.nearest(&data.to_dense()).map(|(i, _)| i);
+        }
+        micro_clusters
             .enumerate()
-            .map(|(i, cluster)| (i, distance(&cluster.center(), &data)))
-            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-        {
+            .map(|(i, (center, categories))| {
+                let distance = data.distance(&self.metric, &center)
+                    + self.categorical_weight * categories.dissimilarity(categorical);
+                (i, distance)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Scales the categorical mismatch of mixed records (in `[0, 1]`) against the numeric
+    /// distance when looking for the nearest micro-cluster. Defaults to 1.
+    pub fn with_categorical_weight(mut self, weight: f64) -> Result<Self, ClusteringError> {
+        if !(weight.is_finite() && weight >= 0.) {
+            return Err(ClusteringError::invalid_parameter(
+                "categorical_weight",
+                "must be finite and non-negative",
+            ));
+        }
+        self.categorical_weight = weight;
+        Ok(self)
+    }
+
+    /// Reports the variance (and covariance) of every micro-cluster in `clusters()`, see
+    /// `Spread`
+    pub fn with_spread(mut self, spread: Spread) -> Result<Self, ClusteringError> {
+        if self.small_clock > 0 || !self.initial_buffer.is_empty() {
+            return Err(ClusteringError::invalid_parameter(
+                "spread",
+                "has to be set before the first insert",
+            ));
+        }
+        self.spread = spread;
+        Ok(self)
+    }
+
+    /// Parameters of the offline DBSCAN that groups potential micro-clusters into the
+    /// reported clusters: micro-clusters with a (faded) weight of at least `mu` are cores,
+    /// and micro-clusters within `2 * epsilon` of a core join its cluster. Defaults to
+    /// `2.7 * EPSILON` and `MI`.
+    pub fn with_offline_clustering(
+        mut self,
+        epsilon: f64,
+        mu: f64,
+    ) -> Result<Self, ClusteringError> {
+        if !(epsilon.is_finite() && epsilon > 0.) {
+            return Err(ClusteringError::invalid_parameter(
+                "epsilon",
+                "must be positive",
+            ));
+        }
+        if !(mu.is_finite() && mu >= 0.) {
+            return Err(ClusteringError::invalid_parameter(
+                "mu",
+                "must be finite and non-negative",
+            ));
+        }
+        self.offline_epsilon = epsilon;
+        self.offline_mu = mu;
+        Ok(self)
+    }
+
+    /// Fixes the accepted dimension (and feature names), see `Schema` for when it fails
+    pub fn with_schema(mut self, schema: Schema) -> Result<Self, ClusteringError> {
+        self.schema.replace(schema)?;
+        Ok(self)
+    }
+
+    fn new_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.listeners.emit(ClusterEvent::Created {
+            id,
+            timestamp: self.clock,
+        });
+        id
+    }
+
+    /// Decay is applied lazily: it leaves centers where they are, so only the micro-cluster
+    /// a point is tried on has to be brought up to date
+    fn merge<P: PointView + ?Sized>(&mut self, data: &P, categorical: &[String]) {
+        // Try to merge with potential micro-clusters
+        if let Some(idx) = self.nearest(
+            self.potential_index.as_ref(),
+            self.potential_micro_clusters
+                .iter()
+                .map(|pmc| (pmc.center(), &pmc.categories)),
+            data,
+            categorical,
+        ) {
+            self.potential_micro_clusters[idx].update(self.clock);
             let after_merge_potential =
-                self.potential_micro_clusters[idx].get_after_merge(data.clone());
+                self.potential_micro_clusters[idx].get_after_merge(data, categorical);
             if after_merge_potential.radius() <= EPSILON {
+                self.listeners.emit(ClusterEvent::Absorbed {
+                    id: after_merge_potential.id,
+                    timestamp: self.clock,
+                });
+                self.potential_index
+                    .insert(idx, &after_merge_potential.center());
                 self.potential_micro_clusters[idx] = after_merge_potential;
                 return;
             }
         }
         // else: try to merge with outlier micro-clusters
-        self.outlier_micro_clusters
-            .iter_mut()
-            .for_each(|omc| omc.update(self.clock));
-
-        if let Some((idx, _)) = self
-            .outlier_micro_clusters
-            .iter()
-            .enumerate()
-            .map(|(i, cluster)| (i, distance(&cluster.center(), &data)))
-            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-        {
+        if let Some(idx) = self.nearest(
+            self.outlier_index.as_ref(),
+            self.outlier_micro_clusters
+                .iter()
+                .map(|omc| (omc.center(), &omc.categories)),
+            data,
+            categorical,
+        ) {
+            self.outlier_micro_clusters[idx].update(self.clock);
             let after_merge_outlier =
-                self.outlier_micro_clusters[idx].get_after_merge(data.clone());
+                self.outlier_micro_clusters[idx].get_after_merge(data, categorical);
             if after_merge_outlier.radius() <= EPSILON {
-                if after_merge_outlier.weight > BETA * MI {
-                    self.outlier_micro_clusters.remove(idx);
+                self.listeners.emit(ClusterEvent::Absorbed {
+                    id: after_merge_outlier.id,
+                    timestamp: self.clock,
+                });
+                if after_merge_outlier.weight() > BETA * MI {
+                    tracing::debug!(
+                        id = after_merge_outlier.id,
+                        "promoting outlier micro-cluster"
+                    );
+                    self.listeners.emit(ClusterEvent::Promoted {
+                        id: after_merge_outlier.id,
+                        timestamp: self.clock,
+                    });
+                    self.remove_outlier(idx);
+                    self.potential_index.insert(
+                        self.potential_micro_clusters.len(),
+                        &after_merge_outlier.center(),
+                    );
                     self.potential_micro_clusters
                         .push(PotentialMicroCluster::from_outlier(after_merge_outlier));
+                    self.promotions += 1;
                 } else {
+                    self.outlier_index
+                        .insert(idx, &after_merge_outlier.center());
                     self.outlier_micro_clusters[idx] = after_merge_outlier;
                 }
                 return;
             }
         }
         // else: create new outlier micro-cluster
-        self.outlier_micro_clusters
-            .push(OutlierMicroCluster::new(data, self.clock));
+        let id = self.new_id();
+        self.outlier_index
+            .insert(self.outlier_micro_clusters.len(), &data.to_dense());
+        self.outlier_micro_clusters.push(OutlierMicroCluster::new(
+            id,
+            data.to_dense(),
+            categorical,
+            self.clock,
+            self.spread,
+        ));
     }
 
-    pub fn insert(&mut self, data: Point) {
+    /// Removes the outlier micro-cluster at `idx`, moving the last one into its place
+    fn remove_outlier(&mut self, idx: usize) -> OutlierMicroCluster {
+        let removed = self.outlier_micro_clusters.swap_remove(idx);
+        self.outlier_index.remove(self.outlier_micro_clusters.len());
+        if let Some(moved) = self.outlier_micro_clusters.get(idx) {
+            self.outlier_index.insert(idx, &moved.center());
+        }
+        removed
+    }
+
+    pub fn insert(&mut self, data: Point) -> Result<(), ClusteringError> {
+        self.schema.validate(&data)?;
+        self.insert_point(data.as_slice(), &[]);
+        Ok(())
+    }
+
+    pub fn insert_sparse(&mut self, data: &SparseVector) -> Result<(), ClusteringError> {
+        self.schema.validate_sparse(data)?;
+        self.insert_point(data, &[]);
+        Ok(())
+    }
+
+    /// Micro-clusters keep a decayed frequency histogram of every categorical attribute,
+    /// and the closest micro-cluster is picked by the mixed distance (see
+    /// `with_categorical_weight`). The radius bound only applies to the numeric part.
+    pub fn insert_mixed(&mut self, data: &MixedPoint) -> Result<(), ClusteringError> {
+        self.schema.validate_mixed(data)?;
+        self.insert_point(data.numeric(), data.categorical());
+        Ok(())
+    }
+
+    fn insert_point<P: PointView + ?Sized>(&mut self, data: &P, categorical: &[String]) {
         if !self.initialised {
-            self.initial_buffer.push(data);
+            self.initial_buffer.push(data.to_dense());
+            self.initial_categories.push(categorical.to_vec());
             if self.initial_buffer.len() >= INIT_N {
                 self.initialised = true;
                 let mappings = initialize_p_micro_clusters(
                     &self.initial_buffer,
                     EPSILON,
                     (BETA * MI) as usize,
+                    &self.metric,
                 );
                 for mapping in mappings {
                     if !mapping.is_empty() {
+                        let id = self.new_id();
                         self.potential_micro_clusters
                             .push(PotentialMicroCluster::new(
+                                id,
                                 mapping
                                     .iter()
                                     .map(|&idx| self.initial_buffer[idx].clone())
                                     .collect(),
+                                &mapping
+                                    .iter()
+                                    .map(|&idx| self.initial_categories[idx].clone())
+                                    .collect::<Vec<_>>(),
+                                self.spread,
                             ));
                     }
                 }
+                self.reindex();
                 self.initial_buffer.clear();
+                self.initial_categories.clear();
+                tracing::debug!(
+                    micro_clusters = self.potential_micro_clusters.len(),
+                    "initialized potential micro-clusters"
+                );
             }
             return;
         }
         // 1. Merge data point with potential micro-clusters
-        self.merge(data);
-        if self.clock % self.t_p == 0 {
+        self.merge(data, categorical);
+        if self.clock.is_multiple_of(self.t_p) {
             // Prune old potential micro-clusters
+            let potential_count = self.potential_micro_clusters.len();
+            let clock = self.clock;
             self.potential_micro_clusters
-                .retain(|cmp| cmp.weight >= BETA * MI);
+                .iter_mut()
+                .for_each(|pmc| pmc.update(clock));
+            self.potential_micro_clusters.retain(|cmp| {
+                let keep = cmp.weight() >= BETA * MI;
+                if !keep {
+                    self.listeners.emit(ClusterEvent::Pruned {
+                        id: cmp.id,
+                        timestamp: self.clock,
+                    });
+                }
+                keep
+            });
+            self.potential_pruned += potential_count - self.potential_micro_clusters.len();
+            if self.potential_micro_clusters.len() < potential_count {
+                self.potential_index.clear();
+                for (i, pmc) in self.potential_micro_clusters.iter().enumerate() {
+                    self.potential_index.insert(i, &pmc.center());
+                }
+            }
             // Prune old outlier micro-clusters
             let mut indexes_to_remove: Vec<usize> = Vec::new();
+            self.outlier_micro_clusters
+                .iter_mut()
+                .for_each(|omc| omc.update(clock));
             for (idx, omp) in self.outlier_micro_clusters.iter().enumerate() {
                 let xi = (2_f64
                     .powf(-LAMBDA * (self.clock as f64 - omp.t_0 as f64 + self.t_p as f64))
                     - 1.)
                     / (2_f64.powf(-LAMBDA * self.t_p as f64) - 1.);
-                if omp.weight * xi < BETA * MI {
+                if omp.weight() * xi < BETA * MI {
                     indexes_to_remove.push(idx);
                 }
             }
+            self.outliers_pruned += indexes_to_remove.len();
+            tracing::debug!(
+                potential = potential_count - self.potential_micro_clusters.len(),
+                outliers = indexes_to_remove.len(),
+                "pruned micro-clusters"
+            );
             indexes_to_remove.sort_unstable_by(|a, b| b.cmp(a));
             indexes_to_remove.iter().for_each(|idx| {
-                self.outlier_micro_clusters.remove(*idx);
+                let pruned = self.remove_outlier(*idx);
+                self.listeners.emit(ClusterEvent::Pruned {
+                    id: pruned.id,
+                    timestamp: self.clock,
+                });
             });
         }
-        if self.small_clock % V == 0 {
+        if self.small_clock.is_multiple_of(V) {
             self.clock += 1;
         }
         self.small_clock += 1;
     }
 
-    fn is_directly_density_reachable(
-        &self,
-        cp: &PotentialMicroCluster,
-        cq: &PotentialMicroCluster,
-        epsilon: f64,
-        mu: f64,
-    ) -> bool {
-        cq.weight >= mu && distance(&cp.center(), &cq.center()) <= 2.0 * epsilon
-    }
-
-    fn is_density_reachable(
-        &self,
-        cp: &PotentialMicroCluster,
-        cq: &PotentialMicroCluster,
-        epsilon: f64,
-        mu: f64,
-    ) -> bool {
-        let mut visited = Vec::new();
-        let mut to_visit = vec![cq];
-
-        while let Some(current) = to_visit.pop() {
-            if visited.iter().any(|v| v == &current.center()) {
-                continue;
-            }
-            visited.push(current.center().clone());
-
-            if self.is_directly_density_reachable(cp, current, epsilon, mu) {
-                return true;
-            }
-
-            for neighbor in &self.potential_micro_clusters {
-                if self.is_directly_density_reachable(current, neighbor, epsilon, mu) {
-                    to_visit.push(neighbor);
-                }
-            }
-        }
-
-        false
-    }
-
+    /// DBSCAN over the potential micro-clusters as weighted points, as in the paper's
+    /// offline phase: a cluster grows from a core micro-cluster through every micro-cluster
+    /// within `2 * epsilon` of one of its cores. Micro-clusters no core reaches are reported
+    /// as clusters of their own. Neighbours come from the search index, so with
+    /// `NeighbourSearch::Graph` a few may be missed.
     pub fn clustering_request(&self) -> Vec<Vec<PotentialMicroCluster>> {
+        // Micro-clusters are only faded when a point is tried on them. Decay leaves centers
+        // where they are, so the index still holds them.
+        let micro_clusters: Vec<PotentialMicroCluster> = self
+            .potential_micro_clusters
+            .iter()
+            .map(|pmc| {
+                let mut pmc = pmc.clone();
+                pmc.update(self.clock);
+                pmc
+            })
+            .collect();
+        let is_core: Vec<bool> = micro_clusters
+            .iter()
+            .map(|pmc| pmc.weight() >= self.offline_mu)
+            .collect();
+        let mut visited = vec![false; micro_clusters.len()];
         let mut clusters = Vec::new();
-        let mut visited = Vec::new();
 
-        for cp in &self.potential_micro_clusters {
-            if visited.iter().any(|v| v == &cp.center()) {
+        for start in (0..micro_clusters.len()).filter(|&i| is_core[i]) {
+            if visited[start] {
                 continue;
             }
-
+            visited[start] = true;
             let mut cluster = Vec::new();
-            let mut to_visit = vec![cp];
-
+            let mut to_visit = vec![start];
             while let Some(current) = to_visit.pop() {
-                if visited.iter().any(|v| v == &current.center()) {
+                cluster.push(micro_clusters[current].clone());
+                // Only cores carry the cluster further
+                if !is_core[current] {
                     continue;
                 }
-                visited.push(current.center().clone());
-                cluster.push(current.clone());
-
-                for neighbor in &self.potential_micro_clusters {
-                    if self.is_density_reachable(current, neighbor, EPSILON * 2.7, MI) {
-                        to_visit.push(neighbor);
+                for (neighbour, _) in self
+                    .potential_index
+                    .within(&micro_clusters[current].center(), 2. * self.offline_epsilon)
+                {
+                    if !visited[neighbour] {
+                        visited[neighbour] = true;
+                        to_visit.push(neighbour);
                     }
                 }
             }
-
             clusters.push(cluster);
         }
+        clusters.extend(
+            micro_clusters
+                .into_iter()
+                .zip(visited)
+                .filter(|(_, visited)| !visited)
+                .map(|(pmc, _)| vec![pmc]),
+        );
 
         clusters
     }
 }
 
-impl super::DataStreamClusteringAlgorithm for Denstream {
-    fn insert(&mut self, data: Point) {
-        self.insert(data);
+impl Default for Denstream {
+    fn default() -> Self {
+        Self::new()
     }
-    fn clusters(&self) -> Vec<super::ClusteringElement> {
+}
+
+impl<D: Metric> super::DataStreamClusteringAlgorithm for Denstream<D> {
+    fn insert(&mut self, data: Point) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("denstream", clock = self.clock).entered();
+        self.insert(data)
+    }
+    fn insert_sparse(&mut self, data: &SparseVector) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("denstream", clock = self.clock).entered();
+        self.insert_sparse(data)
+    }
+    fn insert_mixed(&mut self, data: &MixedPoint) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("denstream", clock = self.clock).entered();
+        self.insert_mixed(data)
+    }
+    fn clusters(&self) -> Result<Vec<super::ClusteringElement>, ClusteringError> {
         let clusters = self.clustering_request();
-        clusters
+        Ok(clusters
             .iter()
             .enumerate()
             .flat_map(|(idx, cluster)| {
-                cluster.iter().map(move |c| super::ClusteringElement {
-                    center: c.center(),
-                    radius: c.radius(),
-                    cluster: idx,
+                cluster.iter().map(move |c| {
+                    let (variance, covariance) = c.moments.summary(self.spread);
+                    super::ClusteringElement {
+                        center: c.center(),
+                        radius: c.radius(),
+                        cluster: idx,
+                        categories: c.categories.modes(),
+                        variance,
+                        covariance,
+                        weight: c.weight(),
+                        id: c.id,
+                        created: c.t_0,
+                        last_update: c.last_point,
+                    }
                 })
             })
-            .collect()
+            .collect())
     }
     fn name(&self) -> String {
         "DenStream".to_string()
     }
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.listeners.subscribe(listener);
+    }
+    fn stats(&self) -> super::AlgorithmStats {
+        let point_bytes = |p: &Point| p.capacity() * std::mem::size_of::<f64>();
+        super::AlgorithmStats {
+            micro_clusters: self.potential_micro_clusters.len() + self.outlier_micro_clusters.len(),
+            total_weight: self
+                .potential_micro_clusters
+                .iter()
+                .map(|p| p.weight_at(self.clock))
+                .chain(
+                    self.outlier_micro_clusters
+                        .iter()
+                        .map(|o| o.weight_at(self.clock)),
+                )
+                .sum(),
+            memory_bytes: std::mem::size_of::<Self>()
+                + self
+                    .potential_micro_clusters
+                    .iter()
+                    .map(|p| {
+                        std::mem::size_of::<PotentialMicroCluster>()
+                            + p.moments.memory_bytes()
+                            + p.categories.memory_bytes()
+                    })
+                    .sum::<usize>()
+                + self
+                    .outlier_micro_clusters
+                    .iter()
+                    .map(|o| {
+                        std::mem::size_of::<OutlierMicroCluster>()
+                            + o.moments.memory_bytes()
+                            + o.categories.memory_bytes()
+                    })
+                    .sum::<usize>()
+                + self.initial_buffer.iter().map(point_bytes).sum::<usize>()
+                + self
+                    .initial_categories
+                    .iter()
+                    .flatten()
+                    .map(|value| std::mem::size_of::<String>() + value.capacity())
+                    .sum::<usize>(),
+            details: super::AlgorithmDetails::DenStream {
+                potential_micro_clusters: self.potential_micro_clusters.len(),
+                outlier_micro_clusters: self.outlier_micro_clusters.len(),
+                potential_pruned: self.potential_pruned,
+                outliers_pruned: self.outliers_pruned,
+                promotions: self.promotions,
+            },
+            sampler: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DenStream holding one potential micro-cluster per group of points
+    fn with_micro_clusters(denstream: Denstream, groups: &[Vec<Point>]) -> Denstream {
+        let mut denstream = denstream.with_search(NeighbourSearch::KdTree);
+        denstream.potential_micro_clusters = groups
+            .iter()
+            .enumerate()
+            .map(|(id, points)| {
+                let categorical = vec![Vec::new(); points.len()];
+                PotentialMicroCluster::new(id, points.clone(), &categorical, Spread::Radius)
+            })
+            .collect();
+        denstream.reindex();
+        denstream
+    }
+
+    fn ids(clusters: &[Vec<PotentialMicroCluster>]) -> Vec<Vec<usize>> {
+        clusters
+            .iter()
+            .map(|cluster| {
+                let mut ids: Vec<usize> = cluster.iter().map(|pmc| pmc.id).collect();
+                ids.sort_unstable();
+                ids
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_offline_shared_centers() {
+        // The first two share the center (0, 0)
+        let denstream = with_micro_clusters(
+            Denstream::new(),
+            &[
+                vec![vec![-1., 0.], vec![1., 0.]],
+                vec![vec![0., -1.], vec![0., 1.]],
+                vec![vec![100., 0.], vec![100., 0.]],
+            ],
+        );
+        assert_eq!(
+            ids(&denstream.clustering_request()),
+            vec![vec![0, 1], vec![2]]
+        );
+    }
+
+    #[test]
+    fn test_offline_parameters() {
+        // Cores at 0 and 20 (two points each), a single point at 10 between them
+        let groups = [
+            vec![vec![0.], vec![0.]],
+            vec![vec![10.]],
+            vec![vec![20.], vec![20.]],
+        ];
+        let denstream = with_micro_clusters(
+            Denstream::new().with_offline_clustering(6., 2.).unwrap(),
+            &groups,
+        );
+        // The single point is no core: reached from the first core it joins that
+        // core's cluster, but it does not link the two cores
+        assert_eq!(
+            ids(&denstream.clustering_request()),
+            vec![vec![0, 1], vec![2]]
+        );
+
+        let denstream = with_micro_clusters(
+            Denstream::new().with_offline_clustering(6., 1.).unwrap(),
+            &groups,
+        );
+        assert_eq!(ids(&denstream.clustering_request()), vec![vec![0, 1, 2]]);
+
+        let denstream = with_micro_clusters(
+            Denstream::new().with_offline_clustering(4., 2.).unwrap(),
+            &groups,
+        );
+        assert_eq!(
+            ids(&denstream.clustering_request()),
+            vec![vec![0], vec![2], vec![1]]
+        );
+
+        // Without cores every micro-cluster is on its own
+        let denstream = with_micro_clusters(
+            Denstream::new().with_offline_clustering(100., 3.).unwrap(),
+            &groups,
+        );
+        assert_eq!(
+            ids(&denstream.clustering_request()),
+            vec![vec![0], vec![1], vec![2]]
+        );
+
+        assert!(Denstream::new().with_offline_clustering(0., 2.).is_err());
+        assert!(Denstream::new().with_offline_clustering(1., -1.).is_err());
+        assert!(Denstream::new()
+            .with_offline_clustering(f64::NAN, 2.)
+            .is_err());
+    }
 }
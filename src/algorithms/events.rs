@@ -0,0 +1,187 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Micro-cluster lifecycle changes. Ids are the algorithm's own micro-cluster ids
+/// (CF-tree node ids for `Split`), timestamps are in the algorithm's clock units.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event")]
+pub enum ClusterEvent {
+    Created {
+        id: usize,
+        timestamp: usize,
+    },
+    /// A point was absorbed by an existing micro-cluster
+    Absorbed {
+        id: usize,
+        timestamp: usize,
+    },
+    /// Micro-cluster `absorbed` was merged into `id`
+    Merged {
+        id: usize,
+        absorbed: usize,
+        timestamp: usize,
+    },
+    /// An outlier micro-cluster became a potential one
+    Promoted {
+        id: usize,
+        timestamp: usize,
+    },
+    Pruned {
+        id: usize,
+        timestamp: usize,
+    },
+    /// CF-tree node `node` was split, moving part of its entries to the new `sibling`
+    Split {
+        node: usize,
+        sibling: usize,
+        timestamp: usize,
+    },
+}
+
+impl ClusterEvent {
+    pub fn timestamp(&self) -> usize {
+        match self {
+            ClusterEvent::Created { timestamp, .. }
+            | ClusterEvent::Absorbed { timestamp, .. }
+            | ClusterEvent::Merged { timestamp, .. }
+            | ClusterEvent::Promoted { timestamp, .. }
+            | ClusterEvent::Pruned { timestamp, .. }
+            | ClusterEvent::Split { timestamp, .. } => *timestamp,
+        }
+    }
+}
+
+pub trait ClusterEventListener: Send {
+    fn on_event(&mut self, event: &ClusterEvent);
+}
+
+impl<F: FnMut(&ClusterEvent) + Send> ClusterEventListener for F {
+    fn on_event(&mut self, event: &ClusterEvent) {
+        self(event)
+    }
+}
+
+/// Subscribed listeners of one algorithm
+#[derive(Default)]
+pub struct Listeners {
+    listeners: Vec<Box<dyn ClusterEventListener>>,
+}
+
+impl Listeners {
+    pub fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.listeners.push(listener);
+    }
+
+    pub fn emit(&mut self, event: ClusterEvent) {
+        for listener in self.listeners.iter_mut() {
+            listener.on_event(&event);
+        }
+    }
+}
+
+impl fmt::Debug for Listeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Listeners({})", self.listeners.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{
+        birch::Birch, clustream::CluStream, denstream::Denstream, DataStreamClusteringAlgorithm,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn record(algorithm: &mut dyn DataStreamClusteringAlgorithm) -> Arc<Mutex<Vec<ClusterEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        algorithm.subscribe(Box::new(move |event: &ClusterEvent| {
+            sink.lock().unwrap().push(event.clone())
+        }));
+        events
+    }
+
+    #[test]
+    fn test_birch_events() {
+        let mut birch = Birch::new(0.5, 4, 2).unwrap();
+        let events = record(&mut birch);
+        for i in 0..200 {
+            birch.insert(vec![(i % 40) as f64 * 10., 0.]).unwrap();
+        }
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events[0],
+            ClusterEvent::Created {
+                id: 0,
+                timestamp: 1
+            }
+        );
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, ClusterEvent::Absorbed { .. })));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, ClusterEvent::Split { .. })));
+        assert!(events
+            .windows(2)
+            .all(|w| w[0].timestamp() <= w[1].timestamp()));
+    }
+
+    #[test]
+    fn test_denstream_events() {
+        let mut denstream = Denstream::new();
+        let events = record(&mut denstream);
+        for i in 0..300 {
+            denstream.insert(vec![(i % 3) as f64 * 100., 0.]).unwrap();
+        }
+        let events = events.lock().unwrap();
+        let created = events
+            .iter()
+            .filter(|event| matches!(event, ClusterEvent::Created { .. }))
+            .count();
+        assert!(created > 0);
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, ClusterEvent::Absorbed { .. })));
+    }
+
+    #[test]
+    fn test_clusters_carry_ids() {
+        let models: Vec<Box<dyn DataStreamClusteringAlgorithm>> = vec![
+            Box::new(Birch::new(1., 4, 3).unwrap()),
+            Box::new(CluStream::new(3).unwrap()),
+            Box::new(Denstream::new()),
+        ];
+        for mut model in models {
+            let events = record(model.as_mut());
+            for i in 0..600 {
+                model
+                    .insert(vec![(i % 3) as f64 * 100. + (i % 5) as f64 * 0.1, 0.])
+                    .unwrap();
+            }
+            let events = events.lock().unwrap();
+            let clusters = model.clusters().unwrap();
+            assert!(!clusters.is_empty());
+            for element in clusters.iter() {
+                // Merges keep the oldest creation time
+                let created = events
+                    .iter()
+                    .find_map(|event| match event {
+                        ClusterEvent::Created { id, timestamp } if *id == element.id => {
+                            Some(*timestamp)
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| panic!("{}: no id {}", model.name(), element.id));
+                assert!(element.created <= created, "{}", model.name());
+                assert!(element.created <= element.last_update, "{}", model.name());
+                assert!(element.weight > 0., "{}", model.name());
+            }
+            if model.name() != "DenStream" {
+                let weight: f64 = clusters.iter().map(|element| element.weight).sum();
+                assert_eq!(weight, 600., "{}", model.name());
+            }
+        }
+    }
+}
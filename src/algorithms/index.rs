@@ -0,0 +1,828 @@
+use super::Metric;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::Debug;
+
+/// Out-links every node of a `GraphIndex` gets on insert
+const GRAPH_DEGREE: usize = 12;
+/// Back-links can grow a node's out-links up to this before they are cut to the closest
+const GRAPH_MAX_DEGREE: usize = 2 * GRAPH_DEGREE;
+/// Candidates kept by the beam search of a `GraphIndex`, more is slower but more accurate
+const GRAPH_SEARCH_WIDTH: usize = 48;
+/// Changes a `KdTree` takes before the first rebuild
+const MIN_REBUILD: usize = 16;
+
+/// Nearest-neighbour search over keyed points, e.g. micro-cluster centers under their
+/// positions. Distances are those of the index's `Metric`.
+pub trait NeighbourIndex: Debug + Send {
+    /// Adds `point` under `key`, replacing the point the key had
+    fn insert(&mut self, key: usize, point: &[f64]);
+    /// Returns whether the key was in the index
+    fn remove(&mut self, key: usize) -> bool;
+    /// Up to `k` nearest points as `(key, distance)`, closest first, ties by key
+    fn k_nearest(&self, query: &[f64], k: usize) -> Vec<(usize, f64)>;
+    /// Points at most `radius` from `query` as `(key, distance)`, ordered by key
+    fn within(&self, query: &[f64], radius: f64) -> Vec<(usize, f64)>;
+    /// The two closest points as `(key, key, distance)`, smaller key first, ties by keys
+    fn closest_pair(&self) -> Option<(usize, usize, f64)>;
+    fn len(&self) -> usize;
+    fn clear(&mut self);
+
+    fn nearest(&self, query: &[f64]) -> Option<(usize, f64)> {
+        self.k_nearest(query, 1).into_iter().next()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// How algorithms find the nearest micro-cluster
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NeighbourSearch {
+    /// Compares the point with every micro-cluster, the cheapest for a few of them
+    #[default]
+    Linear,
+    /// Exact, and much faster than a scan for many micro-clusters in low dimensions. With a
+    /// metric that gives no per-coordinate bound (`Cosine`) it visits every node.
+    KdTree,
+    /// Approximate navigable small-world graph, for many micro-clusters in high dimensions
+    Graph,
+}
+
+impl NeighbourSearch {
+    pub fn build<D: Metric>(self, metric: D) -> Box<dyn NeighbourIndex> {
+        match self {
+            NeighbourSearch::Linear => Box::new(LinearIndex::new(metric)),
+            NeighbourSearch::KdTree => Box::new(KdTree::new(metric)),
+            NeighbourSearch::Graph => Box::new(GraphIndex::new(metric)),
+        }
+    }
+}
+
+/// Orders `(key, distance)` by distance, then key
+fn closer(a: &(usize, f64), b: &(usize, f64)) -> Ordering {
+    a.1.total_cmp(&b.1).then(a.0.cmp(&b.0))
+}
+
+/// Orders `(key, key, distance)` by distance, then keys
+fn closer_pair(a: &(usize, usize, f64), b: &(usize, usize, f64)) -> Ordering {
+    a.2.total_cmp(&b.2).then((a.0, a.1).cmp(&(b.0, b.1)))
+}
+
+/// `(key, key, distance)` with the smaller key first
+fn pair(a: usize, b: usize, distance: f64) -> (usize, usize, f64) {
+    (a.min(b), a.max(b), distance)
+}
+
+/// The `k` best `(key, distance)` pairs seen so far, closest first
+struct Best {
+    k: usize,
+    items: Vec<(usize, f64)>,
+    /// Candidates further than this are of no interest, lets searches prune early
+    limit: f64,
+}
+
+impl Best {
+    fn new(k: usize) -> Self {
+        Best::within(k, f64::INFINITY)
+    }
+
+    fn within(k: usize, limit: f64) -> Self {
+        Best {
+            k,
+            items: Vec::with_capacity(k + 1),
+            limit,
+        }
+    }
+
+    fn offer(&mut self, key: usize, distance: f64) {
+        if self.k == 0 {
+            return;
+        }
+        let item = (key, distance);
+        if self.items.len() == self.k && closer(&item, &self.items[self.k - 1]) != Ordering::Less {
+            return;
+        }
+        let position = self
+            .items
+            .partition_point(|other| closer(other, &item) == Ordering::Less);
+        self.items.insert(position, item);
+        self.items.truncate(self.k);
+    }
+
+    /// Distance a candidate has to beat, the limit until `k` items are in
+    fn bound(&self) -> f64 {
+        if self.items.len() < self.k {
+            self.limit
+        } else {
+            self.items[self.k - 1].1.min(self.limit)
+        }
+    }
+}
+
+/// Compares the query with every point
+#[derive(Debug, Clone)]
+pub struct LinearIndex<D> {
+    points: Vec<(usize, Vec<f64>)>,
+    /// Position of every key in `points`
+    positions: HashMap<usize, usize>,
+    metric: D,
+}
+
+impl<D: Metric> LinearIndex<D> {
+    pub fn new(metric: D) -> Self {
+        LinearIndex {
+            points: Vec::new(),
+            positions: HashMap::new(),
+            metric,
+        }
+    }
+}
+
+impl<D: Metric> NeighbourIndex for LinearIndex<D> {
+    fn insert(&mut self, key: usize, point: &[f64]) {
+        match self.positions.get(&key) {
+            Some(&position) => self.points[position].1 = point.to_vec(),
+            None => {
+                self.positions.insert(key, self.points.len());
+                self.points.push((key, point.to_vec()));
+            }
+        }
+    }
+
+    fn remove(&mut self, key: usize) -> bool {
+        let Some(position) = self.positions.remove(&key) else {
+            return false;
+        };
+        self.points.swap_remove(position);
+        if let Some((moved, _)) = self.points.get(position) {
+            self.positions.insert(*moved, position);
+        }
+        true
+    }
+
+    fn k_nearest(&self, query: &[f64], k: usize) -> Vec<(usize, f64)> {
+        let mut best = Best::new(k);
+        for (key, point) in self.points.iter() {
+            best.offer(*key, self.metric.distance(query, point));
+        }
+        best.items
+    }
+
+    fn within(&self, query: &[f64], radius: f64) -> Vec<(usize, f64)> {
+        let mut found: Vec<(usize, f64)> = self
+            .points
+            .iter()
+            .map(|(key, point)| (*key, self.metric.distance(query, point)))
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+        found.sort_unstable_by_key(|(key, _)| *key);
+        found
+    }
+
+    fn closest_pair(&self) -> Option<(usize, usize, f64)> {
+        let mut closest: Option<(usize, usize, f64)> = None;
+        for (i, (a, x)) in self.points.iter().enumerate() {
+            for (b, y) in self.points[i + 1..].iter() {
+                let candidate = pair(*a, *b, self.metric.distance(x, y));
+                if closest.is_none_or(|closest| closer_pair(&candidate, &closest).is_lt()) {
+                    closest = Some(candidate);
+                }
+            }
+        }
+        closest
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn clear(&mut self) {
+        self.points.clear();
+        self.positions.clear();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct KdNode {
+    key: usize,
+    point: Vec<f64>,
+    axis: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+    /// Removed points stay as tombstones until the next rebuild
+    removed: bool,
+}
+
+/// kd-tree with insertions at the leaves and removal by tombstones. It is rebuilt balanced
+/// once there were as many inserts and removals since the last rebuild as it held then,
+/// which keeps updates at O(log n) amortized.
+#[derive(Debug, Clone)]
+pub struct KdTree<D> {
+    nodes: Vec<KdNode>,
+    root: Option<usize>,
+    /// Node of every key
+    keys: HashMap<usize, usize>,
+    /// Inserts and removals since the last rebuild
+    changes: usize,
+    /// Points at the last rebuild
+    built: usize,
+    metric: D,
+}
+
+impl<D: Metric> KdTree<D> {
+    pub fn new(metric: D) -> Self {
+        KdTree {
+            nodes: Vec::new(),
+            root: None,
+            keys: HashMap::new(),
+            changes: 0,
+            built: 0,
+            metric,
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let mut live: Vec<(usize, Vec<f64>)> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .filter(|node| !node.removed)
+            .map(|node| (node.key, node.point))
+            .collect();
+        self.keys.clear();
+        self.changes = 0;
+        self.built = live.len();
+        self.root = self.build(&mut live, 0);
+    }
+
+    /// Splits `points` at the median along the axis of `depth`, returns the subtree root
+    fn build(&mut self, points: &mut [(usize, Vec<f64>)], depth: usize) -> Option<usize> {
+        if points.is_empty() {
+            return None;
+        }
+        let axis = depth % points[0].1.len().max(1);
+        let median = points.len() / 2;
+        points.select_nth_unstable_by(median, |a, b| {
+            a.1[axis].total_cmp(&b.1[axis]).then(a.0.cmp(&b.0))
+        });
+        let (before, rest) = points.split_at_mut(median);
+        let (middle, after) = rest.split_first_mut()?;
+        let id = self.nodes.len();
+        self.keys.insert(middle.0, id);
+        self.nodes.push(KdNode {
+            key: middle.0,
+            point: std::mem::take(&mut middle.1),
+            axis,
+            left: None,
+            right: None,
+            removed: false,
+        });
+        let left = self.build(before, depth + 1);
+        let right = self.build(after, depth + 1);
+        self.nodes[id].left = left;
+        self.nodes[id].right = right;
+        Some(id)
+    }
+
+    /// Whether the subtree on the other side of a split `difference` away can hold a point
+    /// within `bound`
+    fn may_cross(&self, difference: f64, bound: f64) -> bool {
+        self.metric
+            .axis_lower_bound(difference.abs())
+            .is_none_or(|lower| lower <= bound)
+    }
+
+    fn search_nearest(&self, node: Option<usize>, query: &[f64], best: &mut Best) {
+        let Some(node) = node.map(|id| &self.nodes[id]) else {
+            return;
+        };
+        if !node.removed {
+            best.offer(node.key, self.metric.distance(query, &node.point));
+        }
+        let difference = query[node.axis] - node.point[node.axis];
+        let (near, far) = if difference < 0. {
+            (node.left, node.right)
+        } else {
+            (node.right, node.left)
+        };
+        self.search_nearest(near, query, best);
+        if self.may_cross(difference, best.bound()) {
+            self.search_nearest(far, query, best);
+        }
+    }
+
+    fn search_within(
+        &self,
+        node: Option<usize>,
+        query: &[f64],
+        radius: f64,
+        found: &mut Vec<(usize, f64)>,
+    ) {
+        let Some(node) = node.map(|id| &self.nodes[id]) else {
+            return;
+        };
+        if !node.removed {
+            let distance = self.metric.distance(query, &node.point);
+            if distance <= radius {
+                found.push((node.key, distance));
+            }
+        }
+        let difference = query[node.axis] - node.point[node.axis];
+        let (near, far) = if difference < 0. {
+            (node.left, node.right)
+        } else {
+            (node.right, node.left)
+        };
+        self.search_within(near, query, radius, found);
+        if self.may_cross(difference, radius) {
+            self.search_within(far, query, radius, found);
+        }
+    }
+}
+
+impl<D: Metric> NeighbourIndex for KdTree<D> {
+    fn insert(&mut self, key: usize, point: &[f64]) {
+        self.remove(key);
+        let id = self.nodes.len();
+        let mut axis = 0;
+        if let Some(mut current) = self.root {
+            loop {
+                let node = &self.nodes[current];
+                let next = if point[node.axis] < node.point[node.axis] {
+                    node.left
+                } else {
+                    node.right
+                };
+                axis = (node.axis + 1) % point.len().max(1);
+                match next {
+                    Some(next) => current = next,
+                    None => {
+                        let node = &mut self.nodes[current];
+                        if point[node.axis] < node.point[node.axis] {
+                            node.left = Some(id);
+                        } else {
+                            node.right = Some(id);
+                        }
+                        break;
+                    }
+                }
+            }
+        } else {
+            self.root = Some(id);
+        }
+        self.nodes.push(KdNode {
+            key,
+            point: point.to_vec(),
+            axis,
+            left: None,
+            right: None,
+            removed: false,
+        });
+        self.keys.insert(key, id);
+        self.changes += 1;
+        if self.changes > self.built.max(MIN_REBUILD) {
+            self.rebuild();
+        }
+    }
+
+    fn remove(&mut self, key: usize) -> bool {
+        let Some(id) = self.keys.remove(&key) else {
+            return false;
+        };
+        self.nodes[id].removed = true;
+        self.changes += 1;
+        if self.keys.is_empty() {
+            self.clear();
+        }
+        true
+    }
+
+    fn k_nearest(&self, query: &[f64], k: usize) -> Vec<(usize, f64)> {
+        let mut best = Best::new(k);
+        self.search_nearest(self.root, query, &mut best);
+        best.items
+    }
+
+    fn within(&self, query: &[f64], radius: f64) -> Vec<(usize, f64)> {
+        let mut found = Vec::new();
+        self.search_within(self.root, query, radius, &mut found);
+        found.sort_unstable_by_key(|(key, _)| *key);
+        found
+    }
+
+    /// The nearest other point of every point, searching only as far as the closest pair
+    /// found so far, O(n log n)
+    fn closest_pair(&self) -> Option<(usize, usize, f64)> {
+        let mut closest: Option<(usize, usize, f64)> = None;
+        for node in self.nodes.iter().filter(|node| !node.removed) {
+            let limit = closest.map_or(f64::INFINITY, |(_, _, distance)| distance);
+            let mut best = Best::within(2, limit);
+            self.search_nearest(self.root, &node.point, &mut best);
+            // The point itself is one of the two, unless others sit on it
+            let candidate = best
+                .items
+                .into_iter()
+                .find(|(key, _)| *key != node.key)
+                .map(|(key, distance)| pair(node.key, key, distance));
+            if let Some(candidate) = candidate {
+                if closest.is_none_or(|closest| closer_pair(&candidate, &closest).is_lt()) {
+                    closest = Some(candidate);
+                }
+            }
+        }
+        closest
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = None;
+        self.keys.clear();
+        self.changes = 0;
+        self.built = 0;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GraphNode {
+    key: usize,
+    point: Vec<f64>,
+    /// Out-links, kept when the node is removed so searches can still pass through it
+    neighbours: Vec<usize>,
+    removed: bool,
+}
+
+/// Distance with the order of `f64::total_cmp`, for the heaps of the beam search
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Distance(f64);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Navigable small-world graph: every point is linked to the closest points a beam search
+/// found when it was inserted (and they back to it), and queries walk the links greedily
+/// from an entry point. Searches cost about O(log n) distances regardless of the
+/// dimension, but are approximate: they can miss the true nearest point. Removed points
+/// stay in the graph as waypoints until they outnumber the live ones, then the graph is
+/// rebuilt.
+#[derive(Debug, Clone)]
+pub struct GraphIndex<D> {
+    nodes: Vec<GraphNode>,
+    /// Node of every key
+    keys: HashMap<usize, usize>,
+    metric: D,
+}
+
+impl<D: Metric> GraphIndex<D> {
+    pub fn new(metric: D) -> Self {
+        GraphIndex {
+            nodes: Vec::new(),
+            keys: HashMap::new(),
+            metric,
+        }
+    }
+
+    /// Beam search from the first node: the `width` closest live nodes reached, as
+    /// `(node, distance)` closest first. Removed nodes are passed through.
+    fn search(&self, query: &[f64], width: usize) -> Vec<(usize, f64)> {
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+        let distance = |id: usize| Distance(self.metric.distance(query, &self.nodes[id].point));
+        let mut visited = HashSet::from([0]);
+        let mut candidates = BinaryHeap::from([Reverse((distance(0), 0))]);
+        let mut results = BinaryHeap::new();
+        if !self.nodes[0].removed {
+            results.push((distance(0), 0));
+        }
+        while let Some(Reverse((current, id))) = candidates.pop() {
+            if results.len() >= width && results.peek().is_some_and(|(worst, _)| current > *worst) {
+                break;
+            }
+            for &neighbour in self.nodes[id].neighbours.iter() {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let d = distance(neighbour);
+                if results.len() < width || results.peek().is_some_and(|(worst, _)| d < *worst) {
+                    candidates.push(Reverse((d, neighbour)));
+                    if !self.nodes[neighbour].removed {
+                        results.push((d, neighbour));
+                        if results.len() > width {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+        let mut results: Vec<(usize, f64)> = results.into_iter().map(|(d, id)| (id, d.0)).collect();
+        results.sort_unstable_by(closer);
+        results
+    }
+
+    /// Cuts the out-links of `id` to the `GRAPH_DEGREE` closest ones
+    fn prune(&mut self, id: usize) {
+        let point = &self.nodes[id].point;
+        let mut neighbours: Vec<(usize, f64)> = self.nodes[id]
+            .neighbours
+            .iter()
+            .map(|&n| (n, self.metric.distance(point, &self.nodes[n].point)))
+            .collect();
+        neighbours.sort_unstable_by(closer);
+        self.nodes[id].neighbours = neighbours
+            .into_iter()
+            .take(GRAPH_DEGREE)
+            .map(|(n, _)| n)
+            .collect();
+    }
+
+    fn rebuild(&mut self) {
+        let live: Vec<GraphNode> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .filter(|node| !node.removed)
+            .collect();
+        self.keys.clear();
+        for node in live {
+            self.insert(node.key, &node.point);
+        }
+    }
+}
+
+impl<D: Metric> NeighbourIndex for GraphIndex<D> {
+    /// A point that moves less than the distance to its closest link (e.g. a micro-cluster
+    /// absorbing a point) keeps its node and links, anything else is linked anew
+    fn insert(&mut self, key: usize, point: &[f64]) {
+        if let Some(&id) = self.keys.get(&key) {
+            let node = &self.nodes[id];
+            let closest_link = node
+                .neighbours
+                .iter()
+                .map(|&n| self.metric.distance(&node.point, &self.nodes[n].point))
+                .fold(f64::INFINITY, f64::min);
+            if self.metric.distance(&node.point, point) <= closest_link {
+                self.nodes[id].point = point.to_vec();
+                return;
+            }
+        }
+        self.remove(key);
+        let id = self.nodes.len();
+        let neighbours: Vec<usize> = self
+            .search(point, GRAPH_SEARCH_WIDTH)
+            .into_iter()
+            .take(GRAPH_DEGREE)
+            .map(|(n, _)| n)
+            .collect();
+        self.nodes.push(GraphNode {
+            key,
+            point: point.to_vec(),
+            neighbours: neighbours.clone(),
+            removed: false,
+        });
+        self.keys.insert(key, id);
+        for neighbour in neighbours {
+            self.nodes[neighbour].neighbours.push(id);
+            if self.nodes[neighbour].neighbours.len() > GRAPH_MAX_DEGREE {
+                self.prune(neighbour);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: usize) -> bool {
+        let Some(id) = self.keys.remove(&key) else {
+            return false;
+        };
+        self.nodes[id].removed = true;
+        if self.keys.is_empty() {
+            self.clear();
+        } else if self.nodes.len() > 2 * self.keys.len() {
+            self.rebuild();
+        }
+        true
+    }
+
+    fn k_nearest(&self, query: &[f64], k: usize) -> Vec<(usize, f64)> {
+        let mut found: Vec<(usize, f64)> = self
+            .search(query, GRAPH_SEARCH_WIDTH.max(k))
+            .into_iter()
+            .map(|(id, d)| (self.nodes[id].key, d))
+            .collect();
+        found.sort_unstable_by(closer);
+        found.truncate(k);
+        found
+    }
+
+    /// Approximate: walks the links from the nodes the beam search found within `radius`
+    fn within(&self, query: &[f64], radius: f64) -> Vec<(usize, f64)> {
+        let mut to_visit: Vec<usize> = self
+            .search(query, GRAPH_SEARCH_WIDTH)
+            .into_iter()
+            .filter(|(_, d)| *d <= radius)
+            .map(|(id, _)| id)
+            .collect();
+        let mut visited: HashSet<usize> = to_visit.iter().copied().collect();
+        let mut found = Vec::new();
+        while let Some(id) = to_visit.pop() {
+            let node = &self.nodes[id];
+            if !node.removed {
+                found.push((node.key, self.metric.distance(query, &node.point)));
+            }
+            for &neighbour in node.neighbours.iter() {
+                if visited.insert(neighbour)
+                    && self.metric.distance(query, &self.nodes[neighbour].point) <= radius
+                {
+                    to_visit.push(neighbour);
+                }
+            }
+        }
+        found.sort_unstable_by_key(|(key, _)| *key);
+        found
+    }
+
+    /// Approximate: the closest pair of linked points
+    fn closest_pair(&self) -> Option<(usize, usize, f64)> {
+        self.nodes
+            .iter()
+            .filter(|node| !node.removed)
+            .flat_map(|node| {
+                node.neighbours
+                    .iter()
+                    .map(|&n| &self.nodes[n])
+                    .filter(|other| !other.removed)
+                    .map(move |other| {
+                        pair(
+                            node.key,
+                            other.key,
+                            self.metric.distance(&node.point, &other.point),
+                        )
+                    })
+            })
+            .min_by(closer_pair)
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.keys.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Cosine, Euclidean, Manhattan};
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64;
+
+    fn random_points(count: usize, dimension: usize, seed: u64) -> Vec<Vec<f64>> {
+        let mut rng = Pcg64::seed_from_u64(seed);
+        (0..count)
+            .map(|_| (0..dimension).map(|_| rng.gen_range(-10.0..10.0)).collect())
+            .collect()
+    }
+
+    fn filled<I: NeighbourIndex>(mut index: I, points: &[Vec<f64>]) -> I {
+        for (key, point) in points.iter().enumerate() {
+            index.insert(key, point);
+        }
+        index
+    }
+
+    #[test]
+    fn test_kd_tree_matches_scan() {
+        let points = random_points(500, 3, 1);
+        let queries = random_points(100, 3, 2);
+        let mut linear = filled(LinearIndex::new(Euclidean), &points);
+        let mut tree = filled(KdTree::new(Euclidean), &points);
+        // Move and remove some points, which leaves tombstones and triggers rebuilds
+        for (key, point) in random_points(300, 3, 3).iter().enumerate() {
+            linear.insert(key, point);
+            tree.insert(key, point);
+        }
+        for key in (0..500).step_by(3) {
+            assert!(linear.remove(key));
+            assert!(tree.remove(key));
+        }
+        assert!(!tree.remove(0));
+        assert_eq!(tree.len(), linear.len());
+        assert_eq!(tree.closest_pair(), linear.closest_pair());
+        for query in queries.iter() {
+            assert_eq!(tree.k_nearest(query, 5), linear.k_nearest(query, 5));
+            assert_eq!(tree.within(query, 2.5), linear.within(query, 2.5));
+        }
+        // Metrics without a per-coordinate bound still get exact answers
+        let tree = filled(KdTree::new(Cosine), &points);
+        let linear = filled(LinearIndex::new(Cosine), &points);
+        for query in queries.iter() {
+            assert_eq!(tree.nearest(query), linear.nearest(query));
+        }
+        let tree = filled(KdTree::new(Manhattan), &points);
+        let linear = filled(LinearIndex::new(Manhattan), &points);
+        for query in queries.iter() {
+            assert_eq!(tree.nearest(query), linear.nearest(query));
+        }
+    }
+
+    #[test]
+    fn test_ties_by_key() {
+        let mut tree = KdTree::new(Euclidean);
+        for key in [5, 3, 9] {
+            tree.insert(key, &[1., 1.]);
+        }
+        assert_eq!(tree.k_nearest(&[1., 1.], 2), vec![(3, 0.), (5, 0.)]);
+        let mut linear = LinearIndex::new(Euclidean);
+        for key in [5, 3, 9] {
+            linear.insert(key, &[1., 1.]);
+        }
+        assert_eq!(linear.k_nearest(&[1., 1.], 2), vec![(3, 0.), (5, 0.)]);
+    }
+
+    #[test]
+    fn test_algorithms_match_linear_search() {
+        use crate::algorithms::{
+            clustream::CluStream, denstream::Denstream, DataStreamClusteringAlgorithm,
+        };
+        let points: Vec<Vec<f64>> = random_points(3_000, 2, 6)
+            .into_iter()
+            .enumerate()
+            .map(|(i, point)| {
+                let offset = (i % 20) as f64 * 30.;
+                vec![point[0] / 5. + offset, point[1] / 5. - offset]
+            })
+            .collect();
+        let micro_clusters = |model: &mut dyn DataStreamClusteringAlgorithm| {
+            for point in points.iter() {
+                model.insert(point.clone()).unwrap();
+            }
+            let mut found: Vec<(Vec<f64>, f64)> = model
+                .clusters()
+                .unwrap()
+                .into_iter()
+                .map(|element| (element.center, element.weight))
+                .collect();
+            found.sort_by(|a, b| a.0[0].total_cmp(&b.0[0]).then(a.0[1].total_cmp(&b.0[1])));
+            found
+        };
+        let clustream = |search| {
+            CluStream::new(5)
+                .unwrap()
+                .with_max_micro_clusters(100)
+                .unwrap()
+                .with_search(search)
+        };
+        assert_eq!(
+            micro_clusters(&mut clustream(NeighbourSearch::KdTree)),
+            micro_clusters(&mut clustream(NeighbourSearch::Linear))
+        );
+        let denstream = |search| Denstream::new().with_search(search);
+        assert_eq!(
+            micro_clusters(&mut denstream(NeighbourSearch::KdTree)),
+            micro_clusters(&mut denstream(NeighbourSearch::Linear))
+        );
+        // The graph is approximate, it may pick other micro-clusters
+        assert!(!micro_clusters(&mut denstream(NeighbourSearch::Graph)).is_empty());
+    }
+
+    #[test]
+    fn test_graph_recall() {
+        let points = random_points(1_000, 16, 4);
+        let queries = random_points(200, 16, 5);
+        let linear = filled(LinearIndex::new(Euclidean), &points);
+        let mut graph = filled(GraphIndex::new(Euclidean), &points);
+        let hits = queries
+            .iter()
+            .filter(|query| graph.nearest(query) == linear.nearest(query))
+            .count();
+        assert!(hits >= 190, "{}", hits);
+
+        // Removed points are never returned
+        for key in 0..750 {
+            graph.remove(key);
+        }
+        assert_eq!(graph.len(), 250);
+        for query in queries.iter() {
+            assert!(graph.k_nearest(query, 3).iter().all(|(key, _)| *key >= 750));
+        }
+        for key in 750..1_000 {
+            graph.remove(key);
+        }
+        assert!(graph.is_empty());
+        assert_eq!(graph.nearest(&queries[0]), None);
+    }
+}
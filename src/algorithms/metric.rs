@@ -0,0 +1,160 @@
+use super::SparseVector;
+use std::fmt::Debug;
+
+/// Distance between two points of the same dimension
+pub trait Metric: Debug + Clone + Send + Sync + 'static {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64;
+    /// Same as `distance` for a sparse `a`. The default densifies `a`, the metrics below
+    /// walk `b` once without allocating.
+    fn sparse_distance(&self, a: &SparseVector, b: &[f64]) -> f64 {
+        self.distance(&a.to_dense(), b)
+    }
+    /// Lower bound on the distance of two points whose coordinates differ by `difference`
+    /// along one axis, which lets a `KdTree` skip subtrees. `None` when there is none.
+    fn axis_lower_bound(&self, _difference: f64) -> Option<f64> {
+        None
+    }
+    fn name(&self) -> &'static str;
+}
+
+/// Calls `f(a_i, b_i)` for every coordinate, with `a_i = 0` off the non-zeros of `a`
+fn for_each_coordinate(a: &SparseVector, b: &[f64], mut f: impl FnMut(f64, f64)) {
+    let mut non_zeros = a.iter().peekable();
+    for (i, y) in b.iter().enumerate() {
+        match non_zeros.next_if(|(j, _)| *j == i) {
+            Some((_, x)) => f(x, *y),
+            None => f(0., *y),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Euclidean;
+
+/// Cheaper than `Euclidean` and orders points the same way, but it is not a true metric
+/// (no triangle inequality), so thresholds tuned for `Euclidean` do not carry over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SquaredEuclidean;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Manhattan;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Chebyshev;
+
+/// `1 - cos(a, b)`, in `[0, 2]`. A zero vector has no direction and is at distance 1 from
+/// everything but another zero vector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cosine;
+
+impl Metric for Euclidean {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        SquaredEuclidean.distance(a, b).sqrt()
+    }
+    fn sparse_distance(&self, a: &SparseVector, b: &[f64]) -> f64 {
+        SquaredEuclidean.sparse_distance(a, b).sqrt()
+    }
+    fn axis_lower_bound(&self, difference: f64) -> Option<f64> {
+        Some(difference)
+    }
+    fn name(&self) -> &'static str {
+        "euclidean"
+    }
+}
+
+impl Metric for SquaredEuclidean {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum()
+    }
+    fn sparse_distance(&self, a: &SparseVector, b: &[f64]) -> f64 {
+        let mut sum = 0.;
+        for_each_coordinate(a, b, |x, y| sum += (x - y).powi(2));
+        sum
+    }
+    fn axis_lower_bound(&self, difference: f64) -> Option<f64> {
+        Some(difference * difference)
+    }
+    fn name(&self) -> &'static str {
+        "squared_euclidean"
+    }
+}
+
+impl Metric for Manhattan {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).sum()
+    }
+    fn sparse_distance(&self, a: &SparseVector, b: &[f64]) -> f64 {
+        let mut sum = 0.;
+        for_each_coordinate(a, b, |x, y| sum += (x - y).abs());
+        sum
+    }
+    fn axis_lower_bound(&self, difference: f64) -> Option<f64> {
+        Some(difference)
+    }
+    fn name(&self) -> &'static str {
+        "manhattan"
+    }
+}
+
+impl Metric for Chebyshev {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x - y).abs())
+            .fold(0., f64::max)
+    }
+    fn sparse_distance(&self, a: &SparseVector, b: &[f64]) -> f64 {
+        let mut max: f64 = 0.;
+        for_each_coordinate(a, b, |x, y| max = max.max((x - y).abs()));
+        max
+    }
+    fn axis_lower_bound(&self, difference: f64) -> Option<f64> {
+        Some(difference)
+    }
+    fn name(&self) -> &'static str {
+        "chebyshev"
+    }
+}
+
+impl Metric for Cosine {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        let dot: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+        cosine_distance(dot, norm_a, b)
+    }
+    fn sparse_distance(&self, a: &SparseVector, b: &[f64]) -> f64 {
+        cosine_distance(a.dot(b), a.squared_norm().sqrt(), b)
+    }
+    fn name(&self) -> &'static str {
+        "cosine"
+    }
+}
+
+fn cosine_distance(dot: f64, norm_a: f64, b: &[f64]) -> f64 {
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    match (norm_a == 0., norm_b == 0.) {
+        (true, true) => 0.,
+        (true, false) | (false, true) => 1.,
+        // Rounding can push the cosine slightly outside [-1, 1]
+        (false, false) => 1. - (dot / (norm_a * norm_b)).clamp(-1., 1.),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let a = [0., 0.];
+        let b = [3., 4.];
+        assert_eq!(Euclidean.distance(&a, &b), 5.);
+        assert_eq!(SquaredEuclidean.distance(&a, &b), 25.);
+        assert_eq!(Manhattan.distance(&a, &b), 7.);
+        assert_eq!(Chebyshev.distance(&a, &b), 4.);
+        assert_eq!(Cosine.distance(&a, &b), 1.);
+        assert_eq!(Cosine.distance(&a, &a), 0.);
+        assert!(Cosine.distance(&b, &[6., 8.]).abs() < 1e-12);
+        assert!((Cosine.distance(&b, &[-3., -4.]) - 2.).abs() < 1e-12);
+    }
+}
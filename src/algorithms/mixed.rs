@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Record with numeric and categorical attributes (e.g. a flow's byte counts together with
+/// its protocol and service). The numeric part is checked against the model's `Schema`
+/// like a plain point; the number of categorical attributes is locked by the first record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MixedPoint {
+    numeric: Vec<f64>,
+    categorical: Vec<String>,
+}
+
+impl MixedPoint {
+    pub fn new(numeric: Vec<f64>, categorical: Vec<String>) -> Self {
+        MixedPoint {
+            numeric,
+            categorical,
+        }
+    }
+
+    pub fn numeric(&self) -> &[f64] {
+        &self.numeric
+    }
+
+    pub fn categorical(&self) -> &[String] {
+        &self.categorical
+    }
+}
+
+/// Weighted frequencies of the values of one categorical attribute
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CategoryHistogram {
+    counts: HashMap<String, f64>,
+    total: f64,
+}
+
+impl CategoryHistogram {
+    fn add(&mut self, value: &str, weight: f64) {
+        *self.counts.entry(value.to_string()).or_insert(0.) += weight;
+        self.total += weight;
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.counts.values_mut().for_each(|count| *count *= factor);
+        self.total *= factor;
+    }
+
+    fn merge(&mut self, other: &CategoryHistogram) {
+        for (value, count) in other.counts.iter() {
+            *self.counts.entry(value.clone()).or_insert(0.) += count;
+        }
+        self.total += other.total;
+    }
+
+    /// Share of the weight carrying `value`, 0 for an empty histogram
+    fn frequency(&self, value: &str) -> f64 {
+        if self.total <= 0. {
+            return 0.;
+        }
+        self.counts
+            .get(value)
+            .map_or(0., |count| count / self.total)
+    }
+
+    /// Most frequent value, ties go to the smallest value so the result is deterministic
+    fn mode(&self) -> Option<&str> {
+        self.counts
+            .iter()
+            .max_by(|(a, x), (b, y)| x.total_cmp(y).then_with(|| b.cmp(a)))
+            .map(|(value, _)| value.as_str())
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .counts
+                .keys()
+                .map(|value| std::mem::size_of::<(String, f64)>() + value.capacity())
+                .sum::<usize>()
+    }
+}
+
+/// Category histograms kept next to the numeric sums of a micro-cluster, one per
+/// categorical attribute, as in HCluStream. Histograms are created by the first record
+/// with categorical attributes, so numeric-only micro-clusters carry none.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CategoricalSummary {
+    histograms: Vec<CategoryHistogram>,
+}
+
+impl CategoricalSummary {
+    pub(crate) fn add(&mut self, values: &[String], weight: f64) {
+        if self.histograms.len() < values.len() {
+            self.histograms
+                .resize_with(values.len(), CategoryHistogram::default);
+        }
+        for (histogram, value) in self.histograms.iter_mut().zip(values.iter()) {
+            histogram.add(value, weight);
+        }
+    }
+
+    /// Applies a decay factor to every count
+    pub(crate) fn scale(&mut self, factor: f64) {
+        self.histograms
+            .iter_mut()
+            .for_each(|histogram| histogram.scale(factor));
+    }
+
+    pub(crate) fn merge(&mut self, other: &CategoricalSummary) {
+        if self.histograms.len() < other.histograms.len() {
+            self.histograms
+                .resize_with(other.histograms.len(), CategoryHistogram::default);
+        }
+        for (histogram, other) in self.histograms.iter_mut().zip(other.histograms.iter()) {
+            histogram.merge(other);
+        }
+    }
+
+    /// Mean over the attributes of `1 - frequency(value)`: 0 when every record of the
+    /// micro-cluster shares the values, 1 when none has any of them. 0 for numeric-only
+    /// records.
+    pub(crate) fn dissimilarity(&self, values: &[String]) -> f64 {
+        if values.is_empty() {
+            return 0.;
+        }
+        let mismatch: f64 = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                1. - self
+                    .histograms
+                    .get(i)
+                    .map_or(0., |histogram| histogram.frequency(value))
+            })
+            .sum();
+        mismatch / values.len() as f64
+    }
+
+    /// Most frequent value of every attribute
+    pub(crate) fn modes(&self) -> Vec<String> {
+        self.histograms
+            .iter()
+            .map(|histogram| histogram.mode().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.histograms
+            .iter()
+            .map(CategoryHistogram::memory_bytes)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = CategoricalSummary::default();
+        assert_eq!(summary.dissimilarity(&values(&["tcp"])), 1.);
+        summary.add(&values(&["tcp", "http"]), 3.);
+        summary.add(&values(&["udp", "http"]), 1.);
+        assert_eq!(summary.modes(), values(&["tcp", "http"]));
+        assert_eq!(summary.dissimilarity(&values(&["tcp", "http"])), 0.125);
+        assert_eq!(summary.dissimilarity(&values(&["icmp", "dns"])), 1.);
+        assert_eq!(summary.dissimilarity(&[]), 0.);
+
+        // Frequencies are relative, decay does not change them
+        summary.scale(0.5);
+        assert_eq!(summary.dissimilarity(&values(&["tcp", "http"])), 0.125);
+
+        let mut other = CategoricalSummary::default();
+        other.add(&values(&["udp", "dns"]), 4.);
+        summary.merge(&other);
+        assert_eq!(summary.modes(), values(&["udp", "dns"]));
+    }
+
+    #[test]
+    fn test_mode_ties() {
+        let mut summary = CategoricalSummary::default();
+        summary.add(&values(&["b"]), 1.);
+        summary.add(&values(&["a"]), 1.);
+        assert_eq!(summary.modes(), values(&["a"]));
+    }
+
+    #[test]
+    fn test_algorithms_keep_categories() {
+        use crate::algorithms::{
+            birch::Birch, clustream::CluStream, denstream::Denstream, ClusteringError,
+            DataStreamClusteringAlgorithm,
+        };
+        let records: Vec<MixedPoint> = (0..400)
+            .map(|i| {
+                let jitter = (i % 7) as f64 * 0.05;
+                if i % 2 == 0 {
+                    MixedPoint::new(vec![jitter, jitter], values(&["tcp", "http"]))
+                } else {
+                    MixedPoint::new(vec![20. + jitter, 20.], values(&["udp", "dns"]))
+                }
+            })
+            .collect();
+        let models: Vec<Box<dyn DataStreamClusteringAlgorithm>> = vec![
+            Box::new(CluStream::new(2).unwrap()),
+            Box::new(Denstream::new().with_categorical_weight(5.).unwrap()),
+        ];
+        for mut model in models {
+            for record in records.iter() {
+                model.insert_mixed(record).unwrap();
+            }
+            let clusters = model.clusters().unwrap();
+            assert!(!clusters.is_empty());
+            for element in clusters {
+                let expected = if element.center[0] < 10. {
+                    values(&["tcp", "http"])
+                } else {
+                    values(&["udp", "dns"])
+                };
+                assert_eq!(element.categories, expected);
+            }
+            assert_eq!(
+                model.insert_mixed(&MixedPoint::new(vec![0., 0.], values(&["tcp"]))),
+                Err(ClusteringError::CategoricalMismatch {
+                    expected: 2,
+                    found: 1
+                })
+            );
+            // Numeric-only points still go in
+            assert!(model.insert(vec![0., 0.]).is_ok());
+        }
+
+        let mut birch = Birch::new(1., 10, 2).unwrap();
+        assert!(birch.insert_mixed(&records[0]).is_err());
+        assert!(birch
+            .insert_mixed(&MixedPoint::new(vec![0., 0.], Vec::new()))
+            .is_ok());
+        assert!(CluStream::new(2)
+            .unwrap()
+            .with_categorical_weight(-1.)
+            .is_err());
+    }
+}
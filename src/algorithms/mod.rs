@@ -1,47 +1,329 @@
 pub mod birch;
 pub mod clustream;
 pub mod denstream;
+pub mod events;
+pub mod index;
+pub mod metric;
+pub mod mixed;
+mod moments;
+pub mod schema;
+pub mod sparse;
+pub mod stats;
 
-#[derive(Clone, Debug)]
+pub use crate::error::ClusteringError;
+pub use events::{ClusterEvent, ClusterEventListener, Listeners};
+pub use index::{NeighbourIndex, NeighbourSearch};
+pub use metric::{Chebyshev, Cosine, Euclidean, Manhattan, Metric, SquaredEuclidean};
+pub use mixed::MixedPoint;
+pub use schema::Schema;
+use serde::Serialize;
+pub use sparse::{PointView, SparseVector};
+pub use stats::{AlgorithmDetails, AlgorithmStats, SamplerStats};
+
+/// How much `clusters()` reports about the shape of every cluster besides its radius. The
+/// models track it from the first point on, so their `with_spread` fails once they have
+/// taken any; earlier clusters would have no variances to report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Spread {
+    #[default]
+    Radius,
+    /// Variance of every coordinate, which describes elongated clusters
+    Variance,
+    /// Variances and the full covariance matrix, e.g. for Mahalanobis distances or
+    /// Gaussian components. Memory per cluster grows with the square of the dimension.
+    Covariance,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct ClusteringElement {
     pub center: Vec<f64>,
     pub radius: f64,
     pub cluster: usize,
+    /// Most frequent value of every categorical attribute, empty for numeric-only models
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    /// Variance of every coordinate, with `Spread::Variance` or `Spread::Covariance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variance: Option<Vec<f64>>,
+    /// Covariance matrix, with `Spread::Covariance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub covariance: Option<Vec<Vec<f64>>>,
+    /// Number of points summarised by the micro-cluster, faded for DenStream and for BIRCH
+    /// with decay
+    pub weight: f64,
+    /// Micro-cluster id, as in the `ClusterEvent`s
+    pub id: usize,
+    /// Time of the first and the newest point of the micro-cluster, on the algorithm's
+    /// clock (points for BIRCH, `insert` calls for CluStream, the coarse clock for DenStream)
+    pub created: usize,
+    pub last_update: usize,
+}
+
+/// Macro-cluster: the elements `clusters()` reports with the same `cluster` id
+#[derive(Clone, Debug, Serialize)]
+pub struct MacroCluster {
+    pub cluster: usize,
+    /// Total weight of the members
+    pub weight: f64,
+    /// Mean of the member centers weighted by their weights
+    pub center: Vec<f64>,
+    pub members: Vec<ClusteringElement>,
+}
+
+/// Groups micro-clusters into macro-clusters, ordered by cluster id. Members without any
+/// weight left (e.g. faded out) count equally when all of them are.
+pub fn macro_clusters(clusters: &[ClusteringElement]) -> Vec<MacroCluster> {
+    let mut groups: std::collections::BTreeMap<usize, Vec<ClusteringElement>> =
+        std::collections::BTreeMap::new();
+    for element in clusters {
+        groups
+            .entry(element.cluster)
+            .or_default()
+            .push(element.clone());
+    }
+    groups
+        .into_iter()
+        .map(|(cluster, members)| {
+            let weight: f64 = members.iter().map(|member| member.weight).sum();
+            let share = |member: &ClusteringElement| {
+                if weight > 0. {
+                    member.weight / weight
+                } else {
+                    1. / members.len() as f64
+                }
+            };
+            let mut center = vec![0.; members[0].center.len()];
+            for member in members.iter() {
+                let share = share(member);
+                center
+                    .iter_mut()
+                    .zip(member.center.iter())
+                    .for_each(|(c, x)| *c += share * x);
+            }
+            MacroCluster {
+                cluster,
+                weight,
+                center,
+                members,
+            }
+        })
+        .collect()
 }
 
 impl ClusteringElement {
+    /// Euclidean distance from the center, as used by `ssq` and `predict`
     pub fn distance(&self, other: &[f64]) -> f64 {
-        self.center
+        Euclidean.distance(&self.center, other)
+    }
+
+    /// Distance from the center in standard deviations, through the covariance matrix or,
+    /// without one, the variances. `None` without either, or when the cluster is flat in
+    /// some direction (e.g. a single point).
+    pub fn mahalanobis_distance(&self, point: &[f64]) -> Option<f64> {
+        let delta: Vec<f64> = point
             .iter()
-            .zip(other.iter())
-            .map(|(a, b)| (a - b).powi(2))
-            .sum::<f64>()
-            .sqrt()
+            .zip(self.center.iter())
+            .map(|(x, c)| x - c)
+            .collect();
+        if let Some(covariance) = &self.covariance {
+            let lower = cholesky(covariance)?;
+            // Solve L·y = delta, then |y|² = deltaᵀ·Σ⁻¹·delta
+            let mut y = vec![0.; delta.len()];
+            for i in 0..delta.len() {
+                let sum: f64 = (0..i).map(|j| lower[i][j] * y[j]).sum();
+                y[i] = (delta[i] - sum) / lower[i][i];
+            }
+            return Some(y.iter().map(|y| y * y).sum::<f64>().sqrt());
+        }
+        let variance = self.variance.as_ref()?;
+        if variance.iter().any(|v| *v <= 0.) {
+            return None;
+        }
+        Some(
+            delta
+                .iter()
+                .zip(variance.iter())
+                .map(|(d, v)| d * d / v)
+                .sum::<f64>()
+                .sqrt(),
+        )
     }
 }
 
+/// Lower-triangular `L` with `L·Lᵀ = matrix`, `None` unless the matrix is positive definite
+fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut lower = vec![vec![0.; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+            if i == j {
+                let diagonal = matrix[i][i] - sum;
+                if diagonal <= f64::EPSILON * matrix[i][i].abs().max(f64::MIN_POSITIVE) {
+                    return None;
+                }
+                lower[i][j] = diagonal.sqrt();
+            } else {
+                lower[i][j] = (matrix[i][j] - sum) / lower[j][j];
+            }
+        }
+    }
+    Some(lower)
+}
+
+/// Sum of squared distances between the micro-cluster centers and the center of their
+/// macro-cluster, weighted by the micro-cluster weights
 pub fn ssq(clusters: &[ClusteringElement]) -> f64 {
-    let k = clusters
+    macro_clusters(clusters)
+        .iter()
+        .map(|macro_cluster| {
+            macro_cluster
+                .members
+                .iter()
+                .map(|member| member.weight * member.distance(&macro_cluster.center).powi(2))
+                .sum::<f64>()
+        })
+        .sum()
+}
+
+/// Cluster id of the micro-cluster closest to the point
+pub fn predict(clusters: &[ClusteringElement], point: &[f64]) -> Option<usize> {
+    clusters
         .iter()
-        .fold(std::collections::HashMap::new(), |mut acc: std::collections::HashMap<usize, Vec<ClusteringElement>>, elem| {
-            acc.entry(elem.cluster).or_default().push(elem.clone());
-            acc
-        });
-    let mut ssq = 0.;
-    for k_i in k.values() {
-        let center = k_i.iter().fold(vec![0.0; k_i[0].center.len()], |acc, elem| {
-            acc.iter()
-                .zip(elem.center.iter())
-                .map(|(a, b)| a + b)
-                .collect()
-        }).iter().map(|elem| elem / k_i.len() as f64).collect::<Vec<f64>>();
-        ssq += k_i.iter().map(|elem| elem.distance(&center).powi(2)).sum::<f64>();
-    }
-    ssq
-}
-
-pub trait DataStreamClusteringAlgorithm {
-    fn insert(&mut self, data: Vec<f64>);
+        .map(|elem| (elem.cluster, elem.distance(point)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(cluster, _)| cluster)
+}
+
+pub trait DataStreamClusteringAlgorithm: Send {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError>;
+    /// Inserts a sparse point. The default densifies it. BIRCH keeps it sparse in its leaf
+    /// CFs; CluStream and DenStream densify it for the nearest micro-cluster search and the
+    /// micro-cluster update.
+    fn insert_sparse(&mut self, data: &SparseVector) -> Result<(), ClusteringError> {
+        self.insert(data.to_dense())
+    }
+    /// Inserts a record with categorical attributes. CluStream and DenStream keep category
+    /// histograms in their micro-clusters; the default only accepts records without any.
+    fn insert_mixed(&mut self, data: &MixedPoint) -> Result<(), ClusteringError> {
+        if !data.categorical().is_empty() {
+            return Err(ClusteringError::invalid_parameter(
+                "categorical",
+                format!("{} does not support categorical attributes", self.name()),
+            ));
+        }
+        self.insert(data.numeric().to_vec())
+    }
     fn name(&self) -> String;
-    fn clusters(&self) -> Vec<ClusteringElement>;
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError>;
+    /// Dimension (and feature names) of the accepted points
+    fn schema(&self) -> Schema;
+    fn stats(&self) -> AlgorithmStats;
+    /// Registers a listener for micro-cluster lifecycle events
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covariance() {
+        let element = ClusteringElement {
+            center: vec![1., 1.],
+            radius: 2.,
+            cluster: 0,
+            categories: Vec::new(),
+            variance: Some(vec![4., 1.]),
+            covariance: None,
+            weight: 4.,
+            id: 0,
+            created: 0,
+            last_update: 0,
+        };
+        // Two standard deviations along the first coordinate, one along the second
+        assert_eq!(element.mahalanobis_distance(&[5., 1.]), Some(2.));
+        assert_eq!(element.mahalanobis_distance(&[1., 0.]), Some(1.));
+
+        // Σ = [[2, 1], [1, 2]] has Σ⁻¹ = [[2, -1], [-1, 2]] / 3
+        let element = ClusteringElement {
+            variance: Some(vec![2., 2.]),
+            covariance: Some(vec![vec![2., 1.], vec![1., 2.]]),
+            ..element
+        };
+        // Along the correlation, (1, 1)·Σ⁻¹·(1, 1) = 2/3, across it (1, -1)·Σ⁻¹·(1, -1) = 2
+        let along = element.mahalanobis_distance(&[2., 2.]).unwrap();
+        let across = element.mahalanobis_distance(&[2., 0.]).unwrap();
+        assert!((along - (2_f64 / 3.).sqrt()).abs() < 1e-12);
+        assert!((across - 2_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(element.mahalanobis_distance(&[1., 1.]), Some(0.));
+
+        let flat = ClusteringElement {
+            covariance: Some(vec![vec![1., 1.], vec![1., 1.]]),
+            ..element.clone()
+        };
+        assert_eq!(flat.mahalanobis_distance(&[0., 0.]), None);
+        let unknown = ClusteringElement {
+            variance: None,
+            covariance: None,
+            ..element
+        };
+        assert_eq!(unknown.mahalanobis_distance(&[0., 0.]), None);
+    }
+
+    #[test]
+    fn test_spread_before_first_insert() {
+        use crate::algorithms::{birch::Birch, clustream::CluStream, denstream::Denstream};
+        let mut birch = Birch::new(1., 10, 2).unwrap();
+        let mut clustream = CluStream::new(2).unwrap();
+        let mut denstream = Denstream::new();
+        birch.insert(vec![0., 0.]).unwrap();
+        DataStreamClusteringAlgorithm::insert(&mut clustream, vec![0., 0.]).unwrap();
+        DataStreamClusteringAlgorithm::insert(&mut denstream, vec![0., 0.]).unwrap();
+        assert!(birch.with_spread(Spread::Variance).is_err());
+        assert!(clustream.with_spread(Spread::Variance).is_err());
+        assert!(denstream.with_spread(Spread::Variance).is_err());
+        // A schema given up front is no data yet
+        let schema = Schema::with_dimension(2).unwrap();
+        assert!(CluStream::new(2)
+            .unwrap()
+            .with_schema(schema)
+            .unwrap()
+            .with_spread(Spread::Covariance)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_macro_clusters() {
+        let element =
+            |center: Vec<f64>, cluster: usize, weight: f64, id: usize| ClusteringElement {
+                center,
+                radius: 0.,
+                cluster,
+                categories: Vec::new(),
+                variance: None,
+                covariance: None,
+                weight,
+                id,
+                created: 0,
+                last_update: 0,
+            };
+        let clusters = vec![
+            element(vec![0., 0.], 1, 3., 0),
+            element(vec![4., 0.], 1, 1., 1),
+            element(vec![10., 10.], 0, 0., 2),
+            element(vec![20., 10.], 0, 0., 3),
+        ];
+        let macro_clusters = macro_clusters(&clusters);
+        assert_eq!(macro_clusters.len(), 2);
+        assert_eq!(macro_clusters[0].cluster, 0);
+        // Faded out members count equally
+        assert_eq!(macro_clusters[0].center, vec![15., 10.]);
+        assert_eq!(macro_clusters[1].weight, 4.);
+        assert_eq!(macro_clusters[1].center, vec![1., 0.]);
+        let members: Vec<usize> = macro_clusters[1].members.iter().map(|m| m.id).collect();
+        assert_eq!(members, vec![0, 1]);
+        // 3·1² + 1·3², the faded out cluster adds nothing
+        assert_eq!(ssq(&clusters), 12.);
+    }
 }
@@ -0,0 +1,386 @@
+use super::{PointView, Spread};
+use serde::{Deserialize, Serialize};
+
+/// Sums of squared deviations from the mean (M2) of every coordinate and, if asked for,
+/// of the products of deviations between coordinates (co-moments), which give the
+/// variances and the covariance matrix of a cluster
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Dispersion {
+    m2: Vec<f64>,
+    /// Row-major, grows with the square of the dimension
+    comoments: Option<Vec<f64>>,
+}
+
+impl Dispersion {
+    pub(crate) fn zero(dimension: usize, covariance: bool) -> Self {
+        Dispersion {
+            m2: vec![0.; dimension],
+            comoments: covariance.then(|| vec![0.; dimension * dimension]),
+        }
+    }
+
+    /// Chan's update for adding another summary (`None` for a single point), whose mean
+    /// is `delta` away, with `factor = w_a · w_b / (w_a + w_b)`. Co-moments are dropped
+    /// unless both sides keep them.
+    pub(crate) fn merge(&mut self, other: Option<&Dispersion>, delta: &[f64], factor: f64) {
+        for (i, m2) in self.m2.iter_mut().enumerate() {
+            *m2 += other.map_or(0., |other| other.m2[i]) + factor * delta[i] * delta[i];
+        }
+        if other.is_some_and(|other| other.comoments.is_none()) {
+            self.comoments = None;
+        }
+        if let Some(comoments) = &mut self.comoments {
+            let dimension = delta.len();
+            for (index, comoment) in comoments.iter_mut().enumerate() {
+                let (i, j) = (index / dimension, index % dimension);
+                *comoment += other
+                    .and_then(|other| other.comoments.as_ref())
+                    .map_or(0., |other| other[index])
+                    + factor * delta[i] * delta[j];
+            }
+        }
+    }
+
+    /// Inverse of `merge`, for taking points back out
+    pub(crate) fn unmerge(&mut self, other: &Dispersion, delta: &[f64], factor: f64) {
+        for (i, m2) in self.m2.iter_mut().enumerate() {
+            *m2 = (*m2 - other.m2[i] - factor * delta[i] * delta[i]).max(0.);
+        }
+        match (&mut self.comoments, &other.comoments) {
+            (Some(comoments), Some(other)) => {
+                let dimension = delta.len();
+                for (index, comoment) in comoments.iter_mut().enumerate() {
+                    let (i, j) = (index / dimension, index % dimension);
+                    *comoment -= other[index] + factor * delta[i] * delta[j];
+                }
+            }
+            _ => self.comoments = None,
+        }
+    }
+
+    /// Applies a decay factor along with the weight
+    pub(crate) fn scale(&mut self, factor: f64) {
+        self.m2.iter_mut().for_each(|m2| *m2 *= factor);
+        if let Some(comoments) = &mut self.comoments {
+            comoments
+                .iter_mut()
+                .for_each(|comoment| *comoment *= factor);
+        }
+    }
+
+    /// Variances and covariance matrix reported for `spread`, for points of total weight
+    /// `weight`
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn summary(
+        &self,
+        weight: f64,
+        spread: Spread,
+    ) -> (Option<Vec<f64>>, Option<Vec<Vec<f64>>>) {
+        match spread {
+            Spread::Radius => (None, None),
+            Spread::Variance => (Some(self.variance(weight)), None),
+            Spread::Covariance => (Some(self.variance(weight)), self.covariance(weight)),
+        }
+    }
+
+    pub(crate) fn has_covariance(&self) -> bool {
+        self.comoments.is_some()
+    }
+
+    /// Sum of the M2 of every coordinate
+    pub(crate) fn total(&self) -> f64 {
+        self.m2.iter().sum()
+    }
+
+    /// (Biased) variance of every coordinate of points of total weight `weight`
+    fn variance(&self, weight: f64) -> Vec<f64> {
+        self.m2
+            .iter()
+            .map(|m2| {
+                if weight > 0. {
+                    (m2 / weight).max(0.)
+                } else {
+                    0.
+                }
+            })
+            .collect()
+    }
+
+    fn covariance(&self, weight: f64) -> Option<Vec<Vec<f64>>> {
+        let comoments = self.comoments.as_ref()?;
+        let dimension = self.m2.len();
+        Some(
+            comoments
+                .chunks(dimension.max(1))
+                .map(|row| {
+                    row.iter()
+                        .map(|comoment| if weight > 0. { comoment / weight } else { 0. })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        (self.m2.capacity() + self.comoments.as_ref().map_or(0, Vec::capacity))
+            * std::mem::size_of::<f64>()
+    }
+}
+
+/// Weighted mean and dispersion of every coordinate, kept in place of the linear and
+/// squared sums (CF1/CF2) of a micro-cluster. Those sums cancel out catastrophically for
+/// a tight cluster far from the origin; points added with Welford's update and summaries
+/// merged with Chan's formula do not.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Moments {
+    weight: f64,
+    mean: Vec<f64>,
+    dispersion: Dispersion,
+}
+
+impl Moments {
+    pub(crate) fn from_point(point: &[f64]) -> Self {
+        Moments {
+            weight: 1.,
+            mean: point.to_vec(),
+            dispersion: Dispersion::zero(point.len(), false),
+        }
+    }
+
+    /// Also keeps the co-moments, for `covariance`. Only for empty or single-point
+    /// summaries, whose co-moments are all zero.
+    pub(crate) fn with_covariance(mut self) -> Self {
+        if !self.dispersion.has_covariance() {
+            self.dispersion = Dispersion::zero(self.mean.len(), true);
+        }
+        self
+    }
+
+    pub(crate) fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    pub(crate) fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    /// Sum over the coordinates of the (biased) variance, the squared radius of a CF
+    pub(crate) fn total_variance(&self) -> f64 {
+        if self.weight <= 0. {
+            return 0.;
+        }
+        (self.dispersion.total() / self.weight).max(0.)
+    }
+
+    /// See `Dispersion::summary`. The covariance is `None` unless created
+    /// `with_covariance`.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn summary(&self, spread: Spread) -> (Option<Vec<f64>>, Option<Vec<Vec<f64>>>) {
+        self.dispersion.summary(self.weight, spread)
+    }
+
+    /// Weighted Welford update
+    pub(crate) fn add<P: PointView + ?Sized>(&mut self, point: &P, weight: f64) {
+        if self.weight <= 0. {
+            let covariance = self.dispersion.has_covariance();
+            *self = Moments::from_point(&point.to_dense());
+            if covariance {
+                *self = std::mem::take(self).with_covariance();
+            }
+            self.weight = weight;
+            return;
+        }
+        let total = self.weight + weight;
+        let delta: Vec<f64> = point
+            .to_dense()
+            .iter()
+            .zip(self.mean.iter())
+            .map(|(x, mean)| x - mean)
+            .collect();
+        self.dispersion
+            .merge(None, &delta, self.weight * weight / total);
+        let share = weight / total;
+        self.mean
+            .iter_mut()
+            .zip(delta.iter())
+            .for_each(|(mean, delta)| *mean += share * delta);
+        self.weight = total;
+    }
+
+    /// Chan's parallel merge
+    pub(crate) fn merge(&mut self, other: &Moments) {
+        if other.weight <= 0. {
+            return;
+        }
+        if self.weight <= 0. {
+            *self = other.clone();
+            return;
+        }
+        let total = self.weight + other.weight;
+        let delta: Vec<f64> = other
+            .mean
+            .iter()
+            .zip(self.mean.iter())
+            .map(|(other, mean)| other - mean)
+            .collect();
+        self.dispersion.merge(
+            Some(&other.dispersion),
+            &delta,
+            self.weight * other.weight / total,
+        );
+        let share = other.weight / total;
+        self.mean
+            .iter_mut()
+            .zip(delta.iter())
+            .for_each(|(mean, delta)| *mean += share * delta);
+        self.weight = total;
+    }
+
+    /// Applies a decay factor, which leaves the mean and variance as they are
+    pub(crate) fn scale(&mut self, factor: f64) {
+        self.weight *= factor;
+        self.dispersion.scale(factor);
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.mean.capacity() * std::mem::size_of::<f64>() + self.dispersion.memory_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moments() {
+        let points = [vec![1., 2.], vec![3., 2.], vec![5., 8.], vec![7., 0.]];
+        let mut all = Moments::default();
+        points
+            .iter()
+            .for_each(|point| all.add(point.as_slice(), 1.));
+        assert_eq!(all.weight(), 4.);
+        assert_eq!(all.mean(), &[4., 3.]);
+        // Variances 5 and 9
+        assert!((all.total_variance() - 14.).abs() < 1e-12);
+
+        let mut left = Moments::from_point(&points[0]);
+        left.add(points[1].as_slice(), 1.);
+        let mut right = Moments::from_point(&points[2]);
+        right.add(points[3].as_slice(), 1.);
+        left.merge(&right);
+        assert_eq!(left, all);
+
+        // A weight of 2 counts like the same point twice
+        let mut weighted = Moments::from_point(&points[0]);
+        weighted.add(points[1].as_slice(), 2.);
+        let mut twice = Moments::from_point(&points[0]);
+        twice.add(points[1].as_slice(), 1.);
+        twice.add(points[1].as_slice(), 1.);
+        assert!((weighted.total_variance() - twice.total_variance()).abs() < 1e-12);
+
+        all.scale(0.5);
+        assert_eq!(all.weight(), 2.);
+        assert!((all.total_variance() - 14.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_co_moments() {
+        // Points along the diagonal, plus one off it
+        let points = [vec![0., 0.], vec![1., 1.], vec![2., 2.], vec![3., 1.]];
+        let mut all = Moments::default().with_covariance();
+        points
+            .iter()
+            .for_each(|point| all.add(point.as_slice(), 1.));
+        assert_eq!(all.summary(Spread::Radius), (None, None));
+        assert_eq!(all.summary(Spread::Variance), (Some(vec![1.25, 0.5]), None));
+        let (variance, covariance) = all.summary(Spread::Covariance);
+        assert_eq!(variance, Some(vec![1.25, 0.5]));
+        assert_eq!(covariance, Some(vec![vec![1.25, 0.5], vec![0.5, 0.5]]));
+
+        let mut left = Moments::from_point(&points[0]).with_covariance();
+        left.add(points[1].as_slice(), 1.);
+        let mut right = Moments::from_point(&points[2]).with_covariance();
+        right.add(points[3].as_slice(), 1.);
+        left.merge(&right);
+        assert_eq!(left.summary(Spread::Covariance).1, covariance);
+
+        // Merging a summary without co-moments drops them
+        left.merge(&Moments::from_point(&points[0]));
+        assert_eq!(left.summary(Spread::Covariance).1, None);
+    }
+
+    #[test]
+    fn test_far_from_origin() {
+        let mut moments = Moments::default();
+        for i in 0..1_000 {
+            let noise = (i % 10) as f64 * 1e-3;
+            moments.add([1e9 + noise].as_slice(), 1.);
+        }
+        // Noise uniform over 0, 0.001, ..., 0.009 has variance 8.25e-6
+        assert!((moments.total_variance() - 8.25e-6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_algorithms_report_spread() {
+        use crate::algorithms::{
+            clustream::CluStream, denstream::Denstream, DataStreamClusteringAlgorithm,
+        };
+        let models: Vec<Box<dyn DataStreamClusteringAlgorithm>> = vec![
+            Box::new(
+                CluStream::new(1)
+                    .unwrap()
+                    .with_spread(Spread::Covariance)
+                    .unwrap(),
+            ),
+            Box::new(Denstream::new().with_spread(Spread::Covariance).unwrap()),
+            Box::new(
+                CluStream::new(1)
+                    .unwrap()
+                    .with_spread(Spread::Variance)
+                    .unwrap(),
+            ),
+        ];
+        for mut model in models {
+            // Spread along x only
+            for i in 0..300 {
+                model.insert(vec![(i % 5) as f64 * 0.2, 3.]).unwrap();
+            }
+            for element in model.clusters().unwrap() {
+                let variance = element.variance.unwrap();
+                assert!(variance[0] >= 0. && variance[1].abs() < 1e-12);
+                if let Some(covariance) = element.covariance {
+                    assert_eq!(covariance[0][0], variance[0]);
+                    assert!(covariance[0][1].abs() < 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_algorithms_far_from_origin() {
+        use crate::algorithms::{
+            birch::Birch, clustream::CluStream, denstream::Denstream, DataStreamClusteringAlgorithm,
+        };
+        let models: Vec<Box<dyn DataStreamClusteringAlgorithm>> = vec![
+            Box::new(Birch::new(1., 10, 2).unwrap()),
+            Box::new(CluStream::new(2).unwrap()),
+            Box::new(Denstream::new()),
+        ];
+        for mut model in models {
+            for i in 0..500 {
+                let noise = (i % 7) as f64 * 1e-3;
+                let offset = if i % 2 == 0 { 0. } else { 100. };
+                model
+                    .insert(vec![1e9 + offset + noise, 1e9 - noise])
+                    .unwrap();
+            }
+            let clusters = model.clusters().unwrap();
+            assert!(!clusters.is_empty(), "{}", model.name());
+            for element in clusters {
+                assert!(element.radius.is_finite(), "{}", model.name());
+                // The noise spreads points over less than 0.01
+                assert!(element.radius < 0.1, "{}: {}", model.name(), element.radius);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use super::{ClusteringError, MixedPoint, SparseVector};
+use serde::{Deserialize, Serialize};
+
+/// Shape of the points a model accepts. The dimension is either given at construction or
+/// fixed by the first accepted point, after which points of any other dimension are rejected.
+///
+/// The `with_schema` builders of the models and of `Preprocessed` set the schema before the
+/// first insert. Once inserts locked a model to a dimension, they fail for a schema of any
+/// other dimension (see `replace`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    dimension: Option<usize>,
+    feature_names: Option<Vec<String>>,
+    /// Number of categorical attributes of mixed records, fixed by the first one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    categorical_attributes: Option<usize>,
+}
+
+impl Schema {
+    /// Unlocked schema, the first valid point decides the dimension
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dimension(dimension: usize) -> Result<Self, ClusteringError> {
+        if dimension == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "dimension",
+                "must be at least 1",
+            ));
+        }
+        Ok(Schema {
+            dimension: Some(dimension),
+            feature_names: None,
+            categorical_attributes: None,
+        })
+    }
+
+    /// Locks the dimension to the number of names
+    pub fn with_feature_names(names: Vec<String>) -> Result<Self, ClusteringError> {
+        let mut schema = Self::with_dimension(names.len())?;
+        schema.feature_names = Some(names);
+        Ok(schema)
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    pub fn feature_names(&self) -> Option<&[String]> {
+        self.feature_names.as_deref()
+    }
+
+    pub fn categorical_attributes(&self) -> Option<usize> {
+        self.categorical_attributes
+    }
+
+    /// Takes over `schema`, unless this one is locked to another dimension. The number of
+    /// categorical attributes stays locked too.
+    pub(crate) fn replace(&mut self, schema: Schema) -> Result<(), ClusteringError> {
+        if let Some(dimension) = self.dimension.filter(|&d| schema.dimension != Some(d)) {
+            return Err(ClusteringError::invalid_parameter(
+                "schema",
+                format!("the dimension is already locked to {}", dimension),
+            ));
+        }
+        let categorical_attributes =
+            match (self.categorical_attributes, schema.categorical_attributes) {
+                (Some(locked), Some(other)) if locked != other => {
+                    return Err(ClusteringError::invalid_parameter(
+                        "schema",
+                        format!(
+                            "the number of categorical attributes is already locked to {}",
+                            locked
+                        ),
+                    ))
+                }
+                (locked, other) => other.or(locked),
+            };
+        *self = Schema {
+            categorical_attributes,
+            ..schema
+        };
+        Ok(())
+    }
+
+    /// Rejects empty points, non-finite coordinates and points of another dimension,
+    /// without locking an unlocked schema
+    pub fn check(&self, point: &[f64]) -> Result<(), ClusteringError> {
+        if point.is_empty() {
+            return Err(ClusteringError::EmptyPoint);
+        }
+        if let Some((index, value)) = point.iter().enumerate().find(|(_, x)| !x.is_finite()) {
+            return Err(ClusteringError::NonFinite {
+                index,
+                value: *value,
+            });
+        }
+        match self.dimension {
+            Some(expected) if expected != point.len() => Err(ClusteringError::DimensionMismatch {
+                expected,
+                found: point.len(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Like `check`, but an unlocked schema takes the dimension of the point
+    pub fn validate(&mut self, point: &[f64]) -> Result<(), ClusteringError> {
+        self.check(point)?;
+        self.dimension.get_or_insert(point.len());
+        Ok(())
+    }
+
+    /// `validate` for sparse points, whose coordinates are checked on construction
+    pub fn validate_sparse(&mut self, point: &SparseVector) -> Result<(), ClusteringError> {
+        match self.dimension {
+            Some(expected) if expected != point.dimension() => {
+                Err(ClusteringError::DimensionMismatch {
+                    expected,
+                    found: point.dimension(),
+                })
+            }
+            _ => {
+                self.dimension = Some(point.dimension());
+                Ok(())
+            }
+        }
+    }
+
+    /// `validate` for the numeric part of a mixed record, which also locks the number of
+    /// categorical attributes. Numeric-only points leave that number alone.
+    pub fn validate_mixed(&mut self, point: &MixedPoint) -> Result<(), ClusteringError> {
+        self.check(point.numeric())?;
+        let found = point.categorical().len();
+        match self.categorical_attributes {
+            Some(expected) if expected != found => {
+                Err(ClusteringError::CategoricalMismatch { expected, found })
+            }
+            _ => {
+                self.dimension.get_or_insert(point.numeric().len());
+                self.categorical_attributes = Some(found);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut schema = Schema::new();
+        assert_eq!(schema.validate(&[]), Err(ClusteringError::EmptyPoint));
+        assert!(matches!(
+            schema.validate(&[1., f64::NAN]),
+            Err(ClusteringError::NonFinite { index: 1, .. })
+        ));
+        assert_eq!(schema.dimension(), None);
+        assert!(schema.validate(&[1., 2.]).is_ok());
+        assert_eq!(
+            schema.validate(&[1., 2., 3.]),
+            Err(ClusteringError::DimensionMismatch {
+                expected: 2,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_feature_names() {
+        let schema = Schema::with_feature_names(vec!["x".to_string(), "y".to_string()]).unwrap();
+        assert_eq!(schema.dimension(), Some(2));
+        assert!(schema.check(&[1.]).is_err());
+        assert!(Schema::with_dimension(0).is_err());
+    }
+
+    #[test]
+    fn test_validate_mixed() {
+        let mut schema = Schema::new();
+        let record = |numeric: Vec<f64>, categorical: &[&str]| {
+            MixedPoint::new(
+                numeric,
+                categorical.iter().map(|value| value.to_string()).collect(),
+            )
+        };
+        assert!(schema
+            .validate_mixed(&record(vec![1.], &["tcp", "http"]))
+            .is_ok());
+        assert_eq!(schema.dimension(), Some(1));
+        assert_eq!(schema.categorical_attributes(), Some(2));
+        assert_eq!(
+            schema.validate_mixed(&record(vec![1.], &["tcp"])),
+            Err(ClusteringError::CategoricalMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert!(schema
+            .validate_mixed(&record(vec![1., 2.], &["tcp", "http"]))
+            .is_err());
+        assert!(schema.validate(&[3.]).is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+use super::{ClusteringError, Metric};
+use serde::{Deserialize, Serialize};
+
+/// Point with few non-zero coordinates, stored as sorted `(index, value)` pairs.
+/// Zeros are never stored, so `nnz()` is the number of non-zero coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparseVector {
+    dimension: usize,
+    indices: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl SparseVector {
+    /// Entries may come in any order, values of repeated indices are summed
+    pub fn new(dimension: usize, mut entries: Vec<(usize, f64)>) -> Result<Self, ClusteringError> {
+        if dimension == 0 {
+            return Err(ClusteringError::EmptyPoint);
+        }
+        if let Some((index, _)) = entries.iter().find(|(index, _)| *index >= dimension) {
+            return Err(ClusteringError::invalid_parameter(
+                "index",
+                format!("{} is out of range for dimension {}", index, dimension),
+            ));
+        }
+        if let Some((index, value)) = entries.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(ClusteringError::NonFinite {
+                index: *index,
+                value: *value,
+            });
+        }
+        entries.sort_by_key(|(index, _)| *index);
+        let mut vector = SparseVector {
+            dimension,
+            indices: Vec::with_capacity(entries.len()),
+            values: Vec::with_capacity(entries.len()),
+        };
+        for (index, value) in entries {
+            if vector.indices.last() == Some(&index) {
+                *vector.values.last_mut().unwrap() += value;
+            } else {
+                vector.indices.push(index);
+                vector.values.push(value);
+            }
+        }
+        vector.drop_zeros();
+        Ok(vector)
+    }
+
+    pub fn from_dense(point: &[f64]) -> Self {
+        let (indices, values) = point
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x != 0.)
+            .map(|(i, x)| (i, *x))
+            .unzip();
+        SparseVector {
+            dimension: point.len(),
+            indices,
+            values,
+        }
+    }
+
+    fn drop_zeros(&mut self) {
+        let mut kept = 0;
+        for i in 0..self.indices.len() {
+            if self.values[i] != 0. {
+                self.indices[kept] = self.indices[i];
+                self.values[kept] = self.values[i];
+                kept += 1;
+            }
+        }
+        self.indices.truncate(kept);
+        self.values.truncate(kept);
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.indices
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+
+    pub fn to_dense(&self) -> Vec<f64> {
+        let mut dense = vec![0.; self.dimension];
+        self.add_to(&mut dense, 1.);
+        dense
+    }
+
+    pub fn scaled(&self, factor: f64) -> SparseVector {
+        let mut scaled = SparseVector {
+            dimension: self.dimension,
+            indices: self.indices.clone(),
+            values: self.values.iter().map(|x| x * factor).collect(),
+        };
+        scaled.drop_zeros();
+        scaled
+    }
+
+    pub fn squared_norm(&self) -> f64 {
+        self.values.iter().map(|x| x * x).sum()
+    }
+
+    pub fn dot(&self, dense: &[f64]) -> f64 {
+        self.iter().map(|(i, x)| x * dense[i]).sum()
+    }
+
+    /// Walks both index lists once
+    pub fn dot_sparse(&self, other: &SparseVector) -> f64 {
+        let (mut i, mut j, mut dot) = (0, 0, 0.);
+        while i < self.nnz() && j < other.nnz() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    dot += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        dot
+    }
+
+    /// `dense += weight * self`, touching only the non-zero coordinates
+    pub fn add_to(&self, dense: &mut [f64], weight: f64) {
+        for (i, x) in self.iter() {
+            dense[i] += weight * x;
+        }
+    }
+
+    /// Sum of two sparse vectors of the same dimension
+    pub fn add(&self, other: &SparseVector) -> SparseVector {
+        let mut entries: Vec<(usize, f64)> = self.iter().chain(other.iter()).collect();
+        entries.sort_by_key(|(index, _)| *index);
+        let mut sum = SparseVector {
+            dimension: self.dimension.max(other.dimension),
+            indices: Vec::with_capacity(entries.len()),
+            values: Vec::with_capacity(entries.len()),
+        };
+        for (index, value) in entries {
+            if sum.indices.last() == Some(&index) {
+                *sum.values.last_mut().unwrap() += value;
+            } else {
+                sum.indices.push(index);
+                sum.values.push(value);
+            }
+        }
+        sum.drop_zeros();
+        sum
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.indices.capacity() * std::mem::size_of::<usize>()
+            + self.values.capacity() * std::mem::size_of::<f64>()
+    }
+}
+
+/// What the algorithms need from an incoming point, so they can take dense and sparse
+/// points through the same code path. `distance` only visits the non-zero coordinates of
+/// a sparse point; neighbour indexes and micro-cluster statistics take it as `to_dense`.
+pub trait PointView {
+    fn dimension(&self) -> usize;
+    fn to_dense(&self) -> Vec<f64>;
+    fn squared_norm(&self) -> f64;
+    /// Distance to a dense point
+    fn distance<D: Metric>(&self, metric: &D, other: &[f64]) -> f64;
+}
+
+impl PointView for [f64] {
+    fn dimension(&self) -> usize {
+        self.len()
+    }
+    fn to_dense(&self) -> Vec<f64> {
+        self.to_vec()
+    }
+    fn squared_norm(&self) -> f64 {
+        self.iter().map(|x| x * x).sum()
+    }
+    fn distance<D: Metric>(&self, metric: &D, other: &[f64]) -> f64 {
+        metric.distance(self, other)
+    }
+}
+
+impl PointView for SparseVector {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+    fn to_dense(&self) -> Vec<f64> {
+        SparseVector::to_dense(self)
+    }
+    fn squared_norm(&self) -> f64 {
+        SparseVector::squared_norm(self)
+    }
+    fn distance<D: Metric>(&self, metric: &D, other: &[f64]) -> f64 {
+        metric.sparse_distance(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Chebyshev, Cosine, Euclidean, Manhattan, SquaredEuclidean};
+
+    #[test]
+    fn test_new() {
+        let vector = SparseVector::new(10, vec![(7, 1.), (2, 3.), (7, 2.), (4, 0.)]).unwrap();
+        assert_eq!(vector.iter().collect::<Vec<_>>(), vec![(2, 3.), (7, 3.)]);
+        assert_eq!(vector.to_dense()[7], 3.);
+        assert!(SparseVector::new(10, vec![(10, 1.)]).is_err());
+        assert!(SparseVector::new(10, vec![(1, f64::NAN)]).is_err());
+        assert_eq!(SparseVector::from_dense(&vector.to_dense()), vector);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = SparseVector::new(6, vec![(0, 1.), (3, 2.)]).unwrap();
+        let b = SparseVector::new(6, vec![(3, -2.), (5, 4.)]).unwrap();
+        assert_eq!(a.dot_sparse(&b), -4.);
+        assert_eq!(a.dot(&b.to_dense()), -4.);
+        assert_eq!(a.add(&b).iter().collect::<Vec<_>>(), vec![(0, 1.), (5, 4.)]);
+    }
+
+    #[test]
+    fn test_sparse_distances_match_dense() {
+        let a = SparseVector::new(5, vec![(1, 3.), (4, -1.)]).unwrap();
+        let b = [0.5, 1., -2., 0., 2.];
+        let dense = a.to_dense();
+        let close = |x: f64, y: f64| (x - y).abs() < 1e-12;
+        assert!(close(
+            Euclidean.sparse_distance(&a, &b),
+            Euclidean.distance(&dense, &b)
+        ));
+        assert!(close(
+            SquaredEuclidean.sparse_distance(&a, &b),
+            SquaredEuclidean.distance(&dense, &b)
+        ));
+        assert!(close(
+            Manhattan.sparse_distance(&a, &b),
+            Manhattan.distance(&dense, &b)
+        ));
+        assert!(close(
+            Chebyshev.sparse_distance(&a, &b),
+            Chebyshev.distance(&dense, &b)
+        ));
+        assert!(close(
+            Cosine.sparse_distance(&a, &b),
+            Cosine.distance(&dense, &b)
+        ));
+    }
+
+    #[test]
+    fn test_algorithms_accept_sparse_points() {
+        use crate::algorithms::{
+            birch::Birch, denstream::Denstream, ClusteringElement, DataStreamClusteringAlgorithm,
+        };
+        let points: Vec<SparseVector> = (0..300)
+            .map(|i| {
+                let offset = if i % 2 == 0 { 0 } else { 50 };
+                SparseVector::new(100, vec![(offset + i % 3, 1. + (i % 5) as f64 * 0.1)]).unwrap()
+            })
+            .collect();
+        let centers = |clusters: Vec<ClusteringElement>| {
+            clusters.into_iter().map(|c| c.center).collect::<Vec<_>>()
+        };
+        let models: Vec<(
+            Box<dyn DataStreamClusteringAlgorithm>,
+            Box<dyn DataStreamClusteringAlgorithm>,
+        )> = vec![
+            (
+                Box::new(Birch::new(1., 10, 2).unwrap()),
+                Box::new(Birch::new(1., 10, 2).unwrap()),
+            ),
+            (Box::new(Denstream::new()), Box::new(Denstream::new())),
+        ];
+        for (mut sparse, mut dense) in models {
+            for point in points.iter() {
+                sparse.insert_sparse(point).unwrap();
+                dense.insert(point.to_dense()).unwrap();
+            }
+            assert_eq!(sparse.stats().micro_clusters, dense.stats().micro_clusters);
+            let (sparse, dense) = (
+                centers(sparse.clusters().unwrap()),
+                centers(dense.clusters().unwrap()),
+            );
+            assert!(!sparse.is_empty());
+            for (a, b) in sparse.iter().zip(dense.iter()) {
+                assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-9));
+            }
+        }
+        let mut birch = Birch::new(1., 10, 2).unwrap();
+        birch.insert(vec![0.; 3]).unwrap();
+        assert!(birch.insert_sparse(&points[0]).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Structured report of an algorithm's internal state
+#[derive(Debug, Clone, Serialize)]
+pub struct AlgorithmStats {
+    /// Live micro-clusters (leaf entries for BIRCH)
+    pub micro_clusters: usize,
+    /// Sum of micro-cluster weights, decayed for DenStream
+    pub total_weight: f64,
+    /// Rough estimate of the memory held by the model
+    pub memory_bytes: usize,
+    pub details: AlgorithmDetails,
+    /// Set when the algorithm runs behind a sampler
+    pub sampler: Option<SamplerStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum AlgorithmDetails {
+    Birch {
+        nodes: usize,
+        leaves: usize,
+        /// Levels from the root down to the leaves, a lone leaf has depth 1
+        depth: usize,
+        /// Children per non-leaf node
+        mean_fan_out: f64,
+        max_fan_out: usize,
+        splits: usize,
+        /// Current threshold, raised by every rebuild
+        threshold: f64,
+        /// Rebuilds forced by the node limit
+        rebuilds: usize,
+        /// Potential outliers moved out of the tree, in memory or on disk
+        outliers: usize,
+        /// Entries removed after fading below the decay floor
+        faded: usize,
+    },
+    CluStream {
+        merges: usize,
+        replacements: usize,
+        /// Snapshots currently stored in the pyramidal time frame, indexed by order
+        snapshots_per_order: Vec<usize>,
+    },
+    DenStream {
+        potential_micro_clusters: usize,
+        outlier_micro_clusters: usize,
+        potential_pruned: usize,
+        outliers_pruned: usize,
+        promotions: usize,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SamplerStats {
+    /// Points passed on to the wrapped algorithm
+    pub accepted: usize,
+    pub dropped: usize,
+}
+
+impl fmt::Display for AlgorithmStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "micro_clusters={} total_weight={:.2} memory_bytes={}",
+            self.micro_clusters, self.total_weight, self.memory_bytes
+        )?;
+        match &self.details {
+            AlgorithmDetails::Birch {
+                nodes,
+                leaves,
+                depth,
+                mean_fan_out,
+                max_fan_out,
+                splits,
+                threshold,
+                rebuilds,
+                outliers,
+                faded,
+            } => write!(
+                f,
+                " nodes={} leaves={} depth={} mean_fan_out={:.2} max_fan_out={} splits={} threshold={} rebuilds={} outliers={} faded={}",
+                nodes, leaves, depth, mean_fan_out, max_fan_out, splits, threshold, rebuilds, outliers, faded
+            )?,
+            AlgorithmDetails::CluStream {
+                merges,
+                replacements,
+                snapshots_per_order,
+            } => write!(
+                f,
+                " merges={} replacements={} snapshots_per_order={:?}",
+                merges, replacements, snapshots_per_order
+            )?,
+            AlgorithmDetails::DenStream {
+                potential_micro_clusters,
+                outlier_micro_clusters,
+                potential_pruned,
+                outliers_pruned,
+                promotions,
+            } => write!(
+                f,
+                " potential={} outliers={} potential_pruned={} outliers_pruned={} promotions={}",
+                potential_micro_clusters,
+                outlier_micro_clusters,
+                potential_pruned,
+                outliers_pruned,
+                promotions
+            )?,
+        }
+        if let Some(sampler) = &self.sampler {
+            write!(
+                f,
+                " accepted={} dropped={}",
+                sampler.accepted, sampler.dropped
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,22 @@
-use super::algorithms::{
-    birch::Birch, clustream::CluStream, denstream::Denstream, ssq, DataStreamClusteringAlgorithm,
+use clustermancer::algorithms::{
+    birch::Birch, clustream::CluStream, denstream::Denstream, predict, ssq, ClusteringError,
+    DataStreamClusteringAlgorithm, Euclidean, NeighbourSearch,
 };
-use super::samplers::{
+use clustermancer::formats::arff::ArffReader;
+use clustermancer::formats::moa::{
+    cluster_count, point_ssq, purity, write_clusters_arff, EvaluationCurveWriter, EvaluationRow,
+};
+use clustermancer::samplers::{
     kmeans_dynamic_sampler::KMeansDynamicSampler, static_sampler::StaticSampler, Sampler,
 };
 
 use csv::{ReaderBuilder, Writer};
-use std::fs::File;
-use std::time::Instant;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 const PROCESSING_RATE_DATASETS: [&str; 1] = ["benchmark_data/synthetic/random_5k_4d.csv"];
 
@@ -22,29 +31,52 @@ const DIMENTIONALITY_DATA_SETS: [&str; 8] = [
     "benchmark_data/synthetic/random_5k_80d.csv",
 ];
 
-type AlorithmFactory = Box<dyn Fn() -> Box<dyn DataStreamClusteringAlgorithm>>;
-type SamplerFactory = Box<dyn Fn(Box<dyn DataStreamClusteringAlgorithm>) -> Box<dyn Sampler>>;
+const ARFF_DATA_DIR: &str = "benchmark_data/arff";
+/// Same default evaluation frequency as MOA's clustering tasks
+const MOA_EVALUATION_FREQUENCY: usize = 1_000;
+
+const NEIGHBOUR_SEARCHES: [(NeighbourSearch, &str); 3] = [
+    (NeighbourSearch::Linear, "linear"),
+    (NeighbourSearch::KdTree, "kdtree"),
+    (NeighbourSearch::Graph, "graph"),
+];
+
+type AlorithmFactory =
+    Box<dyn Fn() -> Result<Box<dyn DataStreamClusteringAlgorithm>, ClusteringError>>;
+type SamplerFactory = Box<
+    dyn Fn(Box<dyn DataStreamClusteringAlgorithm>) -> Result<Box<dyn Sampler>, ClusteringError>,
+>;
+pub type BenchmarkResult = Result<(), Box<dyn Error>>;
+
+/// Like `File::open`, but the error names the file
+fn open<P: AsRef<Path>>(path: P) -> Result<File, Box<dyn Error>> {
+    let path = path.as_ref();
+    File::open(path).map_err(|err| format!("{}: {}", path.display(), err).into())
+}
+
+fn create<P: AsRef<Path>>(path: P) -> Result<File, Box<dyn Error>> {
+    let path = path.as_ref();
+    File::create(path).map_err(|err| format!("{}: {}", path.display(), err).into())
+}
 
-pub fn processing_rate_benchmark() {
+pub fn processing_rate_benchmark() -> BenchmarkResult {
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(2., 50, 5))),
-        Box::new(|| Box::new(CluStream::new(5))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(2., 50, 5)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(5)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
 
     // Processing rate benchmark
     let d = [4];
-    let processing_rate_file = File::create("./benchmark_results/processing_rate.csv").unwrap();
+    let processing_rate_file = create("./benchmark_results/processing_rate.csv")?;
     let mut writer = Writer::from_writer(processing_rate_file);
-    writer
-        .write_record(["algorithm", "dimention", "interval", "record_no"])
-        .unwrap();
+    writer.write_record(["algorithm", "dimention", "interval", "record_no"])?;
     for (d_idx, data_set) in PROCESSING_RATE_DATASETS.iter().enumerate() {
         for _ in 0..8 {
             for factory in algorithm_factories.iter() {
-                let data_file = File::open(data_set).unwrap();
+                let data_file = open(data_set)?;
                 let mut rdr = ReaderBuilder::new().from_reader(data_file);
-                let mut algorithm = factory();
+                let mut algorithm = factory()?;
                 println!(
                     "ProcessingRateAlgoBenchmark(Algorithm: {:?} Dataset: {:?})",
                     algorithm.name(),
@@ -53,13 +85,12 @@ pub fn processing_rate_benchmark() {
                 let mut start = Instant::now();
                 let mut results: Vec<(String, String, String, String)> = Vec::new();
                 for (record_no, result) in rdr.records().enumerate() {
-                    let data: Vec<f64> = result
-                        .unwrap()
+                    let data: Vec<f64> = result?
                         .iter()
                         .take(d[d_idx])
-                        .map(|s| s.parse().unwrap())
-                        .collect();
-                    algorithm.insert(data);
+                        .map(|s| s.parse())
+                        .collect::<Result<_, _>>()?;
+                    algorithm.insert(data)?;
                     if record_no % 10_000 == 0 {
                         results.push((
                             algorithm.name(),
@@ -77,36 +108,32 @@ pub fn processing_rate_benchmark() {
                     "1000000".to_string(),
                 ));
                 for result in results.iter() {
-                    writer
-                        .write_record([&result.0, &result.1, &result.2, &result.3])
-                        .unwrap();
+                    writer.write_record([&result.0, &result.1, &result.2, &result.3])?;
                 }
                 drop(algorithm);
             }
         }
     }
-    writer.flush().unwrap();
+    writer.flush()?;
+    Ok(())
 }
 
-pub fn dimentionality_processing_time_benchmark() {
+pub fn dimentionality_processing_time_benchmark() -> BenchmarkResult {
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(5., 50, 5))),
-        Box::new(|| Box::new(CluStream::new(5))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(5., 50, 5)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(5)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
     // Dimentionality processing rate benchmark
     let d = [2, 4, 5, 10, 20, 40, 60, 80];
-    let dimentionality_file =
-        File::create("./benchmark_results/dimentionality_processing_time.csv").unwrap();
+    let dimentionality_file = create("./benchmark_results/dimentionality_processing_time.csv")?;
     let mut writer = Writer::from_writer(dimentionality_file);
-    writer
-        .write_record(["algorithm", "dimention", "processing_time"])
-        .unwrap();
+    writer.write_record(["algorithm", "dimention", "processing_time"])?;
     for (d_idx, data_set) in DIMENTIONALITY_DATA_SETS.iter().enumerate() {
         for factory in algorithm_factories.iter() {
-            let data_file = File::open(data_set).unwrap();
+            let data_file = open(data_set)?;
             let mut rdr = ReaderBuilder::new().from_reader(data_file);
-            let mut algorithm = factory();
+            let mut algorithm = factory()?;
             println!(
                 "DimentionalityAlgoBenchmark(Algorithm: {:?} Dataset: {:?})",
                 algorithm.name(),
@@ -114,140 +141,143 @@ pub fn dimentionality_processing_time_benchmark() {
             );
             let start = Instant::now();
             for result in rdr.records() {
-                let data: Vec<f64> = result
-                    .unwrap()
+                let data: Vec<f64> = result?
                     .iter()
                     .take(d[d_idx])
-                    .map(|s| s.parse().unwrap())
-                    .collect();
-                algorithm.insert(data);
+                    .map(|s| s.parse())
+                    .collect::<Result<_, _>>()?;
+                algorithm.insert(data)?;
             }
-            writer
-                .write_record(&[
-                    algorithm.name(),
-                    d[d_idx].to_string(),
-                    start.elapsed().as_secs_f64().to_string(),
-                ])
-                .unwrap();
+            writer.write_record(&[
+                algorithm.name(),
+                d[d_idx].to_string(),
+                start.elapsed().as_secs_f64().to_string(),
+            ])?;
             drop(algorithm);
         }
     }
-    writer.flush().unwrap();
+    writer.flush()?;
+    Ok(())
 }
 
-pub fn real_quality_benchmark() {
+pub fn real_quality_benchmark() -> BenchmarkResult {
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(2., 50, 2))),
-        Box::new(|| Box::new(CluStream::new(2))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(2., 50, 2)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(2)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
 
     let data_set = "benchmark_data/real/converted_RT_IOT2022_10.csv";
-    let output_file = File::create("./benchmark_results/RT_IOT2022_quality.csv").unwrap();
+    let output_file = create("./benchmark_results/RT_IOT2022_quality.csv")?;
     let mut writer = Writer::from_writer(output_file);
-    writer.write_record(["algorithm", "SSQ"]).unwrap();
+    writer.write_record(["algorithm", "SSQ"])?;
     for factory in algorithm_factories.iter() {
-        let mut algorithm = factory();
+        let mut algorithm = factory()?;
         // input
-        let data_file = File::open(data_set).unwrap();
+        let data_file = open(data_set)?;
         let mut rdr = ReaderBuilder::new().from_reader(data_file);
         // demo
         let start = Instant::now();
         for result in rdr.records() {
-            let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-            algorithm.insert(record);
+            let record: Vec<f64> = result?
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?;
+            algorithm.insert(record)?;
         }
-        let clusters = algorithm.clusters();
+        let clusters = algorithm.clusters()?;
         let ssq = ssq(&clusters);
-        writer
-            .write_record(&[algorithm.name(), ssq.to_string()])
-            .unwrap();
+        writer.write_record(&[algorithm.name(), ssq.to_string()])?;
         println!(
-            "DemoAlgoBenchmark(Algorithm: {:?} Dataset: RT_IOT2022, Time: {:?})",
+            "DemoAlgoBenchmark(Algorithm: {:?} Dataset: RT_IOT2022, Time: {:?}, Stats: {})",
             algorithm.name(),
-            start.elapsed()
+            start.elapsed(),
+            algorithm.stats()
         );
     }
+    Ok(())
 }
-pub fn synthetic_quality_benchmark() {
+pub fn synthetic_quality_benchmark() -> BenchmarkResult {
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(5., 50, 5))),
-        Box::new(|| Box::new(CluStream::new(5))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(5., 50, 5)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(5)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
 
     let data_set = "benchmark_data/synthetic/random_5k_4d.csv";
-    let output_file = File::create("./benchmark_results/synthetic_quality.csv").unwrap();
+    let output_file = create("./benchmark_results/synthetic_quality.csv")?;
     let mut writer = Writer::from_writer(output_file);
-    writer.write_record(["algorithm", "SSQ"]).unwrap();
+    writer.write_record(["algorithm", "SSQ"])?;
 
     for factory in algorithm_factories.iter() {
-        let mut algorithm = factory();
-        let data_file = File::open(data_set).unwrap();
+        let mut algorithm = factory()?;
+        let data_file = open(data_set)?;
         let mut rdr = ReaderBuilder::new().from_reader(data_file);
 
         let start = Instant::now();
         for result in rdr.records() {
-            let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-            algorithm.insert(record);
+            let record: Vec<f64> = result?
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?;
+            algorithm.insert(record)?;
         }
-        let clusters = algorithm.clusters();
+        let clusters = algorithm.clusters()?;
         let ssq = ssq(&clusters);
-        writer
-            .write_record(&[algorithm.name(), ssq.to_string()])
-            .unwrap();
+        writer.write_record(&[algorithm.name(), ssq.to_string()])?;
         println!(
-            "SyntheticQualityBenchmark(Algorithm: {:?} Dataset: random_5k_4d, Time: {:?})",
+            "SyntheticQualityBenchmark(Algorithm: {:?} Dataset: random_5k_4d, Time: {:?}, Stats: {})",
             algorithm.name(),
-            start.elapsed()
+            start.elapsed(),
+            algorithm.stats()
         );
     }
-    writer.flush().unwrap();
+    writer.flush()?;
+    Ok(())
 }
 
-pub fn demo_algorithms() {
+pub fn demo_algorithms() -> BenchmarkResult {
     let demo_name = "blobs";
     let data_path = "demos/blobs_demo.csv";
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(1.0, 50, 3))),
-        Box::new(|| Box::new(CluStream::new(3))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(1.0, 50, 3)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(3)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
     for factory in algorithm_factories.iter() {
-        let mut algorithm = factory();
+        let mut algorithm = factory()?;
         // output
-        let output_file = File::create(format!(
+        let output_file = create(format!(
             "./demo_results/{}_{}.csv",
             demo_name,
             algorithm.name()
-        ))
-        .unwrap();
+        ))?;
         let mut writer = Writer::from_writer(output_file);
-        writer
-            .write_record(["center", "radius", "cluster"])
-            .unwrap();
+        writer.write_record(["center", "radius", "cluster", "weight"])?;
         // input
-        let data_file = File::open(data_path).unwrap();
+        let data_file = open(data_path)?;
         let mut rdr = ReaderBuilder::new().from_reader(data_file);
         // demo
         let start = Instant::now();
         for result in rdr.records() {
-            let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-            algorithm.insert(record);
+            let record: Vec<f64> = result?
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?;
+            algorithm.insert(record)?;
         }
-        for cluster in algorithm.clusters() {
-            writer
-                .write_record(&[
-                    cluster
-                        .center
-                        .iter()
-                        .map(|f| f.to_string())
-                        .collect::<Vec<String>>()
-                        .join(","),
-                    cluster.radius.to_string(),
-                    cluster.cluster.to_string(),
-                ])
-                .unwrap();
+        for cluster in algorithm.clusters()? {
+            writer.write_record(&[
+                cluster
+                    .center
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+                cluster.radius.to_string(),
+                cluster.cluster.to_string(),
+                cluster.weight.to_string(),
+            ])?;
         }
         println!(
             "DemoAlgoBenchmark(Algorithm: {:?} Dataset: {:?}, Time: {:?})",
@@ -260,46 +290,45 @@ pub fn demo_algorithms() {
     let demo_names = ["circles", "moons"];
     let data_paths = ["demos/circles_demo.csv", "demos/moon_demo.csv"];
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(1.0, 50, 2))),
-        Box::new(|| Box::new(CluStream::new(2))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(1.0, 50, 2)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(2)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
     for (i, data_path) in data_paths.iter().enumerate() {
         for factory in algorithm_factories.iter() {
-            let mut algorithm = factory();
+            let mut algorithm = factory()?;
             // output
-            let output_file = File::create(format!(
+            let output_file = create(format!(
                 "./demo_results/{}_{}.csv",
                 demo_names[i],
                 algorithm.name()
-            ))
-            .unwrap();
+            ))?;
             let mut writer = Writer::from_writer(output_file);
-            writer
-                .write_record(["center", "radius", "cluster"])
-                .unwrap();
+            writer.write_record(["center", "radius", "cluster", "weight"])?;
             // input
-            let data_file = File::open(data_path).unwrap();
+            let data_file = open(data_path)?;
             let mut rdr = ReaderBuilder::new().from_reader(data_file);
             // demo
             let start = Instant::now();
             for result in rdr.records() {
-                let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-                algorithm.insert(record);
+                let record: Vec<f64> = result?
+                    .iter()
+                    .map(|s| s.parse())
+                    .collect::<Result<_, _>>()?;
+                algorithm.insert(record)?;
             }
-            for cluster in algorithm.clusters() {
-                writer
-                    .write_record(&[
-                        cluster
-                            .center
-                            .iter()
-                            .map(|f| f.to_string())
-                            .collect::<Vec<String>>()
-                            .join(","),
-                        cluster.radius.to_string(),
-                        cluster.cluster.to_string(),
-                    ])
-                    .unwrap();
+            for cluster in algorithm.clusters()? {
+                writer.write_record(&[
+                    cluster
+                        .center
+                        .iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<String>>()
+                        .join(","),
+                    cluster.radius.to_string(),
+                    cluster.cluster.to_string(),
+                    cluster.weight.to_string(),
+                ])?;
             }
             println!(
                 "DemoAlgoBenchmark(Algorithm: {:?} Dataset: {:?}, Time: {:?})",
@@ -309,40 +338,38 @@ pub fn demo_algorithms() {
             );
         }
     }
+    Ok(())
 }
 
-pub fn processing_rate_samplers_benchmark() {
+pub fn processing_rate_samplers_benchmark() -> BenchmarkResult {
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(3., 50, 5))),
-        Box::new(|| Box::new(CluStream::new(5))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(3., 50, 5)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(5)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
 
     let sampler_factories: Vec<SamplerFactory> = vec![
         // Box::new(|algorithm| Box::new(UniformSampler::new(algorithm))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.3))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.1))),
-        Box::new(|algorithm| Box::new(KMeansDynamicSampler::new(algorithm, 5))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.3)?))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.1)?))),
+        Box::new(|algorithm| Ok(Box::new(KMeansDynamicSampler::new(algorithm, 5)?))),
     ];
 
     let d = [4];
-    let processing_rate_file =
-        File::create("./benchmark_results/processing_rate_samplers.csv").unwrap();
+    let processing_rate_file = create("./benchmark_results/processing_rate_samplers.csv")?;
     let mut pr_writer = Writer::from_writer(processing_rate_file);
-    pr_writer
-        .write_record(["algorithm", "dimention", "interval", "record_no"])
-        .unwrap();
-    let quality_file = File::create("./benchmark_results/synthetic_quality_samplers.csv").unwrap();
+    pr_writer.write_record(["algorithm", "dimention", "interval", "record_no"])?;
+    let quality_file = create("./benchmark_results/synthetic_quality_samplers.csv")?;
     let mut quality_writer = Writer::from_writer(quality_file);
-    quality_writer.write_record(["algorithm", "SSQ"]).unwrap();
+    quality_writer.write_record(["algorithm", "SSQ"])?;
     for (d_idx, data_set) in PROCESSING_RATE_DATASETS.iter().enumerate() {
         for _ in 0..2 {
             for factory in algorithm_factories.iter() {
                 for sampler_factory in sampler_factories.iter() {
-                    let data_file = File::open(data_set).unwrap();
+                    let data_file = open(data_set)?;
                     let mut rdr = ReaderBuilder::new().from_reader(data_file);
-                    let algorithm = factory();
-                    let mut sampler = sampler_factory(algorithm);
+                    let algorithm = factory()?;
+                    let mut sampler = sampler_factory(algorithm)?;
                     let name = sampler.name();
                     println!(
                         "ProcessingRateSamplerBenchmark(Algorithm: {:?}, Dataset: {:?})",
@@ -352,13 +379,12 @@ pub fn processing_rate_samplers_benchmark() {
                     let mut start = Instant::now();
                     let mut results: Vec<(String, String, String, String)> = Vec::new();
                     for (record_no, result) in rdr.records().enumerate() {
-                        let data: Vec<f64> = result
-                            .unwrap()
+                        let data: Vec<f64> = result?
                             .iter()
                             .take(d[d_idx])
-                            .map(|s| s.parse().unwrap())
-                            .collect();
-                        sampler.insert(data);
+                            .map(|s| s.parse())
+                            .collect::<Result<_, _>>()?;
+                        sampler.insert(data)?;
                         if record_no % 10_000 == 0 {
                             results.push((
                                 name.clone(),
@@ -376,76 +402,68 @@ pub fn processing_rate_samplers_benchmark() {
                         "1000000".to_string(),
                     ));
                     for result in results.iter() {
-                        pr_writer
-                            .write_record([&result.0, &result.1, &result.2, &result.3])
-                            .unwrap();
+                        pr_writer.write_record([&result.0, &result.1, &result.2, &result.3])?;
                     }
-                    let clusters = sampler.clusters();
+                    let clusters = sampler.clusters()?;
                     let ssq = ssq(&clusters);
-                    quality_writer
-                        .write_record([name.as_str(), ssq.to_string().as_str()])
-                        .unwrap();
+                    quality_writer.write_record([name.as_str(), ssq.to_string().as_str()])?;
                     drop(sampler);
                 }
             }
         }
     }
-    pr_writer.flush().unwrap();
-    quality_writer.flush().unwrap();
+    pr_writer.flush()?;
+    quality_writer.flush()?;
+    Ok(())
 }
 
-pub fn demo_samplers() {
+pub fn demo_samplers() -> BenchmarkResult {
     let demo_name = "blobs";
     let data_path = "demos/blobs_demo.csv";
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(1.0, 50, 3))),
-        Box::new(|| Box::new(CluStream::new(3))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(1.0, 50, 3)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(3)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
     let sampler_factories: Vec<SamplerFactory> = vec![
         // Box::new(|algorithm| Box::new(UniformSampler::new(algorithm))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.3))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.1))),
-        Box::new(|algorithm| Box::new(KMeansDynamicSampler::new(algorithm, 3))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.3)?))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.1)?))),
+        Box::new(|algorithm| Ok(Box::new(KMeansDynamicSampler::new(algorithm, 3)?))),
     ];
     for factory in algorithm_factories.iter() {
         for sampler_factory in sampler_factories.iter() {
-            let algorithm = factory();
-            let mut sampler = sampler_factory(algorithm);
+            let algorithm = factory()?;
+            let mut sampler = sampler_factory(algorithm)?;
             let name = sampler.name();
             // output
-            let output_file = File::create(format!(
-                "./demo_results/{}_{}.csv",
-                demo_name,
-                name
-            ))
-            .unwrap();
+            let output_file = create(format!("./demo_results/{}_{}.csv", demo_name, name))?;
             let mut writer = Writer::from_writer(output_file);
-            writer
-                .write_record(["center", "radius", "cluster"])
-                .unwrap();
+            writer.write_record(["center", "radius", "cluster", "weight"])?;
             // input
-            let data_file = File::open(data_path).unwrap();
+            let data_file = open(data_path)?;
             let mut rdr = ReaderBuilder::new().from_reader(data_file);
             // demo
             let start = Instant::now();
             for result in rdr.records() {
-                let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-                sampler.insert(record);
+                let record: Vec<f64> = result?
+                    .iter()
+                    .map(|s| s.parse())
+                    .collect::<Result<_, _>>()?;
+                sampler.insert(record)?;
             }
-            for cluster in sampler.clusters() {
-                writer
-                    .write_record(&[
-                        cluster
-                            .center
-                            .iter()
-                            .map(|f| f.to_string())
-                            .collect::<Vec<String>>()
-                            .join(","),
-                        cluster.radius.to_string(),
-                        cluster.cluster.to_string(),
-                    ])
-                    .unwrap();
+            for cluster in sampler.clusters()? {
+                writer.write_record(&[
+                    cluster
+                        .center
+                        .iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<String>>()
+                        .join(","),
+                    cluster.radius.to_string(),
+                    cluster.cluster.to_string(),
+                    cluster.weight.to_string(),
+                ])?;
             }
             println!(
                 "DemoSamplerBenchmark(Sampler: {:?} Dataset: {:?}, Time: {:?})",
@@ -456,57 +474,52 @@ pub fn demo_samplers() {
         }
     }
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(1.0, 50, 2))),
-        Box::new(|| Box::new(CluStream::new(2))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(1.0, 50, 2)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(2)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
     let sampler_factories: Vec<SamplerFactory> = vec![
         // Box::new(|algorithm| Box::new(UniformSampler::new(algorithm))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.3))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.1))),
-        Box::new(|algorithm| Box::new(KMeansDynamicSampler::new(algorithm, 2))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.3)?))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.1)?))),
+        Box::new(|algorithm| Ok(Box::new(KMeansDynamicSampler::new(algorithm, 2)?))),
     ];
     let demo_names = ["circles", "moons"];
     let data_paths = ["demos/circles_demo.csv", "demos/moon_demo.csv"];
     for (i, data_path) in data_paths.iter().enumerate() {
         for factory in algorithm_factories.iter() {
             for sampler_factory in sampler_factories.iter() {
-                let algorithm = factory();
-                let mut sampler = sampler_factory(algorithm);
+                let algorithm = factory()?;
+                let mut sampler = sampler_factory(algorithm)?;
                 let name = sampler.name();
                 // output
-                let output_file = File::create(format!(
-                    "./demo_results/{}_{}.csv",
-                    demo_names[i],
-                    name
-                ))
-                .unwrap();
+                let output_file = create(format!("./demo_results/{}_{}.csv", demo_names[i], name))?;
                 let mut writer = Writer::from_writer(output_file);
-                writer
-                    .write_record(["center", "radius", "cluster"])
-                    .unwrap();
+                writer.write_record(["center", "radius", "cluster", "weight"])?;
                 // input
-                let data_file = File::open(data_path).unwrap();
+                let data_file = open(data_path)?;
                 let mut rdr = ReaderBuilder::new().from_reader(data_file);
                 // demo
                 let start = Instant::now();
                 for result in rdr.records() {
-                    let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-                    sampler.insert(record);
+                    let record: Vec<f64> = result?
+                        .iter()
+                        .map(|s| s.parse())
+                        .collect::<Result<_, _>>()?;
+                    sampler.insert(record)?;
                 }
-                for cluster in sampler.clusters() {
-                    writer
-                        .write_record(&[
-                            cluster
-                                .center
-                                .iter()
-                                .map(|f| f.to_string())
-                                .collect::<Vec<String>>()
-                                .join(","),
-                            cluster.radius.to_string(),
-                            cluster.cluster.to_string(),
-                        ])
-                        .unwrap();
+                for cluster in sampler.clusters()? {
+                    writer.write_record(&[
+                        cluster
+                            .center
+                            .iter()
+                            .map(|f| f.to_string())
+                            .collect::<Vec<String>>()
+                            .join(","),
+                        cluster.radius.to_string(),
+                        cluster.cluster.to_string(),
+                        cluster.weight.to_string(),
+                    ])?;
                 }
                 println!(
                     "DemoSamplerBenchmark(Sampler: {:?} Dataset: {:?}, Time: {:?})",
@@ -517,51 +530,262 @@ pub fn demo_samplers() {
             }
         }
     }
+    Ok(())
 }
 
-pub fn samplers_real_quality_benchmark(){
+pub fn samplers_real_quality_benchmark() -> BenchmarkResult {
     let algorithm_factories: Vec<AlorithmFactory> = vec![
-        Box::new(|| Box::new(Birch::new(2., 50, 2))),
-        Box::new(|| Box::new(CluStream::new(2))),
-        Box::new(|| Box::new(Denstream::new())),
+        Box::new(|| Ok(Box::new(Birch::new(2., 50, 2)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(2)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
     ];
 
     let sampler_factories: Vec<SamplerFactory> = vec![
         // Box::new(|algorithm| Box::new(UniformSampler::new(algorithm))),
-        Box::new(|algorithm| Box::new(StaticSampler::new(algorithm, 0.3))),
-        Box::new(|algorithm| Box::new(KMeansDynamicSampler::new(algorithm, 2))),
+        Box::new(|algorithm| Ok(Box::new(StaticSampler::new(algorithm, 0.3)?))),
+        Box::new(|algorithm| Ok(Box::new(KMeansDynamicSampler::new(algorithm, 2)?))),
     ];
 
     let data_set = "benchmark_data/real/converted_RT_IOT2022_10.csv";
-    let output_file = File::create("./benchmark_results/real_quality_samplers.csv").unwrap();
+    let output_file = create("./benchmark_results/real_quality_samplers.csv")?;
     let mut writer = Writer::from_writer(output_file);
-    writer.write_record(["algorithm", "sampler", "SSQ"]).unwrap();
+    writer.write_record(["algorithm", "sampler", "SSQ"])?;
 
     for factory in algorithm_factories.iter() {
         for sampler_factory in sampler_factories.iter() {
-            let algorithm = factory();
-            let mut sampler = sampler_factory(algorithm);
+            let algorithm = factory()?;
+            let mut sampler = sampler_factory(algorithm)?;
             let name = sampler.name();
             // input
-            let data_file = File::open(data_set).unwrap();
+            let data_file = open(data_set)?;
             let mut rdr = ReaderBuilder::new().from_reader(data_file);
             // demo
             let start = Instant::now();
             for result in rdr.records() {
-                let record: Vec<f64> = result.unwrap().iter().map(|s| s.parse().unwrap()).collect();
-                sampler.insert(record);
+                let record: Vec<f64> = result?
+                    .iter()
+                    .map(|s| s.parse())
+                    .collect::<Result<_, _>>()?;
+                sampler.insert(record)?;
             }
-            let clusters = sampler.clusters();
+            let clusters = sampler.clusters()?;
             let ssq = ssq(&clusters);
-            writer
-                .write_record(&[sampler.name(), name.clone(), ssq.to_string()])
-                .unwrap();
+            writer.write_record(&[sampler.name(), name.clone(), ssq.to_string()])?;
             println!(
-                "RealQualitySamplerBenchmark(Sampler: {:?} Dataset: RT_IOT2022, Time: {:?})",
+                "RealQualitySamplerBenchmark(Sampler: {:?} Dataset: RT_IOT2022, Time: {:?}, Stats: {})",
                 name,
-                start.elapsed()
+                start.elapsed(),
+                sampler.stats()
             );
         }
     }
-    writer.flush().unwrap();
-}
\ No newline at end of file
+    writer.flush()?;
+    Ok(())
+}
+pub fn moa_comparison_benchmark() -> BenchmarkResult {
+    let algorithm_factories: Vec<AlorithmFactory> = vec![
+        Box::new(|| Ok(Box::new(Birch::new(2., 50, 5)?))),
+        Box::new(|| Ok(Box::new(CluStream::new(5)?))),
+        Box::new(|| Ok(Box::new(Denstream::new()))),
+    ];
+    fs::create_dir_all("./benchmark_results/moa")?;
+    let mut data_sets = Vec::new();
+    for entry in fs::read_dir(ARFF_DATA_DIR)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "arff") {
+            data_sets.push(path);
+        }
+    }
+    data_sets.sort();
+    for data_set in data_sets.iter() {
+        let data_name = data_set
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        for factory in algorithm_factories.iter() {
+            let mut algorithm = factory()?;
+            println!(
+                "MoaComparisonBenchmark(Algorithm: {:?} Dataset: {:?})",
+                algorithm.name(),
+                data_name
+            );
+            let reader = ArffReader::open(data_set)?;
+            let curve_file = create(Path::new("./benchmark_results/moa").join(format!(
+                "{}_{}_curve.csv",
+                data_name,
+                algorithm.name()
+            )))?;
+            let mut curve_writer = EvaluationCurveWriter::new(curve_file)?;
+            let start = Instant::now();
+            let mut window: Vec<(Vec<f64>, Option<String>)> = Vec::new();
+            let mut instances = 0;
+            for instance in reader {
+                let instance = instance?;
+                algorithm.insert(instance.values.clone())?;
+                window.push((instance.values, instance.class));
+                instances += 1;
+                if instances % MOA_EVALUATION_FREQUENCY == 0 {
+                    let clusters = algorithm.clusters()?;
+                    let ssq =
+                        point_ssq(window.iter().map(|(point, _)| point.as_slice()), &clusters);
+                    let labeled: Vec<(usize, String)> = window
+                        .drain(..)
+                        .filter_map(|(point, class)| Some((predict(&clusters, &point)?, class?)))
+                        .collect();
+                    curve_writer.write(&EvaluationRow {
+                        instances,
+                        seconds: start.elapsed().as_secs_f64(),
+                        clusters: cluster_count(&clusters),
+                        ssq,
+                        purity: (!labeled.is_empty()).then(|| purity(&labeled)),
+                    })?;
+                }
+            }
+            curve_writer.flush()?;
+            let clusters_file = create(Path::new("./benchmark_results/moa").join(format!(
+                "{}_{}_clusters.arff",
+                data_name,
+                algorithm.name()
+            )))?;
+            write_clusters_arff(clusters_file, &data_name, &algorithm.clusters()?)?;
+        }
+    }
+    Ok(())
+}
+
+fn random_points(rng: &mut Pcg64, count: usize, dimension: usize, range: f64) -> Vec<Vec<f64>> {
+    (0..count)
+        .map(|_| (0..dimension).map(|_| rng.gen_range(0.0..range)).collect())
+        .collect()
+}
+
+/// Nearest micro-cluster search as the number of micro-clusters grows: every index answers
+/// the same queries, each followed by the found center moving towards the query as if it
+/// absorbed the point. Recall is measured against a scan kept in the same state. Then
+/// CluStream and DenStream run with every index on streams that need more and more
+/// micro-clusters.
+pub fn neighbour_search_benchmark() -> BenchmarkResult {
+    let mut rng = Pcg64::seed_from_u64(0);
+    let index_file = create("./benchmark_results/neighbour_search.csv")?;
+    let mut writer = Writer::from_writer(index_file);
+    writer.write_record(["index", "dimention", "micro_clusters", "seconds", "recall"])?;
+    for dimension in [2, 4, 20, 80] {
+        for micro_clusters in [10, 50, 100, 500, 1_000, 5_000, 10_000] {
+            let centers = random_points(&mut rng, micro_clusters, dimension, 100.);
+            let queries = random_points(&mut rng, 2_000, dimension, 100.);
+            for (search, name) in NEIGHBOUR_SEARCHES.iter() {
+                let mut index = search.build(Euclidean);
+                let mut exact = NeighbourSearch::Linear.build(Euclidean);
+                let mut centers = centers.clone();
+                for (key, center) in centers.iter().enumerate() {
+                    index.insert(key, center);
+                    exact.insert(key, center);
+                }
+                let mut elapsed = Duration::ZERO;
+                let mut hits = 0;
+                for query in queries.iter() {
+                    let start = Instant::now();
+                    let (key, distance) = index.nearest(query).ok_or("empty index")?;
+                    elapsed += start.elapsed();
+                    if exact
+                        .nearest(query)
+                        .is_some_and(|(_, best)| best == distance)
+                    {
+                        hits += 1;
+                    }
+                    for (c, x) in centers[key].iter_mut().zip(query.iter()) {
+                        *c += (x - *c) * 0.01;
+                    }
+                    let start = Instant::now();
+                    index.insert(key, &centers[key]);
+                    elapsed += start.elapsed();
+                    exact.insert(key, &centers[key]);
+                }
+                println!(
+                    "NeighbourSearchBenchmark(Index: {:?} Dimension: {:?} MicroClusters: {:?}, Time: {:?})",
+                    name, dimension, micro_clusters, elapsed
+                );
+                writer.write_record(&[
+                    name.to_string(),
+                    dimension.to_string(),
+                    micro_clusters.to_string(),
+                    elapsed.as_secs_f64().to_string(),
+                    (hits as f64 / queries.len() as f64).to_string(),
+                ])?;
+            }
+        }
+    }
+    writer.flush()?;
+
+    let algorithms_file = create("./benchmark_results/neighbour_search_algorithms.csv")?;
+    let mut writer = Writer::from_writer(algorithms_file);
+    writer.write_record(["algorithm", "index", "micro_clusters", "seconds"])?;
+    for q in [50, 100, 200, 500] {
+        // Spread wide enough that every micro-cluster is used
+        let points = random_points(&mut rng, 10_000, 4, 10. * q as f64);
+        for (search, name) in NEIGHBOUR_SEARCHES.iter() {
+            let mut algorithm = CluStream::new(5)?
+                .with_max_micro_clusters(q)?
+                .with_search(*search);
+            let start = Instant::now();
+            for point in points.iter() {
+                algorithm.insert(point.clone())?;
+            }
+            let elapsed = start.elapsed();
+            writer.write_record(&[
+                algorithm.name(),
+                name.to_string(),
+                algorithm.stats().micro_clusters.to_string(),
+                elapsed.as_secs_f64().to_string(),
+            ])?;
+            println!(
+                "NeighbourSearchAlgoBenchmark(Algorithm: {:?} Index: {:?} q: {:?}, Time: {:?})",
+                algorithm.name(),
+                name,
+                q,
+                elapsed
+            );
+        }
+    }
+    for range in [50., 200., 500., 1_000.] {
+        // DenStream grows micro-clusters of radius up to EPSILON to cover the data
+        let points = random_points(&mut rng, 10_000, 2, range);
+        for (search, name) in NEIGHBOUR_SEARCHES.iter() {
+            let mut algorithm = Denstream::new().with_search(*search);
+            let start = Instant::now();
+            for point in points.iter() {
+                algorithm.insert(point.clone())?;
+            }
+            let elapsed = start.elapsed();
+            writer.write_record(&[
+                algorithm.name(),
+                name.to_string(),
+                algorithm.stats().micro_clusters.to_string(),
+                elapsed.as_secs_f64().to_string(),
+            ])?;
+            println!(
+                "NeighbourSearchAlgoBenchmark(Algorithm: {:?} Index: {:?} Range: {:?}, Time: {:?})",
+                algorithm.name(),
+                name,
+                range,
+                elapsed
+            );
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn all_benchmarks() -> BenchmarkResult {
+    processing_rate_benchmark()?;
+    dimentionality_processing_time_benchmark()?;
+    real_quality_benchmark()?;
+    synthetic_quality_benchmark()?;
+    processing_rate_samplers_benchmark()?;
+    samplers_real_quality_benchmark()?;
+    demo_algorithms()?;
+    demo_samplers()?;
+    moa_comparison_benchmark()?;
+    neighbour_search_benchmark()
+}
@@ -0,0 +1,447 @@
+use clustermancer::algorithms::{
+    birch::{Birch, GlobalClustering},
+    clustream::CluStream,
+    denstream::Denstream,
+    Chebyshev, ClusterEvent, ClusteringError, Cosine, DataStreamClusteringAlgorithm, Euclidean,
+    Manhattan, Metric, NeighbourSearch, Schema, Spread, SquaredEuclidean,
+};
+use clustermancer::pipeline::{self, InputFormat, PipelineOptions};
+use clustermancer::preprocessing::{
+    normalization::{MinMax, Robust, ZScore},
+    projection::{IncrementalPca, ProjectionKind, RandomProjection},
+    Preprocessed,
+};
+use clustermancer::samplers::{
+    kmeans_dynamic_sampler::KMeansDynamicSampler, static_sampler::StaticSampler,
+    uniform_sampler::UniformSampler, Sampler,
+};
+use clustermancer::server::http::{self, HttpOptions};
+use clustermancer::server::{line, Ingest, ModelState};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+pub const USAGE: &str = "Usage:
+  clustermancer                      interactive benchmark menu
+  clustermancer stream [OPTIONS]     cluster points read from stdin
+  clustermancer serve [OPTIONS]      cluster points received over TCP, UDP or HTTP
+
+Model options:
+  --algorithm <birch|clustream|denstream>   (default: birch)
+  --sampler <static:ODDS|uniform|kmeans>    wrap the algorithm in a sampler
+  --metric <euclidean|squared_euclidean|manhattan|chebyshev|cosine>
+                                            distance between points (default: euclidean)
+  --normalize <zscore|minmax|robust>        scale every feature online before clustering
+  --project <gaussian:K|sparse:K|pca:K>     reduce points to K dimensions before clustering
+  --k <N>                                   number of macro-clusters (default: 5)
+  --threshold <T>                           BIRCH threshold (default: 2)
+  --branching-factor <B>                    BIRCH branching factor (default: 50)
+  --max-nodes <N>                           BIRCH node limit, raises the threshold and rebuilds the tree
+  --decay <LAMBDA:FLOOR>                    fade BIRCH entries by 2^-LAMBDA per point, drop them below FLOOR
+  --offline <EPSILON:MU>                    DenStream offline DBSCAN: micro-clusters of weight MU are
+                                            cores, reaching 2*EPSILON (default: 6.75:2)
+  --global-clustering <kmeans|agglomerative>
+                                            how BIRCH groups leaf entries (default: kmeans)
+  --spread <radius|variance|covariance>     what clusters report besides the radius (default: radius)
+  --search <linear|kdtree|graph>            how CluStream and DenStream find the nearest micro-cluster
+                                            (default: linear)
+  --dimension <N>                           reject points of any other dimension
+  --features <NAME,NAME,..>                 feature names, also fixes the dimension
+
+Stream options:
+  --format <csv|ndjson>                     input format (default: csv)
+  --refresh-every <N>                       points between clustering refreshes (default: 100)
+  --snapshot-every <N>                      write clusters() every N points
+  --snapshot-file <PATH>                    where snapshots go (default: snapshots.csv)
+  --events-file <PATH>                      log micro-cluster lifecycle events as NDJSON
+
+Serve options:
+  --protocol <tcp|udp|http>                 (default: tcp)
+  --address <HOST:PORT>                     (default: 127.0.0.1:7878)
+  --queue-capacity <N>                      tcp/udp records buffered before producers block (default: 1024)
+  --export-dir <PATH>                       where http POST /export writes reports (default: ./exports)
+  --workers <N>                             http worker threads (default: 4)
+  --metrics-address <HOST:PORT>             tcp/udp: also serve the HTTP API (incl. /metrics) here";
+
+/// `--key value` pairs, every key has to be consumed by the command
+pub struct Args {
+    values: HashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument '{}'", arg))?;
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for '--{}'", key))?;
+            values.insert(key.to_string(), value.clone());
+        }
+        Ok(Args { values })
+    }
+
+    pub fn take(&mut self, key: &str) -> Option<String> {
+        self.values.remove(key)
+    }
+
+    pub fn take_parsed<T: std::str::FromStr>(
+        &mut self,
+        key: &str,
+        default: T,
+    ) -> Result<T, String> {
+        match self.take(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value '{}' for '--{}'", value, key)),
+            None => Ok(default),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self.values.keys().next() {
+            Some(key) => Err(format!("unknown option '--{}'", key)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct ModelOptions {
+    algorithm: String,
+    sampler: Option<String>,
+    metric: String,
+    normalize: Option<String>,
+    project: Option<String>,
+    k: usize,
+    threshold: f64,
+    branching_factor: usize,
+    max_nodes: Option<usize>,
+    decay: Option<(f64, f64)>,
+    offline: Option<(f64, f64)>,
+    global_clustering: GlobalClustering,
+    spread: Spread,
+    search: NeighbourSearch,
+    schema: Schema,
+}
+
+impl ModelOptions {
+    pub fn from_args(args: &mut Args) -> Result<Self, String> {
+        let dimension = args
+            .take("dimension")
+            .map(|dimension| dimension.parse::<usize>())
+            .transpose()
+            .map_err(|_| "invalid value for '--dimension'".to_string())?;
+        let max_nodes = args
+            .take("max-nodes")
+            .map(|max_nodes| max_nodes.parse::<usize>())
+            .transpose()
+            .map_err(|_| "invalid value for '--max-nodes'".to_string())?;
+        let decay = args
+            .take("decay")
+            .map(|decay| {
+                let (lambda, floor) = decay.split_once(':')?;
+                Some((lambda.parse::<f64>().ok()?, floor.parse::<f64>().ok()?))
+            })
+            .map(|decay| {
+                decay.ok_or("invalid value for '--decay', expected LAMBDA:FLOOR".to_string())
+            })
+            .transpose()?;
+        let offline = args
+            .take("offline")
+            .map(|offline| {
+                let (epsilon, mu) = offline.split_once(':')?;
+                Some((epsilon.parse::<f64>().ok()?, mu.parse::<f64>().ok()?))
+            })
+            .map(|offline| {
+                offline.ok_or("invalid value for '--offline', expected EPSILON:MU".to_string())
+            })
+            .transpose()?;
+        let global_clustering = match args.take("global-clustering").as_deref() {
+            None | Some("kmeans") => GlobalClustering::KMeans,
+            Some("agglomerative") => GlobalClustering::Agglomerative,
+            Some(other) => return Err(format!("unknown global clustering '{}'", other)),
+        };
+        let spread = match args.take("spread").as_deref() {
+            None | Some("radius") => Spread::Radius,
+            Some("variance") => Spread::Variance,
+            Some("covariance") => Spread::Covariance,
+            Some(other) => return Err(format!("unknown spread '{}'", other)),
+        };
+        let search = match args.take("search").as_deref() {
+            None | Some("linear") => NeighbourSearch::Linear,
+            Some("kdtree") => NeighbourSearch::KdTree,
+            Some("graph") => NeighbourSearch::Graph,
+            Some(other) => return Err(format!("unknown search '{}'", other)),
+        };
+        let schema = match (args.take("features"), dimension) {
+            (Some(features), dimension) => {
+                let names: Vec<String> = features
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .collect();
+                if dimension.is_some_and(|dimension| dimension != names.len()) {
+                    return Err(format!(
+                        "--features names {} features but --dimension is {}",
+                        names.len(),
+                        dimension.unwrap()
+                    ));
+                }
+                Schema::with_feature_names(names)
+            }
+            (None, Some(dimension)) => Schema::with_dimension(dimension),
+            (None, None) => Ok(Schema::new()),
+        }
+        .map_err(|err| err.to_string())?;
+        Ok(ModelOptions {
+            algorithm: args.take("algorithm").unwrap_or("birch".to_string()),
+            sampler: args.take("sampler"),
+            metric: args.take("metric").unwrap_or("euclidean".to_string()),
+            normalize: args.take("normalize"),
+            project: args.take("project"),
+            k: args.take_parsed("k", 5)?,
+            threshold: args.take_parsed("threshold", 2.)?,
+            branching_factor: args.take_parsed("branching-factor", 50)?,
+            max_nodes,
+            decay,
+            offline,
+            global_clustering,
+            spread,
+            search,
+            schema,
+        })
+    }
+
+    pub fn build(&self) -> Result<Box<dyn DataStreamClusteringAlgorithm>, String> {
+        let algorithm = match self.metric.to_lowercase().as_str() {
+            "euclidean" => self.build_with(Euclidean),
+            "squared_euclidean" => self.build_with(SquaredEuclidean),
+            "manhattan" => self.build_with(Manhattan),
+            "chebyshev" => self.build_with(Chebyshev),
+            "cosine" => self.build_with(Cosine),
+            other => Err(format!("unknown metric '{}'", other)),
+        }?;
+        let algorithm: Box<dyn DataStreamClusteringAlgorithm> = match self.project.as_deref() {
+            None => algorithm,
+            Some(project) => {
+                let unknown = || format!("unknown projection '{}'", project);
+                let (kind, dimension) = project.split_once(':').ok_or_else(unknown)?;
+                let dimension: usize = dimension.parse().map_err(|_| unknown())?;
+                let invalid = |err: ClusteringError| err.to_string();
+                match kind {
+                    "gaussian" => Box::new(
+                        Preprocessed::new(
+                            algorithm,
+                            RandomProjection::new(ProjectionKind::Gaussian, dimension)
+                                .map_err(invalid)?,
+                        )
+                        .with_schema(self.schema.clone())
+                        .map_err(invalid)?,
+                    ),
+                    "sparse" => Box::new(
+                        Preprocessed::new(
+                            algorithm,
+                            RandomProjection::new(ProjectionKind::Sparse, dimension)
+                                .map_err(invalid)?,
+                        )
+                        .with_schema(self.schema.clone())
+                        .map_err(invalid)?,
+                    ),
+                    "pca" => Box::new(
+                        Preprocessed::new(
+                            algorithm,
+                            IncrementalPca::new(dimension).map_err(invalid)?,
+                        )
+                        .with_schema(self.schema.clone())
+                        .map_err(invalid)?,
+                    ),
+                    _ => return Err(unknown()),
+                }
+            }
+        };
+        // Outside the sampler, so the scaling sees every point and not just the sampled ones
+        Ok(match self.normalize.as_deref() {
+            None => algorithm,
+            Some("zscore") => Box::new(Preprocessed::new(algorithm, ZScore::new())),
+            Some("minmax") => Box::new(Preprocessed::new(algorithm, MinMax::new())),
+            Some("robust") => Box::new(Preprocessed::new(algorithm, Robust::new())),
+            Some(other) => return Err(format!("unknown normalization '{}'", other)),
+        })
+    }
+
+    fn build_with<D: Metric>(
+        &self,
+        metric: D,
+    ) -> Result<Box<dyn DataStreamClusteringAlgorithm>, String> {
+        // A projection stage in front validates the input, the algorithm sees projected points
+        let schema = match self.project {
+            Some(_) => Schema::new(),
+            None => self.schema.clone(),
+        };
+        let invalid = |err: ClusteringError| err.to_string();
+        let algorithm: Box<dyn DataStreamClusteringAlgorithm> =
+            match self.algorithm.to_lowercase().as_str() {
+                "birch" => {
+                    let mut birch = Birch::new(self.threshold, self.branching_factor, self.k)
+                        .map_err(invalid)?
+                        .with_schema(schema.clone())
+                        .map_err(invalid)?
+                        .with_global_clustering(self.global_clustering)
+                        .with_spread(self.spread)
+                        .map_err(invalid)?;
+                    if let Some(max_nodes) = self.max_nodes {
+                        birch = birch.with_max_nodes(max_nodes).map_err(invalid)?;
+                    }
+                    if let Some((lambda, floor)) = self.decay {
+                        birch = birch.with_decay(lambda, floor).map_err(invalid)?;
+                    }
+                    Box::new(birch.with_metric(metric.clone()))
+                }
+                "clustream" => Box::new(
+                    CluStream::new(self.k)
+                        .map_err(invalid)?
+                        .with_schema(schema.clone())
+                        .map_err(invalid)?
+                        .with_spread(self.spread)
+                        .map_err(invalid)?
+                        .with_search(self.search)
+                        .with_metric(metric.clone()),
+                ),
+                "denstream" => {
+                    let mut denstream = Denstream::new()
+                        .with_schema(schema.clone())
+                        .map_err(invalid)?
+                        .with_spread(self.spread)
+                        .map_err(invalid)?
+                        .with_search(self.search);
+                    if let Some((epsilon, mu)) = self.offline {
+                        denstream = denstream
+                            .with_offline_clustering(epsilon, mu)
+                            .map_err(invalid)?;
+                    }
+                    Box::new(denstream.with_metric(metric.clone()))
+                }
+                other => return Err(format!("unknown algorithm '{}'", other)),
+            };
+        let sampler: Box<dyn Sampler> = match self.sampler.as_deref() {
+            None => return Ok(algorithm),
+            Some("uniform") => Box::new(UniformSampler::new(algorithm)),
+            Some("kmeans") => Box::new(
+                KMeansDynamicSampler::new(algorithm, self.k)
+                    .map_err(invalid)?
+                    .with_metric(metric),
+            ),
+            Some(sampler) => match sampler.strip_prefix("static:").map(str::parse::<f64>) {
+                Some(Ok(odds)) => Box::new(StaticSampler::new(algorithm, odds).map_err(invalid)?),
+                _ => return Err(format!("unknown sampler '{}'", sampler)),
+            },
+        };
+        Ok(Box::new(sampler))
+    }
+}
+
+pub fn stream(args: &[String]) -> Result<(), String> {
+    let mut args = Args::parse(args)?;
+    let model_options = ModelOptions::from_args(&mut args)?;
+    let format = match args.take("format").as_deref() {
+        None | Some("csv") => InputFormat::Csv,
+        Some("ndjson") => InputFormat::Ndjson,
+        Some(other) => return Err(format!("unknown format '{}'", other)),
+    };
+    let options = PipelineOptions {
+        format,
+        refresh_every: args.take_parsed("refresh-every", 100)?.max(1),
+        snapshot_every: args
+            .take("snapshot-every")
+            .map(|every| every.parse::<usize>())
+            .transpose()
+            .map_err(|_| "invalid value for '--snapshot-every'".to_string())?
+            .filter(|every| *every > 0),
+    };
+    let snapshot_file = args
+        .take("snapshot-file")
+        .unwrap_or("snapshots.csv".to_string());
+    let events_file = args.take("events-file");
+    args.finish()?;
+
+    let mut algorithm = model_options.build()?;
+    if let Some(events_file) = events_file {
+        let mut events = BufWriter::new(
+            File::create(&events_file).map_err(|err| format!("{}: {}", events_file, err))?,
+        );
+        algorithm.subscribe(Box::new(move |event: &ClusterEvent| {
+            // Losing the audit log must not stop the clustering
+            let _ = serde_json::to_writer(&mut events, event)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(events));
+        }));
+    }
+    let snapshots = match options.snapshot_every {
+        Some(_) => Some(BufWriter::new(
+            File::create(&snapshot_file).map_err(|err| format!("{}: {}", snapshot_file, err))?,
+        )),
+        None => None,
+    };
+    pipeline::run(
+        algorithm.as_mut(),
+        io::stdin().lock(),
+        io::stdout().lock(),
+        snapshots,
+        &options,
+    )
+    .map(|_| ())
+    .map_err(|err| err.to_string())
+}
+
+pub fn serve(args: &[String]) -> Result<(), String> {
+    let mut args = Args::parse(args)?;
+    let model_options = ModelOptions::from_args(&mut args)?;
+    let protocol = args.take("protocol").unwrap_or("tcp".to_string());
+    let address = args.take("address").unwrap_or("127.0.0.1:7878".to_string());
+    let capacity = args.take_parsed("queue-capacity", 1024)?;
+    let http_options = HttpOptions {
+        export_dir: args
+            .take("export-dir")
+            .map(PathBuf::from)
+            .unwrap_or(HttpOptions::default().export_dir),
+        workers: args.take_parsed("workers", HttpOptions::default().workers)?,
+    };
+    let metrics_address = args.take("metrics-address");
+    args.finish()?;
+
+    let model = ModelState::shared(model_options.build()?);
+    if protocol == "http" {
+        let server =
+            tiny_http::Server::http(&address).map_err(|err| format!("{}: {}", address, err))?;
+        eprintln!("Listening on http://{}", server.server_addr());
+        http::serve_http(Arc::new(server), model, http_options);
+        return Ok(());
+    }
+    if let Some(metrics_address) = metrics_address {
+        let server = tiny_http::Server::http(&metrics_address)
+            .map_err(|err| format!("{}: {}", metrics_address, err))?;
+        eprintln!("Metrics on http://{}/metrics", server.server_addr());
+        let model = model.clone();
+        thread::spawn(move || http::serve_http(Arc::new(server), model, http_options));
+    }
+    let ingest = Ingest::spawn(model, capacity);
+    let result = match protocol.as_str() {
+        "tcp" => TcpListener::bind(&address).and_then(|listener| {
+            eprintln!("Listening on tcp://{}", listener.local_addr()?);
+            line::serve_tcp(listener, ingest.sender())
+        }),
+        "udp" => UdpSocket::bind(&address).and_then(|socket| {
+            eprintln!("Listening on udp://{}", socket.local_addr()?);
+            line::serve_udp(socket, ingest.sender())
+        }),
+        other => return Err(format!("unknown protocol '{}'", other)),
+    };
+    result.map_err(|err| format!("{}: {}", address, err))
+}
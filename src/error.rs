@@ -0,0 +1,63 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusteringError {
+    EmptyPoint,
+    /// Coordinate `index` is NaN or infinite
+    NonFinite {
+        index: usize,
+        value: f64,
+    },
+    /// The point does not have the dimension of the points seen before it
+    DimensionMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// The record does not have the number of categorical attributes seen before it
+    CategoricalMismatch {
+        expected: usize,
+        found: usize,
+    },
+    InvalidParameter {
+        name: &'static str,
+        reason: String,
+    },
+    /// Reading or writing an on-disk store failed
+    Io(String),
+}
+
+impl ClusteringError {
+    pub(crate) fn invalid_parameter(name: &'static str, reason: impl Into<String>) -> Self {
+        ClusteringError::InvalidParameter {
+            name,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ClusteringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClusteringError::EmptyPoint => write!(f, "point has no coordinates"),
+            ClusteringError::NonFinite { index, value } => {
+                write!(f, "coordinate {} is not finite ({})", index, value)
+            }
+            ClusteringError::DimensionMismatch { expected, found } => write!(
+                f,
+                "expected a point of dimension {}, got {}",
+                expected, found
+            ),
+            ClusteringError::CategoricalMismatch { expected, found } => write!(
+                f,
+                "expected {} categorical attributes, got {}",
+                expected, found
+            ),
+            ClusteringError::InvalidParameter { name, reason } => {
+                write!(f, "invalid parameter '{}': {}", name, reason)
+            }
+            ClusteringError::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClusteringError {}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Lines};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ArffError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ArffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArffError::Io(err) => write!(f, "ARFF I/O error: {}", err),
+            ArffError::Parse { line, message } => {
+                write!(f, "ARFF parse error at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArffError {}
+
+impl From<io::Error> for ArffError {
+    fn from(err: io::Error) -> Self {
+        ArffError::Io(err)
+    }
+}
+
+/// Nominal class attribute, MOA treats the last attribute as the class
+#[derive(Debug, Clone)]
+pub struct ClassAttribute {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArffHeader {
+    pub relation: String,
+    /// Names of the numeric attributes, in file order
+    pub attributes: Vec<String>,
+    pub class: Option<ClassAttribute>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArffInstance {
+    pub values: Vec<f64>,
+    pub class: Option<String>,
+}
+
+/// Streaming reader for dense ARFF files with numeric attributes and an optional
+/// nominal class attribute in the last position.
+pub struct ArffReader<R: BufRead> {
+    header: ArffHeader,
+    lines: Lines<R>,
+    line_no: usize,
+}
+
+impl ArffReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ArffError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> ArffReader<R> {
+    pub fn new(reader: R) -> Result<Self, ArffError> {
+        let mut lines = reader.lines();
+        let mut line_no = 0;
+        let mut relation = String::new();
+        // (name, nominal values)
+        let mut declared: Vec<(String, Option<Vec<String>>)> = Vec::new();
+        loop {
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => {
+                    return Err(ArffError::Parse {
+                        line: line_no,
+                        message: "missing @data section".to_string(),
+                    })
+                }
+            };
+            line_no += 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('%') {
+                continue;
+            }
+            let lowercase = line.to_lowercase();
+            if lowercase.starts_with("@relation") {
+                relation = unquote(line["@relation".len()..].trim()).to_string();
+            } else if lowercase.starts_with("@attribute") {
+                let (name, kind) = split_name(line["@attribute".len()..].trim());
+                let attribute = parse_attribute_type(kind).ok_or_else(|| ArffError::Parse {
+                    line: line_no,
+                    message: format!("unsupported type '{}' for attribute '{}'", kind, name),
+                })?;
+                declared.push((name.to_string(), attribute));
+            } else if lowercase.starts_with("@data") {
+                break;
+            } else {
+                return Err(ArffError::Parse {
+                    line: line_no,
+                    message: format!("unexpected header line '{}'", line),
+                });
+            }
+        }
+
+        let class = match declared.last() {
+            Some((_, Some(_))) => {
+                let (name, values) = declared.pop().unwrap();
+                Some(ClassAttribute {
+                    name,
+                    values: values.unwrap(),
+                })
+            }
+            _ => None,
+        };
+        if let Some((name, _)) = declared.iter().find(|(_, values)| values.is_some()) {
+            return Err(ArffError::Parse {
+                line: line_no,
+                message: format!(
+                    "nominal attribute '{}' is only supported as the last (class) attribute",
+                    name
+                ),
+            });
+        }
+        Ok(ArffReader {
+            header: ArffHeader {
+                relation,
+                attributes: declared.into_iter().map(|(name, _)| name).collect(),
+                class,
+            },
+            lines,
+            line_no,
+        })
+    }
+
+    pub fn header(&self) -> &ArffHeader {
+        &self.header
+    }
+
+    fn parse_instance(&self, line: &str) -> Result<ArffInstance, ArffError> {
+        let error = |message: String| ArffError::Parse {
+            line: self.line_no,
+            message,
+        };
+        if line.starts_with('{') {
+            return Err(error("sparse ARFF instances are not supported".to_string()));
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        let expected = self.header.attributes.len() + self.header.class.is_some() as usize;
+        if fields.len() != expected {
+            return Err(error(format!(
+                "expected {} values, found {}",
+                expected,
+                fields.len()
+            )));
+        }
+        let values = fields
+            .iter()
+            .take(self.header.attributes.len())
+            .zip(self.header.attributes.iter())
+            .map(|(field, name)| {
+                field.parse::<f64>().map_err(|_| {
+                    error(format!(
+                        "invalid value '{}' for attribute '{}'",
+                        field, name
+                    ))
+                })
+            })
+            .collect::<Result<Vec<f64>, ArffError>>()?;
+        let class = match &self.header.class {
+            Some(attribute) => {
+                let value = unquote(fields[fields.len() - 1]);
+                if !attribute.values.iter().any(|v| v == value) {
+                    return Err(error(format!(
+                        "'{}' is not a value of class attribute '{}'",
+                        value, attribute.name
+                    )));
+                }
+                Some(value.to_string())
+            }
+            None => None,
+        };
+        Ok(ArffInstance { values, class })
+    }
+}
+
+impl<R: BufRead> Iterator for ArffReader<R> {
+    type Item = Result<ArffInstance, ArffError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            self.line_no += 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('%') {
+                continue;
+            }
+            return Some(self.parse_instance(line));
+        }
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+        .unwrap_or(value)
+}
+
+/// Splits `name type` where the name may be quoted
+fn split_name(declaration: &str) -> (&str, &str) {
+    for quote in ['\'', '"'] {
+        if let Some(rest) = declaration.strip_prefix(quote) {
+            if let Some(end) = rest.find(quote) {
+                return (&rest[..end], rest[end + 1..].trim());
+            }
+        }
+    }
+    match declaration.find(char::is_whitespace) {
+        Some(end) => (&declaration[..end], declaration[end..].trim()),
+        None => (declaration, ""),
+    }
+}
+
+/// `Some(None)` for numeric attributes, `Some(Some(values))` for nominal ones
+fn parse_attribute_type(kind: &str) -> Option<Option<Vec<String>>> {
+    if let Some(values) = kind.strip_prefix('{').and_then(|k| k.strip_suffix('}')) {
+        return Some(Some(
+            values
+                .split(',')
+                .map(|value| unquote(value.trim()).to_string())
+                .collect(),
+        ));
+    }
+    match kind.to_lowercase().as_str() {
+        "numeric" | "real" | "integer" => Some(None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "% generated by MOA
+@relation 'RandomRBFGenerator'
+
+@attribute att1 numeric
+@attribute 'att 2' real
+@attribute class {class1,class2}
+
+@data
+0.5,1.25,class1
+% comment inside data
+-3,4e2,class2
+";
+
+    #[test]
+    fn test_reader() {
+        let reader = ArffReader::new(SAMPLE.as_bytes()).unwrap();
+        assert_eq!(reader.header().relation, "RandomRBFGenerator");
+        assert_eq!(reader.header().attributes, vec!["att1", "att 2"]);
+        assert_eq!(
+            reader.header().class.as_ref().unwrap().values,
+            vec!["class1", "class2"]
+        );
+        let instances: Vec<ArffInstance> = reader.map(|i| i.unwrap()).collect();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[1].values, vec![-3., 400.]);
+        assert_eq!(instances[1].class.as_deref(), Some("class2"));
+    }
+
+    #[test]
+    fn test_reader_rejects_bad_rows() {
+        let data = "@relation r\n@attribute a numeric\n@data\n1\n?\n";
+        let mut reader = ArffReader::new(data.as_bytes()).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next().unwrap(),
+            Err(ArffError::Parse { line: 5, .. })
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::algorithms::{macro_clusters, ClusteringElement, Euclidean, Metric};
+use csv::Writer;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Column names follow MOA's evaluation dump files so curves can be compared side by side
+const CURVE_HEADER: [&str; 5] = [
+    "learning evaluation instances",
+    "evaluation time (cpu seconds)",
+    "numCluster",
+    "SSQ",
+    "Purity",
+];
+
+#[derive(Debug, Clone)]
+pub struct EvaluationRow {
+    pub instances: usize,
+    pub seconds: f64,
+    /// Number of clusters, see `cluster_count`
+    pub clusters: usize,
+    /// See `point_ssq`
+    pub ssq: f64,
+    /// Only known when the stream carries a class attribute
+    pub purity: Option<f64>,
+}
+
+/// Writes evaluation curves as CSV in the layout of MOA's `-d` dump files
+pub struct EvaluationCurveWriter<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> EvaluationCurveWriter<W> {
+    pub fn new(writer: W) -> csv::Result<Self> {
+        let mut writer = Writer::from_writer(writer);
+        writer.write_record(CURVE_HEADER)?;
+        Ok(EvaluationCurveWriter { writer })
+    }
+
+    pub fn write(&mut self, row: &EvaluationRow) -> csv::Result<()> {
+        self.writer.write_record(&[
+            row.instances.to_string(),
+            row.seconds.to_string(),
+            row.clusters.to_string(),
+            row.ssq.to_string(),
+            row.purity.map(|p| p.to_string()).unwrap_or("?".to_string()),
+        ])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes the clustering result as an ARFF relation of spheres (center, radius, cluster),
+/// the same shape MOA exports for `SphereCluster`s
+pub fn write_clusters_arff<W: Write>(
+    mut writer: W,
+    relation: &str,
+    clusters: &[ClusteringElement],
+) -> io::Result<()> {
+    let dimension = clusters.first().map(|c| c.center.len()).unwrap_or(0);
+    let cluster_ids = clusters
+        .iter()
+        .map(|c| c.cluster)
+        .max()
+        .map(|max| (0..=max).map(|i| i.to_string()).collect::<Vec<String>>())
+        .unwrap_or_default();
+    writeln!(writer, "@relation '{}'", relation)?;
+    writeln!(writer)?;
+    for i in 0..dimension {
+        writeln!(writer, "@attribute att{} numeric", i + 1)?;
+    }
+    writeln!(writer, "@attribute radius numeric")?;
+    writeln!(writer, "@attribute cluster {{{}}}", cluster_ids.join(","))?;
+    writeln!(writer)?;
+    writeln!(writer, "@data")?;
+    for cluster in clusters {
+        let center = cluster
+            .center
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        writeln!(writer, "{},{},{}", center, cluster.radius, cluster.cluster)?;
+    }
+    writer.flush()
+}
+
+/// Number of clusters as MOA's `numCluster` counts them: the distinct `cluster` ids, not
+/// the micro-clusters
+pub fn cluster_count(clusters: &[ClusteringElement]) -> usize {
+    macro_clusters(clusters).len()
+}
+
+/// MOA's `SSQ`: sum of the squared distances from every point to the closest cluster
+/// center, with the macro-clusters of `clusters` as the clusters
+pub fn point_ssq<'a>(
+    points: impl IntoIterator<Item = &'a [f64]>,
+    clusters: &[ClusteringElement],
+) -> f64 {
+    let centers: Vec<Vec<f64>> = macro_clusters(clusters)
+        .into_iter()
+        .map(|macro_cluster| macro_cluster.center)
+        .collect();
+    points
+        .into_iter()
+        .filter_map(|point| {
+            centers
+                .iter()
+                .map(|center| Euclidean.distance(center, point).powi(2))
+                .min_by(f64::total_cmp)
+        })
+        .sum()
+}
+
+/// Fraction of points that belong to the majority class of their assigned cluster
+pub fn purity(assignments: &[(usize, String)]) -> f64 {
+    if assignments.is_empty() {
+        return 0.;
+    }
+    let mut counts: HashMap<usize, HashMap<&str, usize>> = HashMap::new();
+    for (cluster, class) in assignments {
+        *counts
+            .entry(*cluster)
+            .or_default()
+            .entry(class.as_str())
+            .or_default() += 1;
+    }
+    let majority = counts
+        .values()
+        .map(|classes| classes.values().max().copied().unwrap_or(0))
+        .sum::<usize>();
+    majority as f64 / assignments.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::arff::{ArffInstance, ArffReader};
+
+    fn element(center: Vec<f64>, radius: f64, cluster: usize) -> ClusteringElement {
+        ClusteringElement {
+            center,
+            radius,
+            cluster,
+            categories: Vec::new(),
+            variance: None,
+            covariance: None,
+            weight: 1.,
+            id: 0,
+            created: 0,
+            last_update: 0,
+        }
+    }
+
+    #[test]
+    fn test_purity() {
+        let assignments = [
+            (0, "a".to_string()),
+            (0, "a".to_string()),
+            (0, "b".to_string()),
+            (1, "b".to_string()),
+        ];
+        // Cluster 0 has two of three in class a, cluster 1 its single point in b
+        assert_eq!(purity(&assignments), 0.75);
+        assert_eq!(purity(&[]), 0.);
+    }
+
+    #[test]
+    fn test_cluster_count_and_ssq() {
+        let clusters = [
+            element(vec![0., 0.], 1., 0),
+            element(vec![2., 0.], 1., 0),
+            element(vec![10., 0.], 1., 1),
+        ];
+        // Two micro-clusters make up cluster 0, centered at (1, 0)
+        assert_eq!(cluster_count(&clusters), 2);
+        let points = [vec![1., 1.], vec![12., 0.], vec![7., 0.]];
+        // 1² + 2² + 3², each point to the closer of (1, 0) and (10, 0)
+        assert_eq!(point_ssq(points.iter().map(Vec::as_slice), &clusters), 14.);
+        assert_eq!(point_ssq(points.iter().map(Vec::as_slice), &[]), 0.);
+    }
+
+    #[test]
+    fn test_clusters_arff_round_trip() {
+        let clusters = [
+            element(vec![0.5, -1.], 0.25, 0),
+            element(vec![3., 4.], 1.5, 2),
+        ];
+        let mut arff = Vec::new();
+        write_clusters_arff(&mut arff, "clusters", &clusters).unwrap();
+        let reader = ArffReader::new(arff.as_slice()).unwrap();
+        assert_eq!(reader.header().relation, "clusters");
+        assert_eq!(reader.header().attributes, vec!["att1", "att2", "radius"]);
+        assert_eq!(
+            reader.header().class.as_ref().unwrap().values,
+            vec!["0", "1", "2"]
+        );
+        let instances: Vec<ArffInstance> = reader.map(|i| i.unwrap()).collect();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].values, vec![0.5, -1., 0.25]);
+        assert_eq!(instances[1].values, vec![3., 4., 1.5]);
+        assert_eq!(instances[1].class.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_curve_header() {
+        let mut curve = EvaluationCurveWriter::new(Vec::new()).unwrap();
+        curve
+            .write(&EvaluationRow {
+                instances: 1_000,
+                seconds: 0.5,
+                clusters: 3,
+                ssq: 12.,
+                purity: None,
+            })
+            .unwrap();
+        curve.flush().unwrap();
+        let csv = String::from_utf8(curve.writer.into_inner().unwrap()).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "learning evaluation instances,evaluation time (cpu seconds),numCluster,SSQ,Purity"
+            )
+        );
+        assert_eq!(lines.next(), Some("1000,0.5,3,12,?"));
+    }
+}
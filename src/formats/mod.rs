@@ -0,0 +1,14 @@
+pub mod arff;
+pub mod moa;
+
+/// Parses comma separated coordinates, as the stdin pipeline and the line protocol read them
+pub fn parse_point(text: &str) -> Result<Vec<f64>, String> {
+    text.split(',')
+        .map(|field| {
+            field
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number '{}'", field.trim()))
+        })
+        .collect()
+}
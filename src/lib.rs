@@ -0,0 +1,10 @@
+pub mod algorithms;
+pub mod error;
+pub mod formats;
+pub mod metrics;
+pub mod pipeline;
+pub mod preprocessing;
+pub mod samplers;
+pub mod server;
+
+pub use error::ClusteringError;
@@ -1,15 +1,40 @@
-mod algorithms;
 mod benchmark;
-mod samplers;
+mod cli;
 use benchmark::*;
 
 use dialoguer::{theme::ColorfulTheme, Select};
 
-use std::fs;
+use std::{env, fs, io, process};
+use tracing_subscriber::EnvFilter;
 
 fn main() {
-    fs::create_dir_all("./benchmark_results").unwrap();
-    fs::create_dir_all("./demo_results").unwrap();
+    // Diagnostics go to stderr so stdout stays usable in pipelines, RUST_LOG overrides the level
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .with_writer(io::stderr)
+        .init();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None => menu().map_err(|err| err.to_string()),
+        Some("stream") => cli::stream(&args[1..]),
+        Some("serve") => cli::serve(&args[1..]),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Some(other) => Err(format!("unknown command '{}'\n\n{}", other, cli::USAGE)),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn menu() -> BenchmarkResult {
+    fs::create_dir_all("./benchmark_results")?;
+    fs::create_dir_all("./demo_results")?;
     let options = [
         "Benchmark algorithms",
         "Benchmark algorithms with different dimentionality",
@@ -19,6 +44,8 @@ fn main() {
         "Benchmark samplers quality(real data)",
         "Demo algorithms",
         "Demo samplers",
+        "Benchmark against MOA(ARFF data)",
+        "Benchmark nearest micro-cluster search",
         "All",
         "Exit",
     ];
@@ -26,8 +53,7 @@ fn main() {
         .with_prompt("Select an option")
         .items(&options)
         .default(0)
-        .interact()
-        .unwrap();
+        .interact()?;
     match selection {
         0 => processing_rate_benchmark(),
         1 => dimentionality_processing_time_benchmark(),
@@ -37,16 +63,9 @@ fn main() {
         5 => samplers_real_quality_benchmark(),
         6 => demo_algorithms(),
         7 => demo_samplers(),
-        8 => {
-            processing_rate_benchmark();
-            dimentionality_processing_time_benchmark();
-            real_quality_benchmark();
-            synthetic_quality_benchmark();
-            processing_rate_samplers_benchmark();
-            samplers_real_quality_benchmark();
-            demo_algorithms();
-            demo_samplers();
-        }
-        _ => (),
+        8 => moa_comparison_benchmark(),
+        9 => neighbour_search_benchmark(),
+        10 => all_benchmarks(),
+        _ => Ok(()),
     }
 }
@@ -0,0 +1,340 @@
+//! Prometheus text exposition (format 0.0.4) of a model's internals
+
+use crate::algorithms::{AlgorithmDetails, AlgorithmStats};
+use std::fmt::Write;
+
+/// Upper bounds in seconds, from 1µs to ~1s
+const LATENCY_BUCKETS: [f64; 11] = [
+    1e-6, 4e-6, 1.6e-5, 6.4e-5, 2.56e-4, 1.024e-3, 4.096e-3, 1.6384e-2, 6.5536e-2, 0.262144,
+    1.048576,
+];
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// Non-cumulative counts per bucket, the last one is `+Inf`
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.,
+            count: 0,
+        }
+    }
+
+    pub fn latency() -> Self {
+        Self::new(&LATENCY_BUCKETS)
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+struct Exposition {
+    text: String,
+    labels: String,
+}
+
+impl Exposition {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.text, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.text, "# TYPE {} {}", name, kind).unwrap();
+    }
+
+    fn sample(&mut self, name: &str, extra_labels: &str, value: f64) {
+        let separator = if extra_labels.is_empty() { "" } else { "," };
+        writeln!(
+            self.text,
+            "{}{{{}{}{}}} {}",
+            name, self.labels, separator, extra_labels, value
+        )
+        .unwrap();
+    }
+
+    fn single(&mut self, name: &str, kind: &str, help: &str, value: usize) {
+        self.single_f64(name, kind, help, value as f64);
+    }
+
+    fn single_f64(&mut self, name: &str, kind: &str, help: &str, value: f64) {
+        self.header(name, kind, help);
+        self.sample(name, "", value);
+    }
+
+    fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) {
+        self.header(name, "histogram", help);
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(histogram.counts.iter()) {
+            cumulative += count;
+            self.sample(
+                &format!("{}_bucket", name),
+                &format!("le=\"{}\"", bound),
+                cumulative as f64,
+            );
+        }
+        self.sample(
+            &format!("{}_bucket", name),
+            "le=\"+Inf\"",
+            histogram.count as f64,
+        );
+        self.sample(&format!("{}_sum", name), "", histogram.sum);
+        self.sample(&format!("{}_count", name), "", histogram.count as f64);
+    }
+}
+
+/// Renders every metric of one model, labelled with the algorithm name
+pub fn render(
+    algorithm: &str,
+    inserted: usize,
+    rejected: usize,
+    stats: &AlgorithmStats,
+    insert_latency: &Histogram,
+) -> String {
+    let mut out = Exposition {
+        text: String::new(),
+        labels: format!("algorithm=\"{}\"", algorithm.replace('"', "\\\"")),
+    };
+    out.single(
+        "clustermancer_points_inserted_total",
+        "counter",
+        "Points added to the model",
+        inserted,
+    );
+    out.single(
+        "clustermancer_points_rejected_total",
+        "counter",
+        "Points the model rejected as invalid",
+        rejected,
+    );
+    if let Some(sampler) = &stats.sampler {
+        out.single(
+            "clustermancer_points_dropped_total",
+            "counter",
+            "Points dropped by the sampler",
+            sampler.dropped,
+        );
+    }
+    out.single(
+        "clustermancer_micro_clusters",
+        "gauge",
+        "Live micro-clusters",
+        stats.micro_clusters,
+    );
+    out.single_f64(
+        "clustermancer_total_weight",
+        "gauge",
+        "Sum of micro-cluster weights",
+        stats.total_weight,
+    );
+    out.single(
+        "clustermancer_memory_bytes",
+        "gauge",
+        "Estimated memory held by the model",
+        stats.memory_bytes,
+    );
+    match &stats.details {
+        AlgorithmDetails::Birch {
+            nodes,
+            leaves,
+            depth,
+            mean_fan_out,
+            max_fan_out,
+            splits,
+            threshold,
+            rebuilds,
+            outliers,
+            faded,
+        } => {
+            out.single(
+                "clustermancer_birch_nodes",
+                "gauge",
+                "CF-tree nodes",
+                *nodes,
+            );
+            out.single(
+                "clustermancer_birch_leaves",
+                "gauge",
+                "CF-tree leaves",
+                *leaves,
+            );
+            out.single(
+                "clustermancer_birch_depth",
+                "gauge",
+                "CF-tree depth",
+                *depth,
+            );
+            out.single_f64(
+                "clustermancer_birch_mean_fan_out",
+                "gauge",
+                "Mean children per non-leaf node",
+                *mean_fan_out,
+            );
+            out.single(
+                "clustermancer_birch_max_fan_out",
+                "gauge",
+                "Most children of a non-leaf node",
+                *max_fan_out,
+            );
+            out.single(
+                "clustermancer_birch_splits_total",
+                "counter",
+                "CF-tree node splits",
+                *splits,
+            );
+            out.single_f64(
+                "clustermancer_birch_threshold",
+                "gauge",
+                "Current BIRCH threshold",
+                *threshold,
+            );
+            out.single(
+                "clustermancer_birch_rebuilds_total",
+                "counter",
+                "CF-tree rebuilds forced by the node limit",
+                *rebuilds,
+            );
+            out.single(
+                "clustermancer_birch_outliers",
+                "gauge",
+                "Potential outliers moved out of the CF-tree",
+                *outliers,
+            );
+            out.single(
+                "clustermancer_birch_faded_total",
+                "counter",
+                "CF entries removed after fading below the decay floor",
+                *faded,
+            );
+        }
+        AlgorithmDetails::CluStream {
+            merges,
+            replacements,
+            snapshots_per_order,
+        } => {
+            out.single(
+                "clustermancer_clustream_merges_total",
+                "counter",
+                "Merges of the closest micro-cluster pair",
+                *merges,
+            );
+            out.single(
+                "clustermancer_clustream_replacements_total",
+                "counter",
+                "Least relevant micro-clusters replaced by new ones",
+                *replacements,
+            );
+            out.header(
+                "clustermancer_clustream_snapshots",
+                "gauge",
+                "Snapshots stored in the pyramidal time frame",
+            );
+            for (order, count) in snapshots_per_order.iter().enumerate() {
+                out.sample(
+                    "clustermancer_clustream_snapshots",
+                    &format!("order=\"{}\"", order),
+                    *count as f64,
+                );
+            }
+        }
+        AlgorithmDetails::DenStream {
+            potential_micro_clusters,
+            outlier_micro_clusters,
+            potential_pruned,
+            outliers_pruned,
+            promotions,
+        } => {
+            out.single(
+                "clustermancer_denstream_potential_micro_clusters",
+                "gauge",
+                "Potential micro-clusters",
+                *potential_micro_clusters,
+            );
+            out.single(
+                "clustermancer_denstream_outlier_micro_clusters",
+                "gauge",
+                "Outlier micro-clusters",
+                *outlier_micro_clusters,
+            );
+            out.header(
+                "clustermancer_denstream_pruned_total",
+                "counter",
+                "Pruned micro-clusters",
+            );
+            out.sample(
+                "clustermancer_denstream_pruned_total",
+                "kind=\"potential\"",
+                *potential_pruned as f64,
+            );
+            out.sample(
+                "clustermancer_denstream_pruned_total",
+                "kind=\"outlier\"",
+                *outliers_pruned as f64,
+            );
+            out.single(
+                "clustermancer_denstream_promotions_total",
+                "counter",
+                "Outlier micro-clusters promoted to potential ones",
+                *promotions,
+            );
+        }
+    }
+    out.histogram(
+        "clustermancer_insert_duration_seconds",
+        "Time spent inserting a single point",
+        insert_latency,
+    );
+    out.text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::SamplerStats;
+
+    #[test]
+    fn test_render() {
+        let mut latency = Histogram::new(&[0.1, 1.]);
+        latency.observe(0.05);
+        latency.observe(0.5);
+        latency.observe(5.);
+        let stats = AlgorithmStats {
+            micro_clusters: 3,
+            total_weight: 4.,
+            memory_bytes: 100,
+            details: AlgorithmDetails::CluStream {
+                merges: 1,
+                replacements: 2,
+                snapshots_per_order: vec![4, 2],
+            },
+            sampler: Some(SamplerStats {
+                accepted: 4,
+                dropped: 6,
+            }),
+        };
+        let text = render("CluStream", 10, 1, &stats, &latency);
+        assert!(text.contains("clustermancer_points_dropped_total{algorithm=\"CluStream\"} 6\n"));
+        assert!(text.contains("# TYPE clustermancer_clustream_merges_total counter\n"));
+        assert!(text.contains(
+            "clustermancer_clustream_snapshots{algorithm=\"CluStream\",order=\"1\"} 2\n"
+        ));
+        assert!(text.contains(
+            "clustermancer_insert_duration_seconds_bucket{algorithm=\"CluStream\",le=\"1\"} 2\n"
+        ));
+        assert!(text.contains(
+            "clustermancer_insert_duration_seconds_bucket{algorithm=\"CluStream\",le=\"+Inf\"} 3\n"
+        ));
+    }
+}
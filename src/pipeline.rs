@@ -0,0 +1,237 @@
+use crate::algorithms::{predict, ClusteringElement, DataStreamClusteringAlgorithm};
+use crate::formats::parse_point;
+use csv::Writer;
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    /// Comma separated coordinates, an optional header line is skipped
+    Csv,
+    /// One JSON value per line, either `[x, y, ...]` or `{"id": .., "point": [x, y, ...]}`
+    Ndjson,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub format: InputFormat,
+    /// How many points may arrive before the cached clustering used for assignments is refreshed
+    pub refresh_every: usize,
+    /// Write `clusters()` to the snapshot output every N points, N > 0
+    pub snapshot_every: Option<usize>,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        PipelineOptions {
+            format: InputFormat::Csv,
+            refresh_every: 100,
+            snapshot_every: None,
+        }
+    }
+}
+
+fn invalid_line(line_no: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line_no, message),
+    )
+}
+
+/// Parses one input line into an optional caller supplied id and the point
+fn parse_line(format: InputFormat, line: &str) -> Result<(Option<String>, Vec<f64>), String> {
+    match format {
+        InputFormat::Csv => parse_point(line).map(|point| (None, point)),
+        InputFormat::Ndjson => {
+            let value: Value = serde_json::from_str(line).map_err(|err| err.to_string())?;
+            let (id, point) = match &value {
+                Value::Array(_) => (None, &value),
+                Value::Object(object) => (
+                    object.get("id").map(|id| match id {
+                        Value::String(id) => id.clone(),
+                        other => other.to_string(),
+                    }),
+                    object
+                        .get("point")
+                        .ok_or_else(|| "missing \"point\" field".to_string())?,
+                ),
+                _ => return Err("expected an array or an object".to_string()),
+            };
+            let point = point
+                .as_array()
+                .ok_or_else(|| "\"point\" must be an array".to_string())?
+                .iter()
+                .map(|x| x.as_f64().ok_or_else(|| format!("invalid number '{}'", x)))
+                .collect::<Result<Vec<f64>, String>>()?;
+            Ok((id, point))
+        }
+    }
+}
+
+fn write_snapshot<S: Write>(
+    writer: &mut Writer<S>,
+    points: usize,
+    clusters: &[ClusteringElement],
+) -> io::Result<()> {
+    for cluster in clusters {
+        writer.write_record(&[
+            points.to_string(),
+            cluster
+                .center
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            cluster.radius.to_string(),
+            cluster.cluster.to_string(),
+            cluster.weight.to_string(),
+        ])?;
+    }
+    writer.flush()
+}
+
+/// Feeds every point read from `input` into `algorithm` and writes a `point_id,cluster_id`
+/// line per point to `output`. Points are assigned to the closest micro-cluster of a cached
+/// clustering, `cluster_id` is empty until the algorithm reports any clusters.
+/// Returns the number of points processed; fails before reading any input when
+/// `snapshot_every` is 0.
+pub fn run<R: BufRead, W: Write, S: Write>(
+    algorithm: &mut dyn DataStreamClusteringAlgorithm,
+    input: R,
+    mut output: W,
+    snapshots: Option<S>,
+    options: &PipelineOptions,
+) -> io::Result<usize> {
+    if options.snapshot_every == Some(0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "snapshot_every has to be at least 1",
+        ));
+    }
+    let mut snapshot_writer = snapshots.map(Writer::from_writer);
+    if let Some(writer) = snapshot_writer.as_mut() {
+        writer.write_record(["points", "center", "radius", "cluster", "weight"])?;
+    }
+    let mut clusters: Vec<ClusteringElement> = Vec::new();
+    let mut since_refresh = 0;
+    let mut points = 0;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (id, point) = match parse_line(options.format, line) {
+            Ok(parsed) => parsed,
+            // Header line
+            Err(_) if line_no == 0 && options.format == InputFormat::Csv => continue,
+            Err(message) => return Err(invalid_line(line_no + 1, message)),
+        };
+        algorithm
+            .insert(point.clone())
+            .map_err(|err| invalid_line(line_no + 1, err.to_string()))?;
+        points += 1;
+        since_refresh += 1;
+        if clusters.is_empty() || since_refresh >= options.refresh_every {
+            clusters = algorithm.clusters().map_err(io::Error::other)?;
+            since_refresh = 0;
+        }
+        writeln!(
+            output,
+            "{},{}",
+            id.unwrap_or_else(|| (points - 1).to_string()),
+            predict(&clusters, &point)
+                .map(|cluster| cluster.to_string())
+                .unwrap_or_default()
+        )?;
+        if let (Some(writer), Some(every)) = (snapshot_writer.as_mut(), options.snapshot_every) {
+            if points % every == 0 {
+                let clusters = algorithm.clusters().map_err(io::Error::other)?;
+                write_snapshot(writer, points, &clusters)?;
+            }
+        }
+    }
+    output.flush()?;
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::birch::Birch;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line(InputFormat::Csv, "1, 2.5").unwrap(),
+            (None, vec![1., 2.5])
+        );
+        assert_eq!(
+            parse_line(InputFormat::Ndjson, r#"{"id": "a", "point": [1, 2]}"#).unwrap(),
+            (Some("a".to_string()), vec![1., 2.])
+        );
+        assert!(parse_line(InputFormat::Ndjson, r#"{"id": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let input = "x,y\n0,0\n0,1\n10,10\n";
+        let mut output = Vec::new();
+        let mut snapshots = Vec::new();
+        let mut birch = Birch::new(1., 50, 2).unwrap();
+        let options = PipelineOptions {
+            refresh_every: 1,
+            snapshot_every: Some(3),
+            ..Default::default()
+        };
+        let points = run(
+            &mut birch,
+            input.as_bytes(),
+            &mut output,
+            Some(&mut snapshots),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(points, 3);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.starts_with("0,"));
+        assert!(String::from_utf8(snapshots)
+            .unwrap()
+            .starts_with("points,center"));
+    }
+
+    #[test]
+    fn test_run_rejects_zero_snapshot_interval() {
+        let mut birch = Birch::new(1., 50, 2).unwrap();
+        let options = PipelineOptions {
+            snapshot_every: Some(0),
+            ..Default::default()
+        };
+        let err = run(
+            &mut birch,
+            "0,0\n".as_bytes(),
+            Vec::new(),
+            Some(Vec::new()),
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_rejects_dimension_change() {
+        let mut birch = Birch::new(1., 50, 2).unwrap();
+        let err = run(
+            &mut birch,
+            "0,0\n0,1,2\n".as_bytes(),
+            Vec::new(),
+            None::<Vec<u8>>,
+            &PipelineOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("line 2: expected a point of dimension 2"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Feature hashing ("hashing trick"): key/value records of unbounded vocabulary become
+//! sparse vectors of a fixed dimension, without keeping a dictionary of the keys.
+
+use crate::algorithms::{ClusteringError, SparseVector};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct FeatureHasher {
+    dimension: usize,
+    signed: bool,
+}
+
+impl FeatureHasher {
+    pub fn new(dimension: usize) -> Result<Self, ClusteringError> {
+        if dimension == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "dimension",
+                "must be at least 1",
+            ));
+        }
+        Ok(FeatureHasher {
+            dimension,
+            signed: true,
+        })
+    }
+
+    /// By default a second hash bit picks the sign of every value, so colliding keys
+    /// cancel out on average instead of piling up
+    pub fn with_signed(mut self, signed: bool) -> Self {
+        self.signed = signed;
+        self
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Dimension and sign of a key
+    pub fn index(&self, key: &str) -> (usize, f64) {
+        let hash = fnv1a(key.as_bytes());
+        let sign = if self.signed && hash >> 63 == 1 {
+            -1.
+        } else {
+            1.
+        };
+        ((hash % self.dimension as u64) as usize, sign)
+    }
+
+    /// Values of keys that land on the same dimension are summed
+    pub fn hash<K: AsRef<str>>(
+        &self,
+        record: impl IntoIterator<Item = (K, f64)>,
+    ) -> Result<SparseVector, ClusteringError> {
+        let entries = record
+            .into_iter()
+            .map(|(key, value)| {
+                let (index, sign) = self.index(key.as_ref());
+                (index, sign * value)
+            })
+            .collect();
+        SparseVector::new(self.dimension, entries)
+    }
+
+    /// Bag of words, every occurrence of a token counts 1
+    pub fn hash_tokens<K: AsRef<str>>(
+        &self,
+        tokens: impl IntoIterator<Item = K>,
+    ) -> Result<SparseVector, ClusteringError> {
+        self.hash(tokens.into_iter().map(|token| (token, 1.)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let hasher = FeatureHasher::new(1 << 12).unwrap().with_signed(false);
+        let vector = hasher
+            .hash([("bytes", 1500.), ("duration", 0.25), ("bytes", 500.)])
+            .unwrap();
+        assert_eq!(vector.dimension(), 4096);
+        assert_eq!(vector.nnz(), 2);
+        assert_eq!(vector.to_dense()[hasher.index("bytes").0], 2000.);
+
+        let tokens = hasher.hash_tokens("get /index get".split(' ')).unwrap();
+        assert_eq!(tokens.to_dense()[hasher.index("get").0], 2.);
+        assert!(FeatureHasher::new(0).is_err());
+        assert!(hasher.hash([("x", f64::NAN)]).is_err());
+        // Reference value of 64-bit FNV-1a
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,166 @@
+//! Streaming stages that transform points before they reach an algorithm and map the
+//! resulting clusters back into input units
+
+pub mod hashing;
+pub mod normalization;
+pub mod projection;
+pub mod quantile;
+
+use crate::algorithms::{
+    AlgorithmStats, ClusterEventListener, ClusteringElement, ClusteringError,
+    DataStreamClusteringAlgorithm, MixedPoint, Schema,
+};
+
+pub trait Preprocessor: Send {
+    /// Learns from a raw point, called right before the point is transformed
+    fn update(&mut self, point: &[f64]);
+    fn transform(&self, point: &[f64]) -> Vec<f64>;
+    /// Maps a point of the transformed space back into input units
+    fn inverse_transform(&self, point: &[f64]) -> Vec<f64>;
+    /// Maps a difference between points of the transformed space back into input units,
+    /// the linear part of `inverse_transform`
+    fn inverse_direction(&self, direction: &[f64]) -> Vec<f64>;
+    /// Maps a radius of the transformed space back into input units
+    fn inverse_radius(&self, radius: f64) -> f64;
+    /// Whether transformed points keep the input dimension
+    fn preserves_dimension(&self) -> bool {
+        true
+    }
+    fn name(&self) -> String;
+}
+
+/// Runs every point through `preprocessor` before handing it to the wrapped algorithm.
+/// The preprocessor keeps learning, so older points were transformed with older
+/// parameters; `clusters()` maps back with the current ones.
+pub struct Preprocessed<P> {
+    algorithm: Box<dyn DataStreamClusteringAlgorithm>,
+    preprocessor: P,
+    schema: Schema,
+}
+
+impl<P: Preprocessor> Preprocessed<P> {
+    pub fn new(algorithm: Box<dyn DataStreamClusteringAlgorithm>, preprocessor: P) -> Self {
+        // The wrapped algorithm's schema only describes the input if the dimension is kept
+        let schema = if preprocessor.preserves_dimension() {
+            algorithm.schema()
+        } else {
+            Schema::new()
+        };
+        Self {
+            algorithm,
+            preprocessor,
+            schema,
+        }
+    }
+
+    /// Fixes the accepted input dimension (and feature names), see `Schema` for when it fails
+    pub fn with_schema(mut self, schema: Schema) -> Result<Self, ClusteringError> {
+        self.schema.replace(schema)?;
+        Ok(self)
+    }
+
+    pub fn preprocessor(&self) -> &P {
+        &self.preprocessor
+    }
+
+    /// Columns of the linear part of `inverse_transform`, one per transformed coordinate
+    fn inverse_columns(&self, dimension: usize) -> Vec<Vec<f64>> {
+        (0..dimension)
+            .map(|j| {
+                let mut unit = vec![0.; dimension];
+                unit[j] = 1.;
+                self.preprocessor.inverse_direction(&unit)
+            })
+            .collect()
+    }
+}
+
+/// Variances in input units: exact when the inverse scales every coordinate on its own,
+/// otherwise it assumes the transformed coordinates are uncorrelated
+fn inverse_variance(columns: &[Vec<f64>], variance: &[f64]) -> Vec<f64> {
+    let dimension = columns.first().map_or(0, Vec::len);
+    (0..dimension)
+        .map(|i| {
+            columns
+                .iter()
+                .zip(variance.iter())
+                .map(|(column, v)| column[i] * column[i] * v)
+                .sum()
+        })
+        .collect()
+}
+
+/// `J · Σ · Jᵀ` for the Jacobian `J` of the inverse
+fn inverse_covariance(columns: &[Vec<f64>], covariance: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let dimension = columns.first().map_or(0, Vec::len);
+    (0..dimension)
+        .map(|i| {
+            (0..dimension)
+                .map(|j| {
+                    covariance
+                        .iter()
+                        .enumerate()
+                        .map(|(a, row)| {
+                            row.iter()
+                                .enumerate()
+                                .map(|(b, c)| columns[a][i] * c * columns[b][j])
+                                .sum::<f64>()
+                        })
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl<P: Preprocessor> DataStreamClusteringAlgorithm for Preprocessed<P> {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError> {
+        self.schema.validate(&data)?;
+        self.preprocessor.update(&data);
+        self.algorithm.insert(self.preprocessor.transform(&data))
+    }
+    /// Only the numeric part goes through the preprocessor
+    fn insert_mixed(&mut self, data: &MixedPoint) -> Result<(), ClusteringError> {
+        self.schema.validate_mixed(data)?;
+        self.preprocessor.update(data.numeric());
+        self.algorithm.insert_mixed(&MixedPoint::new(
+            self.preprocessor.transform(data.numeric()),
+            data.categorical().to_vec(),
+        ))
+    }
+    fn name(&self) -> String {
+        format!("({}, {})", self.preprocessor.name(), self.algorithm.name())
+    }
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError> {
+        Ok(self
+            .algorithm
+            .clusters()?
+            .into_iter()
+            .map(|element| {
+                let columns = self.inverse_columns(element.center.len());
+                ClusteringElement {
+                    center: self.preprocessor.inverse_transform(&element.center),
+                    radius: self.preprocessor.inverse_radius(element.radius),
+                    variance: element
+                        .variance
+                        .as_ref()
+                        .map(|variance| inverse_variance(&columns, variance)),
+                    covariance: element
+                        .covariance
+                        .as_ref()
+                        .map(|covariance| inverse_covariance(&columns, covariance)),
+                    ..element
+                }
+            })
+            .collect())
+    }
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn stats(&self) -> AlgorithmStats {
+        self.algorithm.stats()
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.algorithm.subscribe(listener);
+    }
+}
@@ -0,0 +1,347 @@
+//! Per-dimension affine scalers, `(x - offset) / scale`, with parameters learnt online
+
+use super::quantile::P2Quantile;
+use super::Preprocessor;
+
+/// Offset and scale of dimension `i`. Dimensions without a usable spread get a scale of 1
+/// so constant features do not blow up.
+trait Affine {
+    fn affine(&self, i: usize) -> (f64, f64);
+    fn dimension(&self) -> usize;
+}
+
+fn usable_scale(scale: f64) -> f64 {
+    if scale.is_finite() && scale > 0. {
+        scale
+    } else {
+        1.
+    }
+}
+
+fn affine_transform(scaler: &impl Affine, point: &[f64]) -> Vec<f64> {
+    point
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let (offset, scale) = scaler.affine(i);
+            (x - offset) / scale
+        })
+        .collect()
+}
+
+fn affine_inverse(scaler: &impl Affine, point: &[f64]) -> Vec<f64> {
+    point
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let (offset, scale) = scaler.affine(i);
+            x * scale + offset
+        })
+        .collect()
+}
+
+fn affine_direction(scaler: &impl Affine, direction: &[f64]) -> Vec<f64> {
+    direction
+        .iter()
+        .enumerate()
+        .map(|(i, x)| x * scaler.affine(i).1)
+        .collect()
+}
+
+/// Radii are scalars, so this assumes the spread is the same in every dimension and
+/// scales by the root mean square of the per-dimension scales
+fn affine_radius(scaler: &impl Affine, radius: f64) -> f64 {
+    let dimension = scaler.dimension();
+    if dimension == 0 {
+        return radius;
+    }
+    let mean_square = (0..dimension)
+        .map(|i| scaler.affine(i).1.powi(2))
+        .sum::<f64>()
+        / dimension as f64;
+    radius * mean_square.sqrt()
+}
+
+/// Running z-score, mean and variance are tracked with Welford's algorithm
+#[derive(Debug, Clone, Default)]
+pub struct ZScore {
+    n: usize,
+    mean: Vec<f64>,
+    /// Sum of squared deviations from the running mean
+    m2: Vec<f64>,
+}
+
+impl ZScore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    /// Population standard deviation per dimension
+    pub fn standard_deviation(&self) -> Vec<f64> {
+        self.m2
+            .iter()
+            .map(|m2| (m2 / self.n.max(1) as f64).sqrt())
+            .collect()
+    }
+}
+
+impl Affine for ZScore {
+    fn affine(&self, i: usize) -> (f64, f64) {
+        match (self.mean.get(i), self.m2.get(i)) {
+            (Some(mean), Some(m2)) => (*mean, usable_scale((m2 / self.n as f64).sqrt())),
+            _ => (0., 1.),
+        }
+    }
+    fn dimension(&self) -> usize {
+        self.mean.len()
+    }
+}
+
+impl Preprocessor for ZScore {
+    fn update(&mut self, point: &[f64]) {
+        if self.mean.is_empty() {
+            self.mean = vec![0.; point.len()];
+            self.m2 = vec![0.; point.len()];
+        }
+        self.n += 1;
+        for ((x, mean), m2) in point
+            .iter()
+            .zip(self.mean.iter_mut())
+            .zip(self.m2.iter_mut())
+        {
+            let delta = x - *mean;
+            *mean += delta / self.n as f64;
+            *m2 += delta * (x - *mean);
+        }
+    }
+    fn transform(&self, point: &[f64]) -> Vec<f64> {
+        affine_transform(self, point)
+    }
+    fn inverse_transform(&self, point: &[f64]) -> Vec<f64> {
+        affine_inverse(self, point)
+    }
+    fn inverse_direction(&self, direction: &[f64]) -> Vec<f64> {
+        affine_direction(self, direction)
+    }
+    fn inverse_radius(&self, radius: f64) -> f64 {
+        affine_radius(self, radius)
+    }
+    fn name(&self) -> String {
+        "ZScore".to_string()
+    }
+}
+
+/// Running min-max scaling into `[0, 1]`. Points outside the range seen so far widen it
+/// before they are transformed, so every inserted point lands inside `[0, 1]`.
+#[derive(Debug, Clone, Default)]
+pub struct MinMax {
+    min: Vec<f64>,
+    max: Vec<f64>,
+}
+
+impl MinMax {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Affine for MinMax {
+    fn affine(&self, i: usize) -> (f64, f64) {
+        match (self.min.get(i), self.max.get(i)) {
+            (Some(min), Some(max)) => (*min, usable_scale(max - min)),
+            _ => (0., 1.),
+        }
+    }
+    fn dimension(&self) -> usize {
+        self.min.len()
+    }
+}
+
+impl Preprocessor for MinMax {
+    fn update(&mut self, point: &[f64]) {
+        if self.min.is_empty() {
+            self.min = point.to_vec();
+            self.max = point.to_vec();
+            return;
+        }
+        for ((x, min), max) in point
+            .iter()
+            .zip(self.min.iter_mut())
+            .zip(self.max.iter_mut())
+        {
+            *min = min.min(*x);
+            *max = max.max(*x);
+        }
+    }
+    fn transform(&self, point: &[f64]) -> Vec<f64> {
+        affine_transform(self, point)
+    }
+    fn inverse_transform(&self, point: &[f64]) -> Vec<f64> {
+        affine_inverse(self, point)
+    }
+    fn inverse_direction(&self, direction: &[f64]) -> Vec<f64> {
+        affine_direction(self, direction)
+    }
+    fn inverse_radius(&self, radius: f64) -> f64 {
+        affine_radius(self, radius)
+    }
+    fn name(&self) -> String {
+        "MinMax".to_string()
+    }
+}
+
+/// Centers on the median and divides by the interquartile range, both estimated with
+/// P² sketches, so a few extreme values barely move the scaling
+#[derive(Debug, Clone, Default)]
+pub struct Robust {
+    /// First quartile, median and third quartile per dimension
+    quartiles: Vec<[P2Quantile; 3]>,
+}
+
+impl Robust {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Affine for Robust {
+    fn affine(&self, i: usize) -> (f64, f64) {
+        let Some([q1, median, q3]) = self.quartiles.get(i) else {
+            return (0., 1.);
+        };
+        match (q1.estimate(), median.estimate(), q3.estimate()) {
+            (Some(q1), Some(median), Some(q3)) => (median, usable_scale(q3 - q1)),
+            _ => (0., 1.),
+        }
+    }
+    fn dimension(&self) -> usize {
+        self.quartiles.len()
+    }
+}
+
+impl Preprocessor for Robust {
+    fn update(&mut self, point: &[f64]) {
+        if self.quartiles.is_empty() {
+            self.quartiles = point
+                .iter()
+                .map(|_| {
+                    [
+                        P2Quantile::new(0.25),
+                        P2Quantile::new(0.5),
+                        P2Quantile::new(0.75),
+                    ]
+                })
+                .collect();
+        }
+        for (x, quartiles) in point.iter().zip(self.quartiles.iter_mut()) {
+            quartiles.iter_mut().for_each(|q| q.observe(*x));
+        }
+    }
+    fn transform(&self, point: &[f64]) -> Vec<f64> {
+        affine_transform(self, point)
+    }
+    fn inverse_transform(&self, point: &[f64]) -> Vec<f64> {
+        affine_inverse(self, point)
+    }
+    fn inverse_direction(&self, direction: &[f64]) -> Vec<f64> {
+        affine_direction(self, direction)
+    }
+    fn inverse_radius(&self, radius: f64) -> f64 {
+        affine_radius(self, radius)
+    }
+    fn name(&self) -> String {
+        "Robust".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Preprocessed;
+    use super::*;
+    use crate::algorithms::clustream::CluStream;
+    use crate::algorithms::DataStreamClusteringAlgorithm;
+
+    #[test]
+    fn test_scalers() {
+        let points = [[1., 100.], [2., 300.], [3., 200.], [4., 400.]];
+        let mut z_score = ZScore::new();
+        let mut min_max = MinMax::new();
+        let mut robust = Robust::new();
+        for point in points.iter() {
+            z_score.update(point);
+            min_max.update(point);
+            robust.update(point);
+        }
+        assert_eq!(z_score.mean(), &[2.5, 250.]);
+        assert!((z_score.standard_deviation()[1] - 125_f64.sqrt() * 10.).abs() < 1e-9);
+        assert_eq!(min_max.transform(&[2.5, 250.]), vec![0.5, 0.5]);
+        for scaler in [&z_score as &dyn Preprocessor, &min_max, &robust] {
+            let round_trip = scaler.inverse_transform(&scaler.transform(&[3., 150.]));
+            assert!((round_trip[0] - 3.).abs() < 1e-9 && (round_trip[1] - 150.).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_constant_dimension() {
+        let mut z_score = ZScore::new();
+        z_score.update(&[5., 1.]);
+        z_score.update(&[5., 3.]);
+        assert_eq!(z_score.transform(&[5., 2.]), vec![0., 0.]);
+    }
+
+    #[test]
+    fn test_preprocessed_clusters_in_input_units() {
+        let clustream = CluStream::new(2).unwrap();
+        let mut model = Preprocessed::new(Box::new(clustream), ZScore::new());
+        for i in 0..200 {
+            let (x, y) = if i % 2 == 0 { (0., 0.) } else { (10., 10_000.) };
+            model.insert(vec![x + (i % 3) as f64 * 0.01, y]).unwrap();
+        }
+        assert_eq!(model.name(), "(ZScore, CluStream)");
+        let clusters = model.clusters().unwrap();
+        assert!(clusters.iter().any(|c| c.center[1] > 9_000.));
+        assert!(clusters.iter().any(|c| c.center[1] < 1_000.));
+    }
+
+    #[test]
+    fn test_preprocessed_schema_stays_locked() {
+        use crate::algorithms::Schema;
+        let mut model = Preprocessed::new(Box::new(CluStream::new(2).unwrap()), ZScore::new());
+        model.insert(vec![1., 2.]).unwrap();
+        let model = model
+            .with_schema(Schema::with_dimension(2).unwrap())
+            .unwrap();
+        assert!(model
+            .with_schema(Schema::with_dimension(3).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_preprocessed_spread_in_input_units() {
+        use crate::algorithms::{birch::Birch, Spread};
+        let birch = Birch::new(10., 10, 1)
+            .unwrap()
+            .with_spread(Spread::Covariance)
+            .unwrap();
+        let mut model = Preprocessed::new(Box::new(birch), MinMax::new());
+        // x over [0, 1], y = 1000·x over [0, 1000]. The extremes come first, so every
+        // point is scaled with the final range.
+        for i in [0, 10, 1, 2, 3, 4, 5, 6, 7, 8, 9] {
+            let x = i as f64 / 10.;
+            model.insert(vec![x, 1_000. * x]).unwrap();
+        }
+        let clusters = model.clusters().unwrap();
+        assert_eq!(clusters.len(), 1);
+        let variance = clusters[0].variance.clone().unwrap();
+        let covariance = clusters[0].covariance.clone().unwrap();
+        // The variance of 0, 0.1, ..., 1 is 0.1
+        assert!((variance[0] - 0.1).abs() < 1e-9);
+        assert!((variance[1] - 0.1e6).abs() < 1e-3);
+        assert!((covariance[0][1] - 100.).abs() < 1e-6);
+        assert!((covariance[1][1] - variance[1]).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,440 @@
+//! Dimensionality reduction stages. Both are set up from the first point, so the input
+//! dimension does not have to be known in advance.
+
+use super::Preprocessor;
+use crate::algorithms::ClusteringError;
+use rand::prelude::*;
+use rand_pcg::Pcg64;
+use statrs::distribution::Normal;
+
+/// How much CCIPCA favours recent points over old ones, 0 weighs all points equally
+const AMNESIA: f64 = 2.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionKind {
+    /// Dense matrix with N(0, 1/k) entries
+    Gaussian,
+    /// Li et al.'s very sparse projection, a fraction `1/sqrt(d)` of the entries is non-zero
+    Sparse,
+}
+
+#[derive(Debug, Clone)]
+enum Matrix {
+    Dense(Vec<Vec<f64>>),
+    /// Non-zero `(column, value)` pairs per row
+    Sparse(Vec<Vec<(usize, f64)>>),
+}
+
+impl Matrix {
+    fn multiply(&self, point: &[f64]) -> Vec<f64> {
+        match self {
+            Matrix::Dense(rows) => rows
+                .iter()
+                .map(|row| row.iter().zip(point.iter()).map(|(r, x)| r * x).sum())
+                .collect(),
+            Matrix::Sparse(rows) => rows
+                .iter()
+                .map(|row| row.iter().map(|(j, r)| r * point[*j]).sum())
+                .collect(),
+        }
+    }
+
+    fn to_dense(&self, input_dimension: usize) -> Vec<Vec<f64>> {
+        match self {
+            Matrix::Dense(rows) => rows.clone(),
+            Matrix::Sparse(rows) => rows
+                .iter()
+                .map(|row| {
+                    let mut dense = vec![0.; input_dimension];
+                    row.iter().for_each(|(j, r)| dense[*j] = *r);
+                    dense
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Inverse of a small square matrix by Gauss-Jordan elimination, `None` if it is singular
+fn invert(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1. } else { 0. }).collect())
+        .collect();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|a, b| {
+            matrix[*a][column]
+                .abs()
+                .total_cmp(&matrix[*b][column].abs())
+        })?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+        let scale = matrix[column][column];
+        for j in 0..n {
+            matrix[column][j] /= scale;
+            inverse[column][j] /= scale;
+        }
+        for row in 0..n {
+            if row == column {
+                continue;
+            }
+            let factor = matrix[row][column];
+            for j in 0..n {
+                matrix[row][j] -= factor * matrix[column][j];
+                inverse[row][j] -= factor * inverse[column][j];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// Johnson–Lindenstrauss random projection onto `output_dimension` dimensions. Pairwise
+/// distances are preserved up to a small factor, so radii are reported unchanged; centers are
+/// mapped back with the pseudo-inverse, i.e. to the closest point that projects onto them.
+#[derive(Debug, Clone)]
+pub struct RandomProjection {
+    kind: ProjectionKind,
+    output_dimension: usize,
+    rng: Pcg64,
+    /// `output_dimension × d`, `None` until the first point, and while the input is not
+    /// larger than the output (no reduction needed, points pass through)
+    matrix: Option<Matrix>,
+    /// `d × output_dimension`
+    pseudo_inverse: Vec<Vec<f64>>,
+    initialised: bool,
+}
+
+impl RandomProjection {
+    pub fn new(kind: ProjectionKind, output_dimension: usize) -> Result<Self, ClusteringError> {
+        if output_dimension == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "output_dimension",
+                "must be at least 1",
+            ));
+        }
+        Ok(RandomProjection {
+            kind,
+            output_dimension,
+            rng: Pcg64::from_entropy(),
+            matrix: None,
+            pseudo_inverse: Vec::new(),
+            initialised: false,
+        })
+    }
+
+    /// Makes the drawn matrix reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Pcg64::seed_from_u64(seed);
+        self
+    }
+
+    fn initialise(&mut self, input_dimension: usize) {
+        self.initialised = true;
+        if input_dimension <= self.output_dimension {
+            tracing::warn!(
+                input_dimension,
+                output_dimension = self.output_dimension,
+                "random projection would not reduce the dimension, passing points through"
+            );
+            return;
+        }
+        let k = self.output_dimension;
+        let matrix = match self.kind {
+            ProjectionKind::Gaussian => {
+                let normal = Normal::new(0., 1. / (k as f64).sqrt()).unwrap();
+                Matrix::Dense(
+                    (0..k)
+                        .map(|_| {
+                            (0..input_dimension)
+                                .map(|_| self.rng.sample(normal))
+                                .collect()
+                        })
+                        .collect(),
+                )
+            }
+            ProjectionKind::Sparse => {
+                let s = (input_dimension as f64).sqrt();
+                let value = (s / k as f64).sqrt();
+                Matrix::Sparse(
+                    (0..k)
+                        .map(|_| {
+                            (0..input_dimension)
+                                .filter_map(|j| {
+                                    let draw: f64 = self.rng.gen();
+                                    if draw < 1. / (2. * s) {
+                                        Some((j, value))
+                                    } else if draw < 1. / s {
+                                        Some((j, -value))
+                                    } else {
+                                        None
+                                    }
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                )
+            }
+        };
+        // pinv(R) = Rᵀ (R Rᵀ)⁻¹, with a scaled transpose if R happens to be rank deficient
+        let dense = matrix.to_dense(input_dimension);
+        let gram: Vec<Vec<f64>> = dense
+            .iter()
+            .map(|a| {
+                dense
+                    .iter()
+                    .map(|b| a.iter().zip(b.iter()).map(|(x, y)| x * y).sum())
+                    .collect()
+            })
+            .collect();
+        let gram_inverse = invert(gram).unwrap_or_else(|| {
+            let scale = k as f64 / input_dimension as f64;
+            (0..k)
+                .map(|i| (0..k).map(|j| if i == j { scale } else { 0. }).collect())
+                .collect()
+        });
+        self.pseudo_inverse = (0..input_dimension)
+            .map(|j| {
+                (0..k)
+                    .map(|c| (0..k).map(|i| dense[i][j] * gram_inverse[i][c]).sum())
+                    .collect()
+            })
+            .collect();
+        self.matrix = Some(matrix);
+    }
+}
+
+impl Preprocessor for RandomProjection {
+    fn update(&mut self, point: &[f64]) {
+        if !self.initialised {
+            self.initialise(point.len());
+        }
+    }
+    fn transform(&self, point: &[f64]) -> Vec<f64> {
+        match &self.matrix {
+            Some(matrix) => matrix.multiply(point),
+            None => point.to_vec(),
+        }
+    }
+    fn inverse_transform(&self, point: &[f64]) -> Vec<f64> {
+        if self.matrix.is_none() {
+            return point.to_vec();
+        }
+        self.pseudo_inverse
+            .iter()
+            .map(|row| row.iter().zip(point.iter()).map(|(p, y)| p * y).sum())
+            .collect()
+    }
+    /// The inverse is linear already
+    fn inverse_direction(&self, direction: &[f64]) -> Vec<f64> {
+        self.inverse_transform(direction)
+    }
+    fn inverse_radius(&self, radius: f64) -> f64 {
+        radius
+    }
+    fn preserves_dimension(&self) -> bool {
+        false
+    }
+    fn name(&self) -> String {
+        format!("{:?}Projection({})", self.kind, self.output_dimension)
+    }
+}
+
+/// Incremental PCA with CCIPCA (Weng et al., 2003): the leading `output_dimension`
+/// eigenvectors of the covariance are refined with every point, without ever forming the
+/// covariance matrix. Points are projected onto the current basis, so the coordinates of
+/// older micro-clusters drift while the basis settles.
+#[derive(Debug, Clone)]
+pub struct IncrementalPca {
+    output_dimension: usize,
+    n: usize,
+    mean: Vec<f64>,
+    /// Unnormalised eigenvector estimates, their norms estimate the eigenvalues
+    components: Vec<Vec<f64>>,
+}
+
+impl IncrementalPca {
+    pub fn new(output_dimension: usize) -> Result<Self, ClusteringError> {
+        if output_dimension == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "output_dimension",
+                "must be at least 1",
+            ));
+        }
+        Ok(IncrementalPca {
+            output_dimension,
+            n: 0,
+            mean: Vec::new(),
+            components: Vec::new(),
+        })
+    }
+
+    /// Current unit-length basis, missing components (too few points so far) are zero
+    pub fn basis(&self) -> Vec<Vec<f64>> {
+        self.components
+            .iter()
+            .map(|component| {
+                let norm = component.iter().map(|x| x * x).sum::<f64>().sqrt();
+                if norm > 0. {
+                    component.iter().map(|x| x / norm).collect()
+                } else {
+                    component.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Estimated variance along each component
+    pub fn explained_variance(&self) -> Vec<f64> {
+        self.components
+            .iter()
+            .map(|component| component.iter().map(|x| x * x).sum::<f64>().sqrt())
+            .collect()
+    }
+}
+
+impl Preprocessor for IncrementalPca {
+    fn update(&mut self, point: &[f64]) {
+        if self.mean.is_empty() {
+            let components = self.output_dimension.min(point.len());
+            self.mean = vec![0.; point.len()];
+            self.components = vec![vec![0.; point.len()]; components];
+        }
+        self.n += 1;
+        let n = self.n as f64;
+        for (mean, x) in self.mean.iter_mut().zip(point.iter()) {
+            *mean += (x - *mean) / n;
+        }
+        let mut residual: Vec<f64> = point
+            .iter()
+            .zip(self.mean.iter())
+            .map(|(x, m)| x - m)
+            .collect();
+        let amnesia = AMNESIA.min((n - 2.).max(0.));
+        for (i, component) in self.components.iter_mut().enumerate() {
+            if self.n == i + 1 {
+                // The i-th component starts out as the i-th residual
+                component.clone_from(&residual);
+                break;
+            }
+            let norm = component.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm == 0. {
+                component.clone_from(&residual);
+                break;
+            }
+            let projection: f64 = residual
+                .iter()
+                .zip(component.iter())
+                .map(|(r, v)| r * v)
+                .sum::<f64>()
+                / norm;
+            for (v, r) in component.iter_mut().zip(residual.iter()) {
+                *v = (n - 1. - amnesia) / n * *v + (1. + amnesia) / n * r * projection;
+            }
+            // Remove the part explained by this component before estimating the next one
+            let norm = component.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm > 0. {
+                let along: f64 = residual
+                    .iter()
+                    .zip(component.iter())
+                    .map(|(r, v)| r * v)
+                    .sum::<f64>()
+                    / norm;
+                for (r, v) in residual.iter_mut().zip(component.iter()) {
+                    *r -= along * v / norm;
+                }
+            }
+        }
+    }
+    fn transform(&self, point: &[f64]) -> Vec<f64> {
+        self.basis()
+            .iter()
+            .map(|direction| {
+                point
+                    .iter()
+                    .zip(self.mean.iter())
+                    .zip(direction.iter())
+                    .map(|((x, m), v)| (x - m) * v)
+                    .sum()
+            })
+            .collect()
+    }
+    fn inverse_transform(&self, point: &[f64]) -> Vec<f64> {
+        let mut restored = self.mean.clone();
+        for (y, direction) in point.iter().zip(self.basis().iter()) {
+            for (x, v) in restored.iter_mut().zip(direction.iter()) {
+                *x += y * v;
+            }
+        }
+        restored
+    }
+    fn inverse_direction(&self, direction: &[f64]) -> Vec<f64> {
+        let mut restored = vec![0.; self.mean.len()];
+        for (y, direction) in direction.iter().zip(self.basis().iter()) {
+            for (x, v) in restored.iter_mut().zip(direction.iter()) {
+                *x += y * v;
+            }
+        }
+        restored
+    }
+    fn inverse_radius(&self, radius: f64) -> f64 {
+        radius
+    }
+    fn preserves_dimension(&self) -> bool {
+        false
+    }
+    fn name(&self) -> String {
+        format!("IncrementalPca({})", self.output_dimension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Euclidean, Metric};
+
+    #[test]
+    fn test_random_projection() {
+        for kind in [ProjectionKind::Gaussian, ProjectionKind::Sparse] {
+            let mut projection = RandomProjection::new(kind, 20).unwrap().with_seed(3);
+            let a: Vec<f64> = (0..400).map(|i| (i % 7) as f64).collect();
+            let b: Vec<f64> = (0..400).map(|i| (i % 5) as f64).collect();
+            projection.update(&a);
+            let (pa, pb) = (projection.transform(&a), projection.transform(&b));
+            assert_eq!(pa.len(), 20);
+            let original = Euclidean.distance(&a, &b);
+            let projected = Euclidean.distance(&pa, &pb);
+            assert!((projected / original - 1.).abs() < 0.5, "{:?}", kind);
+            // The pseudo-inverse maps back onto a point with the same projection
+            let restored = projection.transform(&projection.inverse_transform(&pa));
+            assert!(restored
+                .iter()
+                .zip(pa.iter())
+                .all(|(r, p)| (r - p).abs() < 1e-6));
+        }
+        let mut small = RandomProjection::new(ProjectionKind::Gaussian, 5).unwrap();
+        small.update(&[1., 2.]);
+        assert_eq!(small.transform(&[1., 2.]), vec![1., 2.]);
+    }
+
+    #[test]
+    fn test_incremental_pca() {
+        let mut rng = Pcg64::seed_from_u64(11);
+        let mut pca = IncrementalPca::new(1).unwrap();
+        // Points along (1, 1, 0) with a little noise
+        for _ in 0..5_000 {
+            let t: f64 = rng.gen_range(-10.0..10.0);
+            let noise: f64 = rng.gen_range(-0.1..0.1);
+            pca.update(&[t + 5., t + noise, noise]);
+        }
+        let direction = &pca.basis()[0];
+        let alignment = (direction[0] + direction[1]).abs() / 2_f64.sqrt();
+        assert!(alignment > 0.99, "{:?}", direction);
+        let restored = pca.inverse_transform(&pca.transform(&[8., 3., 0.]));
+        assert!(restored
+            .iter()
+            .zip([8., 3., 0.])
+            .all(|(r, x)| (r - x).abs() < 0.2));
+    }
+}
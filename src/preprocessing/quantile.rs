@@ -0,0 +1,134 @@
+/// Streaming estimate of a single quantile in constant memory, using the P² algorithm
+/// (Jain & Chlamtac, 1985): five markers whose heights are adjusted with a piecewise
+/// parabolic fit as observations arrive.
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    /// Marker heights
+    heights: [f64; 5],
+    /// Actual marker positions, 1-based
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// `p` is clamped to `[0, 1]`
+    pub fn new(p: f64) -> Self {
+        let p = p.clamp(0., 1.);
+        P2Quantile {
+            p,
+            count: 0,
+            heights: [0.; 5],
+            positions: [1., 2., 3., 4., 5.],
+            desired: [1., 1. + 2. * p, 1. + 4. * p, 3. + 2. * p, 5.],
+            increments: [0., p / 2., p, (1. + p) / 2., 1.],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn observe(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let cell = if x < self.heights[0] {
+            self.heights[0] = x;
+            0
+        } else if x >= self.heights[4] {
+            self.heights[4] = x;
+            3
+        } else {
+            (1..5).find(|&i| x < self.heights[i]).unwrap() - 1
+        };
+        for position in self.positions.iter_mut().skip(cell + 1) {
+            *position += 1.;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments.iter()) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            if (d >= 1. && self.positions[i + 1] - self.positions[i] > 1.)
+                || (d <= -1. && self.positions[i - 1] - self.positions[i] < -1.)
+            {
+                let d = d.signum();
+                let parabolic = self.parabolic(i, d);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, d)
+                    };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let j = if d > 0. { i + 1 } else { i - 1 };
+        self.heights[i]
+            + d * (self.heights[j] - self.heights[i]) / (self.positions[j] - self.positions[i])
+    }
+
+    /// `None` before the first observation. Exact (nearest rank) for up to five observations.
+    pub fn estimate(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..=4 => {
+                let mut seen = self.heights[..self.count].to_vec();
+                seen.sort_by(f64::total_cmp);
+                Some(seen[((self.count - 1) as f64 * self.p).round() as usize])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand_pcg::Pcg64;
+
+    #[test]
+    fn test_estimate() {
+        let mut rng = Pcg64::seed_from_u64(7);
+        let mut values: Vec<f64> = (0..10_000).map(f64::from).collect();
+        values.shuffle(&mut rng);
+        let mut quantiles = [
+            P2Quantile::new(0.25),
+            P2Quantile::new(0.5),
+            P2Quantile::new(0.9),
+        ];
+        assert_eq!(quantiles[0].estimate(), None);
+        for value in values {
+            quantiles.iter_mut().for_each(|q| q.observe(value));
+        }
+        for (quantile, expected) in quantiles.iter().zip([2_500., 5_000., 9_000.]) {
+            assert!((quantile.estimate().unwrap() - expected).abs() < 100.);
+        }
+
+        let mut small = P2Quantile::new(0.5);
+        [3., 1., 2.].iter().for_each(|&x| small.observe(x));
+        assert_eq!(small.estimate(), Some(2.));
+    }
+}
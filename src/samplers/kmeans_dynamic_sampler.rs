@@ -1,5 +1,8 @@
-use super::Sampler;
-use crate::algorithms::DataStreamClusteringAlgorithm;
+use super::{sampled_stats, Sampler};
+use crate::algorithms::{
+    AlgorithmStats, ClusterEventListener, ClusteringElement, ClusteringError,
+    DataStreamClusteringAlgorithm, Euclidean, Metric, SamplerStats, Schema,
+};
 use itertools::Itertools;
 use rand::prelude::*;
 use rand_pcg::Pcg64;
@@ -10,14 +13,19 @@ const ALPHA: f64 = 1.0;
 const BETA: f64 = 0.5;
 const LAMBDA: f64 = 0.001;
 
-pub fn kmeans(data: &[Vec<f64>], k: usize, max_iterations: usize) -> Vec<usize> {
+pub fn kmeans<D: Metric>(
+    data: &[Vec<f64>],
+    k: usize,
+    max_iterations: usize,
+    metric: &D,
+) -> Vec<usize> {
     let mut centroids = initialize_centroids(data, k);
     let mut assignments = vec![0; data.len()];
 
     for _ in 0..max_iterations {
         // Assign clusters
         for (i, point) in data.iter().enumerate() {
-            assignments[i] = closest_centroid(point, &centroids);
+            assignments[i] = closest_centroid(point, &centroids, metric);
         }
 
         // Update centroids
@@ -49,31 +57,24 @@ fn initialize_centroids(data: &[Vec<f64>], k: usize) -> Vec<Vec<f64>> {
     data.iter().take(k).cloned().collect()
 }
 
-fn closest_centroid(point: &[f64], centroids: &[Vec<f64>]) -> usize {
+fn closest_centroid<D: Metric>(point: &[f64], centroids: &[Vec<f64>], metric: &D) -> usize {
     centroids
         .iter()
         .enumerate()
-        .map(|(i, centroid)| (i, euclidean_distance(point, centroid)))
-        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(i, centroid)| (i, metric.distance(point, centroid)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
         .map(|(i, _)| i)
         .unwrap()
 }
 
-fn euclidean_distance(a: &[f64], b: &[f64]) -> f64 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| (x - y).powi(2))
-        .sum::<f64>()
-        .sqrt()
-}
-
 struct ClusterCharacteristics {
     centroid: Vec<f64>,
+    /// Root mean square distance of the members from the centroid
     radius: f64,
     _count: usize,
 }
 
-pub struct KMeansDynamicSampler {
+pub struct KMeansDynamicSampler<D = Euclidean> {
     algorithm: Box<dyn DataStreamClusteringAlgorithm>,
     assignments: Vec<ClusterCharacteristics>,
     max_distance: f64,
@@ -81,11 +82,24 @@ pub struct KMeansDynamicSampler {
     initial_buffer: Vec<Vec<f64>>,
     initialised: bool,
     k: usize,
+    counters: SamplerStats,
+    schema: Schema,
+    metric: D,
 }
 
 impl KMeansDynamicSampler {
-    pub fn new(algorithm: Box<dyn DataStreamClusteringAlgorithm>, k: usize) -> Self {
-        Self {
+    pub fn new(
+        algorithm: Box<dyn DataStreamClusteringAlgorithm>,
+        k: usize,
+    ) -> Result<Self, ClusteringError> {
+        if k == 0 {
+            return Err(ClusteringError::invalid_parameter(
+                "k",
+                "must be at least 1",
+            ));
+        }
+        let schema = algorithm.schema();
+        Ok(Self {
             algorithm,
             assignments: Vec::new(),
             max_distance: f64::MAX,
@@ -93,22 +107,48 @@ impl KMeansDynamicSampler {
             initial_buffer: Vec::new(),
             initialised: false,
             k,
+            counters: SamplerStats::default(),
+            schema,
+            metric: Euclidean,
+        })
+    }
+}
+
+impl<D: Metric> KMeansDynamicSampler<D> {
+    /// Measures closeness to the initial clusters with `metric` instead of the Euclidean
+    /// distance
+    pub fn with_metric<E: Metric>(self, metric: E) -> KMeansDynamicSampler<E> {
+        KMeansDynamicSampler {
+            algorithm: self.algorithm,
+            assignments: self.assignments,
+            max_distance: self.max_distance,
+            rng: self.rng,
+            initial_buffer: self.initial_buffer,
+            initialised: self.initialised,
+            k: self.k,
+            counters: self.counters,
+            schema: self.schema,
+            metric,
         }
     }
 }
 
-impl Sampler for KMeansDynamicSampler {
-    fn insert(&mut self, data: Vec<f64>) {
+impl<D: Metric> Sampler for KMeansDynamicSampler<D> {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError> {
+        self.schema.validate(&data)?;
         if !self.initialised {
-            self.algorithm.insert(data.clone());
+            self.algorithm.insert(data.clone())?;
+            self.counters.accepted += 1;
             self.initial_buffer.push(data);
             if self.initial_buffer.len() >= DELTA {
-                let kmeans_result = kmeans(&self.initial_buffer, self.k, MAX_ITERATIONS);
+                let kmeans_result =
+                    kmeans(&self.initial_buffer, self.k, MAX_ITERATIONS, &self.metric);
                 let mut clusters = vec![Vec::new(); self.k];
                 for (assignment, point) in kmeans_result.iter().zip(self.initial_buffer.iter()) {
                     clusters[*assignment].push(point.clone());
                 }
-                for cluster in clusters {
+                // k-means may leave some clusters empty
+                for cluster in clusters.into_iter().filter(|cluster| !cluster.is_empty()) {
                     let centroid: Vec<f64> = cluster
                         .iter()
                         .fold(vec![0.0; cluster[0].len()], |mut acc, e| {
@@ -120,14 +160,10 @@ impl Sampler for KMeansDynamicSampler {
                         .iter()
                         .map(|e| e / cluster.len() as f64)
                         .collect();
+                    // In `metric`, as the distances it gets subtracted from below
                     let radius = (cluster
                         .iter()
-                        .map(|e| {
-                            e.iter()
-                                .zip(centroid.iter())
-                                .map(|(a, b)| (a - b).powi(2))
-                                .sum::<f64>()
-                        })
+                        .map(|e| self.metric.distance(e, &centroid).powi(2))
                         .sum::<f64>()
                         / cluster.len() as f64)
                         .sqrt();
@@ -137,12 +173,19 @@ impl Sampler for KMeansDynamicSampler {
                         _count: cluster.len(),
                     });
                 }
-                self.max_distance = 
-                self.initial_buffer.iter().combinations(2).map(|pair| {
-                    euclidean_distance(pair[0], pair[1])
-                }).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
+                self.max_distance = self
+                    .initial_buffer
+                    .iter()
+                    .combinations(2)
+                    .map(|pair| self.metric.distance(pair[0], pair[1]))
+                    .max_by(|a, b| a.total_cmp(b))
+                    .unwrap_or(0.);
                 self.initialised = true;
                 self.initial_buffer.clear();
+                tracing::debug!(
+                    max_distance = self.max_distance,
+                    "initialized sampler clusters"
+                );
             }
         } else {
             // sample with probability tied to closenes to centroids
@@ -150,10 +193,10 @@ impl Sampler for KMeansDynamicSampler {
                 .assignments
                 .iter()
                 .map(|cluster| {
-                    let distance = euclidean_distance(&data, &cluster.centroid) - cluster.radius;
+                    let distance = self.metric.distance(&data, &cluster.centroid) - cluster.radius;
                     (cluster, distance)
                 })
-                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .min_by(|a, b| a.1.total_cmp(&b.1))
                 .unwrap();
             let probability: f64;
             if min_distance <= 0.0 {
@@ -164,15 +207,29 @@ impl Sampler for KMeansDynamicSampler {
             } else {
                 probability = ALPHA * (min_distance / self.max_distance);
             }
+            tracing::trace!(min_distance, probability, "sampling point");
             if self.rng.gen_bool(probability) {
-                self.algorithm.insert(data);
+                self.algorithm.insert(data)?;
+                self.counters.accepted += 1;
+            } else {
+                self.counters.dropped += 1;
             }
         }
+        Ok(())
     }
     fn name(&self) -> String {
         format!("(KMeansDynamicSampler, {})", self.algorithm.name())
     }
-    fn clusters(&self) -> Vec<crate::algorithms::ClusteringElement> {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError> {
         self.algorithm.clusters()
     }
+    fn stats(&self) -> AlgorithmStats {
+        sampled_stats(self.algorithm.as_ref(), self.counters.clone())
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.algorithm.subscribe(listener);
+    }
 }
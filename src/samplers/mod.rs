@@ -1,10 +1,53 @@
 pub mod kmeans_dynamic_sampler;
 pub mod static_sampler;
+pub mod uniform_sampler;
 
-use crate::algorithms::ClusteringElement;
+use crate::algorithms::{
+    AlgorithmStats, ClusterEventListener, ClusteringElement, ClusteringError,
+    DataStreamClusteringAlgorithm, SamplerStats, Schema,
+};
 
-pub trait Sampler {
-    fn insert(&mut self, data: Vec<f64>);
+pub trait Sampler: Send {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError>;
     fn name(&self) -> String;
-    fn clusters(&self) -> Vec<ClusteringElement>;
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError>;
+    /// Starts as the wrapped algorithm's schema
+    fn schema(&self) -> Schema;
+    fn stats(&self) -> AlgorithmStats;
+    /// Forwards the listener to the wrapped algorithm
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>);
+}
+
+/// Stats of the wrapped algorithm with the sampler counters attached
+fn sampled_stats(
+    algorithm: &dyn DataStreamClusteringAlgorithm,
+    sampler: SamplerStats,
+) -> AlgorithmStats {
+    AlgorithmStats {
+        sampler: Some(sampler),
+        ..algorithm.stats()
+    }
+}
+
+/// Lets a sampled algorithm be used wherever a plain algorithm is expected
+impl DataStreamClusteringAlgorithm for Box<dyn Sampler> {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError> {
+        let _span = tracing::trace_span!("sampler").entered();
+        self.as_mut().insert(data)
+    }
+    fn name(&self) -> String {
+        self.as_ref().name()
+    }
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError> {
+        self.as_ref().clusters()
+    }
+    fn schema(&self) -> Schema {
+        self.as_ref().schema()
+    }
+    fn stats(&self) -> AlgorithmStats {
+        self.as_ref().stats()
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.as_mut().subscribe(listener);
+    }
 }
@@ -1,5 +1,8 @@
-use super::Sampler;
-use crate::algorithms::DataStreamClusteringAlgorithm;
+use super::{sampled_stats, Sampler};
+use crate::algorithms::{
+    AlgorithmStats, ClusterEventListener, ClusteringElement, ClusteringError,
+    DataStreamClusteringAlgorithm, SamplerStats, Schema,
+};
 use rand::prelude::*;
 use rand_pcg::Pcg64;
 
@@ -7,28 +10,56 @@ pub struct StaticSampler {
     algorithm: Box<dyn DataStreamClusteringAlgorithm>,
     odds: f64,
     rng: Pcg64,
+    counters: SamplerStats,
+    schema: Schema,
 }
 
 impl StaticSampler {
-    pub fn new(algorithm: Box<dyn DataStreamClusteringAlgorithm>, odds: f64) -> Self {
-        Self {
+    pub fn new(
+        algorithm: Box<dyn DataStreamClusteringAlgorithm>,
+        odds: f64,
+    ) -> Result<Self, ClusteringError> {
+        if !(0. ..=1.).contains(&odds) {
+            return Err(ClusteringError::invalid_parameter(
+                "odds",
+                "must be between 0 and 1",
+            ));
+        }
+        let schema = algorithm.schema();
+        Ok(Self {
             algorithm,
             odds,
             rng: Pcg64::from_entropy(),
-        }
+            counters: SamplerStats::default(),
+            schema,
+        })
     }
 }
 
 impl Sampler for StaticSampler {
-    fn insert(&mut self, data: Vec<f64>) {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError> {
+        self.schema.validate(&data)?;
         if self.rng.gen_bool(self.odds) {
-            self.algorithm.insert(data);
+            self.algorithm.insert(data)?;
+            self.counters.accepted += 1;
+        } else {
+            self.counters.dropped += 1;
         }
+        Ok(())
     }
     fn name(&self) -> String {
         format!("(StaticSampler({}), {})", self.odds, self.algorithm.name())
     }
-    fn clusters(&self) -> Vec<crate::algorithms::ClusteringElement> {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError> {
         self.algorithm.clusters()
     }
+    fn stats(&self) -> AlgorithmStats {
+        sampled_stats(self.algorithm.as_ref(), self.counters.clone())
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.algorithm.subscribe(listener);
+    }
 }
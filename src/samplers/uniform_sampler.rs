@@ -1,36 +1,59 @@
-use super::Sampler;
-use crate::algorithms::DataStreamClusteringAlgorithm;
+use super::{sampled_stats, Sampler};
+use crate::algorithms::{
+    AlgorithmStats, ClusterEventListener, ClusteringElement, ClusteringError,
+    DataStreamClusteringAlgorithm, SamplerStats, Schema,
+};
 use rand::prelude::*;
 use rand_pcg::Pcg64;
 
+/// Passes the n-th point on with odds 1/n, so the sample thins out as the stream grows
 pub struct UniformSampler {
     algorithm: Box<dyn DataStreamClusteringAlgorithm>,
     counter: usize,
     rng: Pcg64,
+    counters: SamplerStats,
+    schema: Schema,
 }
 
 impl UniformSampler {
     pub fn new(algorithm: Box<dyn DataStreamClusteringAlgorithm>) -> Self {
+        let schema = algorithm.schema();
         Self {
             algorithm,
             counter: 1,
             rng: Pcg64::from_entropy(),
+            counters: SamplerStats::default(),
+            schema,
         }
     }
 }
 
 impl Sampler for UniformSampler {
-    fn insert(&mut self, data: Vec<f64>) {
+    fn insert(&mut self, data: Vec<f64>) -> Result<(), ClusteringError> {
+        self.schema.validate(&data)?;
         if self.rng.gen_bool(1.0 / self.counter as f64) {
-            println!("Inserting at: {:?}", self.counter);
-            self.algorithm.insert(data);
+            tracing::trace!(counter = self.counter, "inserting sampled point");
+            self.algorithm.insert(data)?;
+            self.counters.accepted += 1;
+        } else {
+            self.counters.dropped += 1;
         }
         self.counter += 1;
+        Ok(())
     }
     fn name(&self) -> String {
         format!("(UniformSampler, {})", self.algorithm.name())
     }
-    fn clusters(&self) -> Vec<crate::algorithms::ClusteringElement> {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+    fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError> {
         self.algorithm.clusters()
     }
+    fn stats(&self) -> AlgorithmStats {
+        sampled_stats(self.algorithm.as_ref(), self.counters.clone())
+    }
+    fn subscribe(&mut self, listener: Box<dyn ClusterEventListener>) {
+        self.algorithm.subscribe(listener);
+    }
 }
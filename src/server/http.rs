@@ -0,0 +1,312 @@
+//! JSON API over HTTP for a model shared with other readers:
+//!
+//! - `POST /points` with `{"points": [[x, y], ...]}` inserts a batch, stopping at the first
+//!   point the model rejects
+//! - `GET /clusters` returns the current clustering elements with their macro-cluster ids
+//! - `POST /predict` with `{"point": [x, y]}` or `{"points": [...]}` returns cluster ids
+//! - `GET /stats` returns the model statistics
+//! - `GET /schema` returns the accepted `dimension` and `feature_names`, `null` until known
+//! - `POST /export` writes the schema, statistics and clusters to the export directory as a
+//!   JSON report. It is not a snapshot of the model state, nothing can be restored from it.
+//! - `POST /snapshot`, persisting restorable model state, is not built yet and answers
+//!   `501`: it needs serde on the models and a load path, which is deferred to a follow-up
+//! - `GET /metrics` returns the model internals in Prometheus text format
+
+use super::{ModelStats, SharedModel};
+use crate::algorithms::{ClusteringElement, ClusteringError, Schema};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(Deserialize)]
+struct PointsBody {
+    points: Vec<Vec<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PredictBody {
+    One { point: Vec<f64> },
+    Many { points: Vec<Vec<f64>> },
+}
+
+#[derive(Serialize)]
+struct Export {
+    schema: Schema,
+    stats: ModelStats,
+    clusters: Vec<ClusteringElement>,
+}
+
+enum Reply {
+    Json(serde_json::Value),
+    Metrics(String),
+}
+
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn bad_request(message: String) -> Self {
+        HttpError {
+            status: 400,
+            message,
+        }
+    }
+}
+
+impl From<ClusteringError> for HttpError {
+    fn from(err: ClusteringError) -> Self {
+        HttpError {
+            status: 500,
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpOptions {
+    pub export_dir: PathBuf,
+    pub workers: usize,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            export_dir: PathBuf::from("./exports"),
+            workers: 4,
+        }
+    }
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, HttpError> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|err| HttpError::bad_request(err.to_string()))?;
+    serde_json::from_str(&body).map_err(|err| HttpError::bad_request(err.to_string()))
+}
+
+fn route(
+    request: &mut Request,
+    model: &SharedModel,
+    options: &HttpOptions,
+) -> Result<Reply, HttpError> {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let body = match (request.method(), path.as_str()) {
+        (Method::Post, "/points") => {
+            let body: PointsBody = parse_body(request)?;
+            let total = body.points.len();
+            let mut model = model.lock().unwrap();
+            for (inserted, point) in body.points.into_iter().enumerate() {
+                model.insert(point).map_err(|err| {
+                    HttpError::bad_request(format!(
+                        "point {} rejected: {} ({} points inserted)",
+                        inserted, err, inserted
+                    ))
+                })?;
+            }
+            json!({ "inserted": total })
+        }
+        (Method::Get, "/clusters") => json!(model.lock().unwrap().clusters()?),
+        (Method::Post, "/predict") => {
+            let body: PredictBody = parse_body(request)?;
+            let (schema, clusters) = {
+                let model = model.lock().unwrap();
+                (model.schema(), model.clusters()?)
+            };
+            let points = match &body {
+                PredictBody::One { point } => std::slice::from_ref(point),
+                PredictBody::Many { points } => points.as_slice(),
+            };
+            for (i, point) in points.iter().enumerate() {
+                schema
+                    .check(point)
+                    .map_err(|err| HttpError::bad_request(format!("point {}: {}", i, err)))?;
+            }
+            match body {
+                PredictBody::One { point } => {
+                    json!({ "cluster": crate::algorithms::predict(&clusters, &point) })
+                }
+                PredictBody::Many { points } => json!({
+                    "clusters": points
+                        .iter()
+                        .map(|point| crate::algorithms::predict(&clusters, point))
+                        .collect::<Vec<Option<usize>>>()
+                }),
+            }
+        }
+        (Method::Get, "/stats") => json!(model.lock().unwrap().stats()?),
+        (Method::Get, "/schema") => json!(model.lock().unwrap().schema()),
+        (Method::Get, "/metrics") => return Ok(Reply::Metrics(model.lock().unwrap().metrics())),
+        (Method::Post, "/snapshot") => {
+            return Err(HttpError {
+                status: 501,
+                message: "model snapshots are not supported yet, POST /export writes a report"
+                    .to_string(),
+            })
+        }
+        (Method::Post, "/export") => {
+            let export = {
+                let model = model.lock().unwrap();
+                Export {
+                    schema: model.schema(),
+                    stats: model.stats()?,
+                    clusters: model.clusters()?,
+                }
+            };
+            let path = options.export_dir.join(format!(
+                "{}_{}.json",
+                export.stats.algorithm, export.stats.inserted
+            ));
+            let internal = |err: std::io::Error| HttpError {
+                status: 500,
+                message: err.to_string(),
+            };
+            fs::create_dir_all(&options.export_dir).map_err(internal)?;
+            let file = File::create(&path).map_err(internal)?;
+            serde_json::to_writer(BufWriter::new(file), &export)
+                .map_err(|err| internal(err.into()))?;
+            json!({ "path": path })
+        }
+        _ => {
+            return Err(HttpError {
+                status: 404,
+                message: format!("no route for {} {}", request.method(), path),
+            })
+        }
+    };
+    Ok(Reply::Json(body))
+}
+
+fn respond(mut request: Request, model: &SharedModel, options: &HttpOptions) {
+    let (status, body, content_type) = match route(&mut request, model, options) {
+        Ok(Reply::Json(body)) => (200, body.to_string(), "application/json"),
+        Ok(Reply::Metrics(text)) => (200, text, "text/plain; version=0.0.4"),
+        Err(err) => (
+            err.status,
+            json!({ "error": err.message }).to_string(),
+            "application/json",
+        ),
+    };
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).unwrap());
+    // The client may have hung up already
+    let _ = request.respond(response);
+}
+
+/// Serves requests on `options.workers` threads until the server is unblocked
+pub fn serve_http(server: Arc<Server>, model: SharedModel, options: HttpOptions) {
+    let workers: Vec<_> = (0..options.workers.max(1))
+        .map(|_| {
+            let server = server.clone();
+            let model = model.clone();
+            let options = options.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    respond(request, &model, &options);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ModelState;
+    use super::*;
+    use crate::algorithms::clustream::CluStream;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn request(address: &str, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let address = server.server_addr().to_ip().unwrap().to_string();
+        let model = ModelState::shared(Box::new(CluStream::new(2).unwrap()));
+        let export_dir = std::env::temp_dir().join("clustermancer_http_test");
+        let options = HttpOptions {
+            export_dir: export_dir.clone(),
+            workers: 1,
+        };
+        thread::spawn(move || serve_http(server, model, options));
+
+        let points: Vec<Vec<f64>> = (0..20)
+            .map(|i| vec![(i % 2) as f64 * 100. + (i as f64) * 0.01, 0.])
+            .collect();
+        let body = json!({ "points": points }).to_string();
+        let (status, body) = request(&address, "POST", "/points", &body);
+        assert_eq!((status, body["inserted"].as_u64()), (200, Some(20)));
+
+        let (_, clusters) = request(&address, "GET", "/clusters", "");
+        assert!(!clusters.as_array().unwrap().is_empty());
+
+        let (_, one) = request(&address, "POST", "/predict", r#"{"point": [100, 0]}"#);
+        assert!(one["cluster"].is_u64());
+        let (_, many) = request(
+            &address,
+            "POST",
+            "/predict",
+            r#"{"points": [[100, 0], [0, 0]]}"#,
+        );
+        assert_ne!(many["clusters"][0], many["clusters"][1]);
+
+        let (_, stats) = request(&address, "GET", "/stats", "");
+        assert_eq!(stats["inserted"], 20);
+        let (_, schema) = request(&address, "GET", "/schema", "");
+        assert_eq!(schema["dimension"], 2);
+        let (status, _) = request(&address, "POST", "/predict", r#"{"point": [1]}"#);
+        assert_eq!(status, 400);
+
+        let (status, export) = request(&address, "POST", "/export", "");
+        assert_eq!(status, 200);
+        assert!(PathBuf::from(export["path"].as_str().unwrap()).exists());
+        fs::remove_dir_all(export_dir).unwrap();
+
+        let (status, _) = request(&address, "POST", "/points", "not json");
+        assert_eq!(status, 400);
+        let (status, body) = request(&address, "POST", "/points", r#"{"points": [[1, 2, 3]]}"#);
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains("dimension"));
+        let mut stream = TcpStream::connect(&address).unwrap();
+        write!(stream, "GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut metrics = String::new();
+        stream.read_to_string(&mut metrics).unwrap();
+        assert!(metrics.contains("clustermancer_points_inserted_total{algorithm=\"CluStream\"} 20"));
+
+        let (status, body) = request(&address, "POST", "/snapshot", "");
+        assert_eq!(status, 501);
+        assert!(body["error"].as_str().unwrap().contains("/export"));
+        let (status, _) = request(&address, "GET", "/nothing", "");
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,196 @@
+//! Newline-delimited text protocol. Every line is either a record (`x,y,z`) that gets
+//! inserted without a reply, or one of the queries below:
+//!
+//! - `CLUSTERS` -> one `cluster,radius,x,y,...` line per micro-cluster followed by `END`
+//! - `PREDICT x,y,z` -> the macro-cluster id or `NONE`
+//! - `STATS` -> `algorithm=.. inserted=.. rejected=.. clusters=.. micro_clusters=.. ...` with
+//!   the algorithm specific statistics appended
+//! - `SCHEMA` -> `dimension=N features=a,b,..`, both `-` while not known yet
+//!
+//! Malformed lines and failed queries are answered with `ERR <reason>`. Records the model
+//! rejects (e.g. wrong dimension) are only counted in `rejected`.
+
+use super::{Answer, Command, Query};
+use crate::formats::parse_point;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+const MAX_DATAGRAM: usize = 65_507;
+
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    Point(Vec<f64>),
+    Query(Query),
+}
+
+pub fn parse_request(line: &str) -> Result<Request, String> {
+    let (command, rest) = match line.split_once(char::is_whitespace) {
+        Some((command, rest)) => (command, rest.trim()),
+        None => (line, ""),
+    };
+    match command.to_uppercase().as_str() {
+        "CLUSTERS" => Ok(Request::Query(Query::Clusters)),
+        "STATS" => Ok(Request::Query(Query::Stats)),
+        "SCHEMA" => Ok(Request::Query(Query::Schema)),
+        "PREDICT" => parse_point(rest).map(|point| Request::Query(Query::Predict(point))),
+        _ => parse_point(line).map(Request::Point),
+    }
+}
+
+pub fn format_answer(answer: &Answer) -> String {
+    match answer {
+        Answer::Clusters(clusters) => {
+            let mut response = String::new();
+            for cluster in clusters {
+                response.push_str(&format!("{},{}", cluster.cluster, cluster.radius));
+                for x in cluster.center.iter() {
+                    response.push_str(&format!(",{}", x));
+                }
+                response.push('\n');
+            }
+            response.push_str("END\n");
+            response
+        }
+        Answer::Prediction(Some(cluster)) => format!("{}\n", cluster),
+        Answer::Prediction(None) => "NONE\n".to_string(),
+        Answer::Stats(stats) => format!(
+            "algorithm={} inserted={} rejected={} clusters={} {}\n",
+            stats.algorithm, stats.inserted, stats.rejected, stats.clusters, stats.internals
+        ),
+        Answer::Schema(schema) => format!(
+            "dimension={} features={}\n",
+            schema
+                .dimension()
+                .map_or("-".to_string(), |dimension| dimension.to_string()),
+            schema
+                .feature_names()
+                .map_or("-".to_string(), |names| names.join(","))
+        ),
+        Answer::Error(err) => format!("ERR {}\n", err),
+    }
+}
+
+/// Applies one line, returns the reply if the line expects one.
+/// `None` from the outer option means the ingest worker is gone.
+fn handle_line(line: &str, ingest: &SyncSender<Command>) -> Option<Option<String>> {
+    match parse_request(line) {
+        Ok(Request::Point(point)) => {
+            ingest.send(Command::Insert(point)).ok()?;
+            Some(None)
+        }
+        Ok(Request::Query(query)) => {
+            let (reply, answer) = mpsc::channel();
+            ingest.send(Command::Query(query, reply)).ok()?;
+            Some(Some(format_answer(&answer.recv().ok()?)))
+        }
+        Err(err) => Some(Some(format!("ERR {}\n", err))),
+    }
+}
+
+fn serve_connection(stream: TcpStream, ingest: SyncSender<Command>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match handle_line(line, &ingest) {
+            Some(Some(reply)) => writer.write_all(reply.as_bytes())?,
+            Some(None) => {}
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Accepts connections forever, one thread per connection. A slow model blocks the
+/// connection threads on the ingest queue, so TCP flow control pushes back on producers.
+pub fn serve_tcp(listener: TcpListener, ingest: SyncSender<Command>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let ingest = ingest.clone();
+        thread::spawn(move || {
+            let _ = serve_connection(stream, ingest);
+        });
+    }
+    Ok(())
+}
+
+/// Serves datagrams with one or more newline-delimited lines each, replies go back to the
+/// sender. UDP has no flow control: while the ingest queue is full this loop blocks and
+/// stops reading the socket, so datagrams pile up in the socket's receive buffer and the
+/// kernel drops new ones once it overflows.
+pub fn serve_udp(socket: UdpSocket, ingest: SyncSender<Command>) -> io::Result<()> {
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+    loop {
+        let (size, peer) = socket.recv_from(&mut buffer)?;
+        let datagram = String::from_utf8_lossy(&buffer[..size]);
+        let mut reply = String::new();
+        for line in datagram.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match handle_line(line, &ingest) {
+                Some(Some(response)) => reply.push_str(&response),
+                Some(None) => {}
+                None => return Ok(()),
+            }
+        }
+        if !reply.is_empty() {
+            socket.send_to(reply.as_bytes(), peer)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Ingest, ModelState};
+    use super::*;
+    use crate::algorithms::birch::Birch;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(parse_request("1, 2").unwrap(), Request::Point(vec![1., 2.]));
+        assert_eq!(
+            parse_request("predict 1,2").unwrap(),
+            Request::Query(Query::Predict(vec![1., 2.]))
+        );
+        assert!(parse_request("PREDICT").is_err());
+    }
+
+    #[test]
+    fn test_tcp_round_trip() {
+        let model = ModelState::shared(Box::new(Birch::new(1., 50, 2).unwrap()));
+        let ingest = Ingest::spawn(model, 16);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let sender = ingest.sender();
+        thread::spawn(move || serve_tcp(listener, sender));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"SCHEMA\n0,0\n0,0.5\n10,10\n1,2,3\nSCHEMA\nPREDICT 1\nSTATS\n")
+            .unwrap();
+        stream.write_all(b"PREDICT 10,9.5\nCLUSTERS\n").unwrap();
+        let mut lines = BufReader::new(stream).lines().map(|l| l.unwrap());
+        assert_eq!(lines.next().unwrap(), "dimension=- features=-");
+        assert_eq!(lines.next().unwrap(), "dimension=2 features=-");
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("ERR expected a point of dimension 2"));
+        assert!(lines.next().unwrap().contains("inserted=3 rejected=1"));
+        let predicted = lines.next().unwrap();
+        let mut clusters = Vec::new();
+        for line in lines.by_ref() {
+            if line == "END" {
+                break;
+            }
+            clusters.push(line);
+        }
+        assert!(!clusters.is_empty());
+        assert!(clusters
+            .iter()
+            .any(|c| c.starts_with(&format!("{},", predicted))));
+    }
+}
@@ -0,0 +1,171 @@
+pub mod http;
+pub mod line;
+
+use crate::algorithms::{
+    predict, AlgorithmStats, ClusteringElement, ClusteringError, DataStreamClusteringAlgorithm,
+    Schema,
+};
+use crate::metrics::{self, Histogram};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// Algorithm (or sampler) shared between the ingest worker and the query handlers
+pub struct ModelState {
+    algorithm: Box<dyn DataStreamClusteringAlgorithm>,
+    inserted: usize,
+    rejected: usize,
+    insert_latency: Histogram,
+}
+
+pub type SharedModel = Arc<Mutex<ModelState>>;
+
+impl ModelState {
+    pub fn shared(algorithm: Box<dyn DataStreamClusteringAlgorithm>) -> SharedModel {
+        Arc::new(Mutex::new(ModelState {
+            algorithm,
+            inserted: 0,
+            rejected: 0,
+            insert_latency: Histogram::latency(),
+        }))
+    }
+
+    pub fn insert(&mut self, point: Vec<f64>) -> Result<(), ClusteringError> {
+        let start = Instant::now();
+        if let Err(err) = self.algorithm.insert(point) {
+            self.rejected += 1;
+            return Err(err);
+        }
+        self.insert_latency.observe(start.elapsed().as_secs_f64());
+        self.inserted += 1;
+        Ok(())
+    }
+
+    pub fn clusters(&self) -> Result<Vec<ClusteringElement>, ClusteringError> {
+        self.algorithm.clusters()
+    }
+
+    /// Points of the wrong dimension are rejected instead of being compared on a prefix
+    pub fn predict(&self, point: &[f64]) -> Result<Option<usize>, ClusteringError> {
+        self.schema().check(point)?;
+        Ok(predict(&self.clusters()?, point))
+    }
+
+    pub fn schema(&self) -> Schema {
+        self.algorithm.schema()
+    }
+
+    pub fn stats(&self) -> Result<ModelStats, ClusteringError> {
+        let clusters = self.clusters()?;
+        Ok(ModelStats {
+            algorithm: self.algorithm.name(),
+            inserted: self.inserted,
+            rejected: self.rejected,
+            clusters: clusters
+                .iter()
+                .map(|c| c.cluster)
+                .collect::<HashSet<usize>>()
+                .len(),
+            internals: self.algorithm.stats(),
+        })
+    }
+
+    /// Prometheus exposition of the model internals
+    pub fn metrics(&self) -> String {
+        metrics::render(
+            &self.algorithm.name(),
+            self.inserted,
+            self.rejected,
+            &self.algorithm.stats(),
+            &self.insert_latency,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelStats {
+    pub algorithm: String,
+    pub inserted: usize,
+    /// Points the model refused, e.g. for a wrong dimension
+    pub rejected: usize,
+    /// Distinct macro-clusters in the current clustering
+    pub clusters: usize,
+    pub internals: AlgorithmStats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Clusters,
+    Predict(Vec<f64>),
+    Stats,
+    Schema,
+}
+
+#[derive(Debug, Clone)]
+pub enum Answer {
+    Clusters(Vec<ClusteringElement>),
+    Prediction(Option<usize>),
+    Stats(ModelStats),
+    Schema(Schema),
+    Error(ClusteringError),
+}
+
+pub enum Command {
+    Insert(Vec<f64>),
+    /// Queries travel through the same queue as points, so they observe every point
+    /// sent before them on the same connection
+    Query(Query, Sender<Answer>),
+}
+
+/// Single worker that applies commands in arrival order. The bounded queue is what
+/// provides backpressure: producers block once `capacity` commands are waiting.
+pub struct Ingest {
+    sender: SyncSender<Command>,
+    worker: JoinHandle<()>,
+}
+
+impl Ingest {
+    pub fn spawn(model: SharedModel, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let worker = thread::spawn(move || Self::work(model, receiver));
+        Ingest { sender, worker }
+    }
+
+    fn work(model: SharedModel, receiver: Receiver<Command>) {
+        for command in receiver {
+            let mut model = model.lock().unwrap();
+            match command {
+                Command::Insert(point) => {
+                    // Nobody waits for a reply, the rejection shows up in the stats
+                    if let Err(err) = model.insert(point) {
+                        tracing::warn!(%err, "rejected point");
+                    }
+                }
+                Command::Query(query, reply) => {
+                    let answer = match query {
+                        Query::Clusters => model.clusters().map(Answer::Clusters),
+                        Query::Predict(point) => model.predict(&point).map(Answer::Prediction),
+                        Query::Stats => model.stats().map(Answer::Stats),
+                        Query::Schema => Ok(Answer::Schema(model.schema())),
+                    }
+                    .unwrap_or_else(Answer::Error);
+                    // The asking connection may be gone already
+                    let _ = reply.send(answer);
+                }
+            }
+        }
+    }
+
+    pub fn sender(&self) -> SyncSender<Command> {
+        self.sender.clone()
+    }
+
+    /// Waits until every queued command has been applied
+    pub fn shutdown(self) {
+        drop(self.sender);
+        self.worker.join().unwrap();
+    }
+}